@@ -0,0 +1,531 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::cpu::{decode_opcode, PROGRAM_ADDRESS};
+use super::disassembler::disassemble;
+use super::registers::CrispsAteDecodedOpcodes;
+use super::symbols::CrispAteSymbols;
+use crate::utils::hex;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EdgeKind {
+    Next, // falls through, or where a call returns to
+    Jump,
+    Skip, // a skip that skipped
+    Call,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BasicBlock {
+    pub start: u16,
+    pub end: u16, // right after its last instruction
+    pub edges: Vec<(u16, EdgeKind)>,
+}
+
+// an FX55 or FX33 whose I is known and lands on code
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CodeWrite {
+    pub address: u16,
+    pub target: u16,
+    pub length: u16,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AnalysisWarning {
+    pub address: u16,
+    pub message: String,
+}
+
+impl fmt::Display for AnalysisWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", hex(self.address), self.message)
+    }
+}
+
+// What can be told about a ROM without running it. Code is found by
+// following every jump, call and skip from 0x200, so whatever is only
+// reached through BNNN (or written at runtime) ends up as data. I is only
+// followed within a block, from its ANNN to the FX55/FX33 that writes there.
+pub struct CrispAteAnalysis {
+    pub blocks: BTreeMap<u16, BasicBlock>,
+    pub subroutines: BTreeSet<u16>, // 2NNN targets
+    pub indirect_jumps: Vec<u16>,   // BNNN
+    pub code_writes: Vec<CodeWrite>,
+    pub data: Vec<(u16, u16)>, // start and end of what isn't code
+    pub warnings: Vec<AnalysisWarning>,
+    program: Vec<u8>,
+    code: BTreeSet<u16>,      // where instructions start
+    data_refs: BTreeSet<u16>, // ANNN targets
+}
+
+// does the instruction end its block
+fn ends_block(decoded: &CrispsAteDecodedOpcodes) -> bool {
+    matches!(
+        decoded,
+        CrispsAteDecodedOpcodes::Jump(_)
+            | CrispsAteDecodedOpcodes::CallSubRoutine(_)
+            | CrispsAteDecodedOpcodes::Return
+            | CrispsAteDecodedOpcodes::JumpToAddress(_)
+            | CrispsAteDecodedOpcodes::Call(_)
+            | CrispsAteDecodedOpcodes::None(_)
+            | CrispsAteDecodedOpcodes::SkipIfVXEquals(_, _)
+            | CrispsAteDecodedOpcodes::SkipIfVXNotEqual(_, _)
+            | CrispsAteDecodedOpcodes::SkipIfVXEqualsVY(_, _)
+            | CrispsAteDecodedOpcodes::SkipIfVXNotEqualVY(_, _)
+            | CrispsAteDecodedOpcodes::SkipIfKeyAtVXIsPressed(_)
+            | CrispsAteDecodedOpcodes::SkipIfKeyAtVXIsNotPressed(_)
+    )
+}
+
+// where the instruction can go next
+fn successors(address: u16, decoded: &CrispsAteDecodedOpcodes) -> Vec<(u16, EdgeKind)> {
+    let next = address.wrapping_add(2);
+
+    match decoded {
+        CrispsAteDecodedOpcodes::Jump(target) => vec![(*target, EdgeKind::Jump)],
+        CrispsAteDecodedOpcodes::CallSubRoutine(target) => {
+            vec![(*target, EdgeKind::Call), (next, EdgeKind::Next)]
+        }
+        CrispsAteDecodedOpcodes::SkipIfVXEquals(_, _)
+        | CrispsAteDecodedOpcodes::SkipIfVXNotEqual(_, _)
+        | CrispsAteDecodedOpcodes::SkipIfVXEqualsVY(_, _)
+        | CrispsAteDecodedOpcodes::SkipIfVXNotEqualVY(_, _)
+        | CrispsAteDecodedOpcodes::SkipIfKeyAtVXIsPressed(_)
+        | CrispsAteDecodedOpcodes::SkipIfKeyAtVXIsNotPressed(_) => {
+            vec![
+                (next, EdgeKind::Next),
+                (address.wrapping_add(4), EdgeKind::Skip),
+            ]
+        }
+        CrispsAteDecodedOpcodes::Return
+        | CrispsAteDecodedOpcodes::JumpToAddress(_)
+        | CrispsAteDecodedOpcodes::Call(_)
+        | CrispsAteDecodedOpcodes::None(_) => vec![],
+        _ => vec![(next, EdgeKind::Next)],
+    }
+}
+
+// for DOT's quoted strings
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl CrispAteAnalysis {
+    // `program` as it is loaded at 0x200
+    pub fn analyze(program: &[u8]) -> Self {
+        let mut analysis = CrispAteAnalysis {
+            blocks: BTreeMap::new(),
+            subroutines: BTreeSet::new(),
+            indirect_jumps: Vec::new(),
+            code_writes: Vec::new(),
+            data: Vec::new(),
+            warnings: Vec::new(),
+            program: program.to_vec(),
+            code: BTreeSet::new(),
+            data_refs: BTreeSet::new(),
+        };
+
+        let leaders = analysis.discover();
+        analysis.build_blocks(&leaders);
+        analysis.find_data();
+        analysis.check_targets();
+        analysis.warnings.sort_by_key(|warning| warning.address);
+
+        analysis
+    }
+
+    fn end(&self) -> usize {
+        PROGRAM_ADDRESS + self.program.len()
+    }
+
+    fn contains(&self, address: u16) -> bool {
+        (PROGRAM_ADDRESS..self.end()).contains(&(address as usize))
+    }
+
+    fn opcode(&self, address: u16) -> Option<u16> {
+        let offset = (address as usize).checked_sub(PROGRAM_ADDRESS)?;
+        let bytes = self.program.get(offset..offset + 2)?;
+        Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    fn warn(&mut self, address: u16, message: String) {
+        self.warnings.push(AnalysisWarning { address, message });
+    }
+
+    // every instruction reachable from 0x200, and the addresses that start
+    // a block
+    fn discover(&mut self) -> BTreeSet<u16> {
+        let entry = PROGRAM_ADDRESS as u16;
+        let mut leaders = BTreeSet::from([entry]);
+        let mut pending = vec![entry];
+
+        while let Some(address) = pending.pop() {
+            if self.code.contains(&address) {
+                continue;
+            }
+            let opcode = match self.opcode(address) {
+                Some(opcode) => opcode,
+                None => continue, // reported where it was reached from
+            };
+            self.code.insert(address);
+            let decoded = decode_opcode(opcode);
+
+            match decoded {
+                CrispsAteDecodedOpcodes::CallSubRoutine(target) => {
+                    self.subroutines.insert(target);
+                }
+                CrispsAteDecodedOpcodes::JumpToAddress(_) => self.indirect_jumps.push(address),
+                CrispsAteDecodedOpcodes::SetIAddress(target) => {
+                    self.data_refs.insert(target);
+                }
+                CrispsAteDecodedOpcodes::Call(_) => self.warn(
+                    address,
+                    String::from("machine code call (0NNN), which isn't supported"),
+                ),
+                CrispsAteDecodedOpcodes::None(_) => {
+                    self.warn(address, String::from("invalid instruction"))
+                }
+                _ => {}
+            }
+
+            for (target, kind) in successors(address, &decoded) {
+                if !self.contains(target) {
+                    let message = match kind {
+                        EdgeKind::Next | EdgeKind::Skip => {
+                            String::from("runs past the end of the program")
+                        }
+                        _ => format!("jumps outside the program ({})", hex(target)),
+                    };
+                    self.warn(address, message);
+                    continue;
+                }
+
+                if kind != EdgeKind::Next || ends_block(&decoded) {
+                    leaders.insert(target);
+                }
+                pending.push(target);
+            }
+        }
+
+        leaders
+    }
+
+    fn build_blocks(&mut self, leaders: &BTreeSet<u16>) {
+        let starts: Vec<u16> = leaders
+            .iter()
+            .copied()
+            .filter(|leader| self.code.contains(leader))
+            .collect();
+
+        for leader in starts {
+            let mut address = leader;
+            // what I holds, as long as it is set by ANNN in this block
+            let mut i: Option<u16> = None;
+
+            let edges = loop {
+                let opcode = self.opcode(address).expect("code is inside the program");
+                let decoded = decode_opcode(opcode);
+                let next = address.wrapping_add(2);
+
+                let write = match decoded {
+                    CrispsAteDecodedOpcodes::StoreFromV0ToVXStartingFromI(x) => {
+                        Some(x.nibble() + 1)
+                    }
+                    CrispsAteDecodedOpcodes::StoreBinaryCodedDecimalVX(_) => Some(3),
+                    _ => None,
+                };
+                if let (Some(length), Some(target)) = (write, i) {
+                    self.check_write(address, target, length);
+                }
+                i = match decoded {
+                    CrispsAteDecodedOpcodes::SetIAddress(target) => Some(target),
+                    CrispsAteDecodedOpcodes::AddVXToI(_)
+                    | CrispsAteDecodedOpcodes::SetIToLocationOfVXChar(_) => None,
+                    _ => i,
+                };
+
+                if ends_block(&decoded) || !self.code.contains(&next) || leaders.contains(&next) {
+                    break successors(address, &decoded)
+                        .into_iter()
+                        .filter(|(target, _)| self.code.contains(target))
+                        .collect();
+                }
+                address = next;
+            };
+
+            self.blocks.insert(
+                leader,
+                BasicBlock {
+                    start: leader,
+                    end: address.wrapping_add(2),
+                    edges,
+                },
+            );
+        }
+    }
+
+    fn check_write(&mut self, address: u16, target: u16, length: u16) {
+        // any instruction with a byte in the written range
+        let first = target.saturating_sub(1);
+        let written = self
+            .code
+            .range(first..target.saturating_add(length))
+            .next()
+            .copied();
+
+        if let Some(instruction) = written {
+            self.code_writes.push(CodeWrite {
+                address,
+                target,
+                length,
+            });
+            self.warn(
+                address,
+                format!(
+                    "writes over the instruction at {} (self-modifying code)",
+                    hex(instruction)
+                ),
+            );
+        }
+    }
+
+    // the runs of bytes no instruction covers
+    fn find_data(&mut self) {
+        let mut start: Option<usize> = None;
+
+        for address in PROGRAM_ADDRESS..=self.end() {
+            let covered = address == self.end()
+                || self.code.contains(&(address as u16))
+                || address
+                    .checked_sub(1)
+                    .is_some_and(|before| self.code.contains(&(before as u16)));
+
+            match (covered, start) {
+                (false, None) => start = Some(address),
+                (true, Some(from)) => {
+                    self.data.push((from as u16, address as u16));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        for (start, end) in self.data.clone() {
+            // nothing points I at it, and it reads as instructions all the way
+            let referenced = self.data_refs.range(start..end).next().is_some();
+            let instructions: Vec<u16> = (start..end.saturating_sub(1))
+                .step_by(2)
+                .filter_map(|address| self.opcode(address))
+                .collect();
+            let valid = instructions
+                .iter()
+                .all(|opcode| !matches!(decode_opcode(*opcode), CrispsAteDecodedOpcodes::None(_)));
+
+            if !referenced && valid && instructions.iter().any(|opcode| *opcode != 0) {
+                self.warn(
+                    start,
+                    format!("unreachable code up to {}", hex(end.saturating_sub(1))),
+                );
+            }
+        }
+    }
+
+    // jumps and calls that land on data or inside another instruction
+    fn check_targets(&mut self) {
+        let mut warnings: Vec<AnalysisWarning> = Vec::new();
+
+        for block in self.blocks.values() {
+            let from = block.end.wrapping_sub(2);
+
+            for (target, kind) in &block.edges {
+                if !matches!(kind, EdgeKind::Jump | EdgeKind::Call) {
+                    continue;
+                }
+
+                let message = if self.data_refs.contains(target) {
+                    Some(format!(
+                        "jumps into data at {} (I points there too)",
+                        hex(*target)
+                    ))
+                } else if self.code.contains(&target.wrapping_sub(1)) {
+                    Some(format!(
+                        "jumps into the middle of the instruction at {}",
+                        hex(target.wrapping_sub(1))
+                    ))
+                } else {
+                    None
+                };
+
+                if let Some(message) = message {
+                    warnings.push(AnalysisWarning {
+                        address: from,
+                        message,
+                    });
+                }
+            }
+        }
+
+        self.warnings.extend(warnings);
+    }
+
+    // "5 blocks, 2 subroutines, 1 indirect jump, 0 writes into code, 1 data region"
+    pub fn summary(&self) -> String {
+        format!(
+            "{} blocks, {} subroutines, {} indirect jumps, {} writes into code, {} data regions",
+            self.blocks.len(),
+            self.subroutines.len(),
+            self.indirect_jumps.len(),
+            self.code_writes.len(),
+            self.data.len()
+        )
+    }
+
+    // The control flow graph for Graphviz: a box per block with its
+    // disassembly, dashed edges for calls and a labelled edge where a skip
+    // skips. Blocks ending in BNNN are orange and the ones that write over
+    // code red, data regions are drawn as notes.
+    pub fn dot(&self, name: &str, symbols: &CrispAteSymbols) -> String {
+        let mut dot = format!(
+            "digraph \"{}\" {{\n    node [shape=box, fontname=\"monospace\"];\n",
+            escape(name)
+        );
+
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            if let Some(name) = symbols.label(block.start) {
+                label.push_str(&format!("{}:\\l", escape(name)));
+            }
+
+            for address in (block.start..block.end).step_by(2) {
+                let opcode = self.opcode(address).unwrap_or(0);
+                label.push_str(&format!(
+                    "{}  {}\\l",
+                    hex(address),
+                    escape(&disassemble(opcode, symbols))
+                ));
+            }
+
+            let last = block.end.wrapping_sub(2);
+            let color = if self
+                .code_writes
+                .iter()
+                .any(|write| (block.start..block.end).contains(&write.address))
+            {
+                ", color=red"
+            } else if self.indirect_jumps.contains(&last) {
+                ", color=orange"
+            } else {
+                ""
+            };
+            dot.push_str(&format!(
+                "    \"{}\" [label=\"{}\"{}];\n",
+                hex(block.start),
+                label,
+                color
+            ));
+
+            for (target, kind) in &block.edges {
+                let style = match kind {
+                    EdgeKind::Next | EdgeKind::Jump => "",
+                    EdgeKind::Skip => " [label=\"skip\"]",
+                    EdgeKind::Call => " [style=dashed, label=\"call\"]",
+                };
+                dot.push_str(&format!(
+                    "    \"{}\" -> \"{}\"{};\n",
+                    hex(block.start),
+                    hex(*target),
+                    style
+                ));
+            }
+        }
+
+        for (start, end) in &self.data {
+            dot.push_str(&format!(
+                "    \"{}\" [shape=note, label=\"data {}-{}\"];\n",
+                hex(*start),
+                hex(*start),
+                hex(end.wrapping_sub(1))
+            ));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod analyzer_tests {
+    use super::*;
+
+    // 0x200 :call 0x20a      0x20a i := 0x20e
+    // 0x202 if v0 != 1 then  0x20c save v0 (over the 6001 at 0x20e)
+    // 0x204 jump 0x200       0x20e v0 := 1
+    // 0x206 jump0 0x210      0x210 return
+    // 0x208 0xF0 0x90 (never reached)
+    const PROGRAM: [u8; 18] = [
+        0x22, 0x0A, 0x30, 0x01, 0x12, 0x00, 0xB2, 0x10, 0xF0, 0x90, 0xA2, 0x0E, 0xF0, 0x55, 0x60,
+        0x01, 0x00, 0xEE,
+    ];
+
+    #[test]
+    fn finds_blocks_and_subroutines() {
+        let sut = CrispAteAnalysis::analyze(&PROGRAM);
+
+        assert_eq!(
+            sut.blocks.keys().copied().collect::<Vec<u16>>(),
+            vec![0x200, 0x202, 0x204, 0x206, 0x20A]
+        );
+        assert_eq!(
+            sut.blocks[&0x200].edges,
+            vec![(0x20A, EdgeKind::Call), (0x202, EdgeKind::Next)]
+        );
+        assert_eq!(
+            sut.blocks[&0x202].edges,
+            vec![(0x204, EdgeKind::Next), (0x206, EdgeKind::Skip)]
+        );
+        assert_eq!(sut.blocks[&0x20A].end, 0x212);
+        assert_eq!(sut.subroutines, BTreeSet::from([0x20A]));
+        assert_eq!(sut.indirect_jumps, vec![0x206]);
+        assert_eq!(sut.data, vec![(0x208, 0x20A)]);
+        assert_eq!(
+            sut.code_writes,
+            vec![CodeWrite {
+                address: 0x20C,
+                target: 0x20E,
+                length: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn warns_and_writes_dot() {
+        let mut program = PROGRAM.to_vec();
+        // the data becomes 7001, reads as code and nothing points at it
+        program[8..10].copy_from_slice(&[0x70, 0x01]);
+        // and the loop jumps into the middle of the call
+        program[5] = 0x01;
+        let sut = CrispAteAnalysis::analyze(&program);
+
+        let warnings: Vec<String> = sut.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                // what the misaligned jump decodes as
+                "0x203: machine code call (0NNN), which isn't supported",
+                "0x204: jumps into the middle of the instruction at 0x200",
+                "0x208: unreachable code up to 0x209",
+                "0x20c: writes over the instruction at 0x20e (self-modifying code)",
+            ]
+        );
+
+        let symbols =
+            CrispAteSymbols::parse("label 0x20a flip\n", std::path::Path::new("")).unwrap();
+        let dot = CrispAteAnalysis::analyze(&PROGRAM).dot("test", &symbols);
+        assert!(dot.starts_with("digraph \"test\" {\n"));
+        assert!(dot.contains("\"0x200\" -> \"0x20a\" [style=dashed, label=\"call\"];"));
+        assert!(dot.contains("\"0x202\" -> \"0x206\" [label=\"skip\"];"));
+        assert!(dot.contains("[label=\"flip:\\l0x20a  i := 0x20e\\l0x20c  save v0\\l"));
+        assert!(dot.contains("\"0x208\" [shape=note, label=\"data 0x208-0x209\"];"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
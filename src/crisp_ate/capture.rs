@@ -0,0 +1,516 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::cpu::CrispAte;
+use super::palette::CrispAtePalette;
+use super::screenshot::{to_rgb, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// every `emulation_cyle` ticks the timers once, so one cycle is one 60Hz
+// frame of emulated time, however fast the frontend happens to render
+pub const FRAMES_PER_SECOND: usize = 60;
+
+const SAMPLE_RATE: usize = 44100;
+const SAMPLES_PER_FRAME: usize = SAMPLE_RATE / FRAMES_PER_SECOND;
+const BEEP_FREQUENCY: usize = 440;
+
+// GIF codes can never be wider than 12 bits
+const MAX_LZW_CODES: u16 = 4096;
+
+// GIF sizes are 16 bits
+fn gif_size(scale: usize) -> io::Result<(u16, u16)> {
+    let too_big = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Scale {} is too big for a GIF", scale),
+        )
+    };
+    let width = u16::try_from(SCREEN_WIDTH * scale).map_err(|_| too_big())?;
+    let height = u16::try_from(SCREEN_HEIGHT * scale).map_err(|_| too_big())?;
+
+    Ok((width, height))
+}
+
+// Streams frames to an animated GIF. A frame is only written once the screen
+// changes, with its delay covering every emulated frame it stayed up for.
+pub struct GifRecorder<W: Write> {
+    out: W,
+    scale: usize,
+    pending: Option<[bool; 64 * 32]>,
+    pending_since: usize,
+    pub frames: usize,
+}
+
+impl<W: Write> GifRecorder<W> {
+    pub fn new(mut out: W, scale: usize, palette: &CrispAtePalette) -> io::Result<Self> {
+        let (width, height) = gif_size(scale)?;
+
+        out.write_all(b"GIF89a")?;
+        out.write_all(&width.to_le_bytes())?;
+        out.write_all(&height.to_le_bytes())?;
+        out.write_all(&[0x80, 0, 0])?; // 2 color global table, no background/aspect
+        out.write_all(&palette.color(false))?; // index 0
+        out.write_all(&palette.color(true))?; // index 1
+
+        // NETSCAPE2.0 extension, loop forever
+        out.write_all(&[0x21, 0xFF, 0x0B])?;
+        out.write_all(b"NETSCAPE2.0")?;
+        out.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])?;
+
+        Ok(GifRecorder {
+            out,
+            scale,
+            pending: None,
+            pending_since: 0,
+            frames: 0,
+        })
+    }
+
+    // call once per emulated frame
+    pub fn record(&mut self, screen: &[bool; 64 * 32]) -> io::Result<()> {
+        if self.pending.as_ref() != Some(screen) {
+            self.write_pending()?;
+            self.pending = Some(*screen);
+            self.pending_since = self.frames;
+        }
+
+        self.frames += 1;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_pending()?;
+        self.out.write_all(&[0x3B])?;
+        self.out.flush()?;
+
+        Ok(self.out)
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        let screen = match self.pending.take() {
+            Some(screen) => screen,
+            None => return Ok(()),
+        };
+
+        // GIF delays are in 1/100s, rounding the start and end of every frame
+        // (instead of its length) keeps the clip from drifting. A screen that
+        // stays up longer than a delay can hold is written again until it's
+        // covered
+        let mut delay = centiseconds(self.frames) - centiseconds(self.pending_since);
+        loop {
+            let chunk = delay.min(u16::MAX as usize);
+            self.write_image(&screen, chunk as u16)?;
+            delay -= chunk;
+
+            if delay == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn write_image(&mut self, screen: &[bool; 64 * 32], delay: u16) -> io::Result<()> {
+        let (width, height) = gif_size(self.scale)?;
+
+        // graphic control extension (delay) and image descriptor (full frame)
+        self.out.write_all(&[0x21, 0xF9, 0x04, 0x00])?;
+        self.out.write_all(&delay.to_le_bytes())?;
+        self.out.write_all(&[0x00, 0x00])?;
+
+        self.out.write_all(&[0x2C, 0, 0, 0, 0])?;
+        self.out.write_all(&width.to_le_bytes())?;
+        self.out.write_all(&height.to_le_bytes())?;
+        self.out.write_all(&[0x00])?;
+
+        let mut indices: Vec<u8> = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                indices.push(screen[(y / self.scale) * SCREEN_WIDTH + x / self.scale] as u8);
+            }
+        }
+
+        self.out.write_all(&[2])?; // minimum code size
+        for block in lzw_encode(&indices, 2).chunks(255) {
+            self.out.write_all(&[block.len() as u8])?;
+            self.out.write_all(block)?;
+        }
+        self.out.write_all(&[0x00])?;
+
+        Ok(())
+    }
+}
+
+fn centiseconds(frame: usize) -> usize {
+    (frame * 100 + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.current |= (code as u32) << self.bits;
+        self.bits += size;
+
+        while self.bits >= 8 {
+            self.bytes.push(self.current as u8);
+            self.current >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.current as u8);
+        }
+
+        self.bytes
+    }
+}
+
+// variable width LZW as GIF wants it: codes packed LSB first, a clear code
+// up front and whenever the table fills up, growing the code size as soon as
+// the decoder will need the extra bit
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end + 1;
+    let mut code_size = min_code_size + 1;
+    let mut bits = BitWriter {
+        bytes: Vec::new(),
+        current: 0,
+        bits: 0,
+    };
+
+    bits.write(clear, code_size);
+
+    let mut indices = indices.iter();
+    let mut prefix = match indices.next() {
+        Some(index) => *index as u16,
+        None => {
+            bits.write(end, code_size);
+            return bits.finish();
+        }
+    };
+
+    for index in indices {
+        if let Some(code) = table.get(&(prefix, *index)) {
+            prefix = *code;
+            continue;
+        }
+
+        bits.write(prefix, code_size);
+        if next_code >= 1 << code_size && code_size < 12 {
+            code_size += 1;
+        }
+
+        if next_code < MAX_LZW_CODES {
+            table.insert((prefix, *index), next_code);
+            next_code += 1;
+        } else {
+            bits.write(clear, code_size);
+            table.clear();
+            next_code = end + 1;
+            code_size = min_code_size + 1;
+        }
+
+        prefix = *index as u16;
+    }
+
+    bits.write(prefix, code_size);
+    if next_code >= 1 << code_size && code_size < 12 {
+        code_size += 1;
+    }
+    bits.write(end, code_size);
+
+    bits.finish()
+}
+
+// Streams the beeper to an 8-bit mono WAV: a square wave for every emulated
+// frame the sound timer is running, silence for the rest.
+pub struct WavRecorder<W: Write + Seek> {
+    out: W,
+    samples: usize,
+}
+
+impl<W: Write + Seek> WavRecorder<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        // sizes are filled in by `finish`, once they are known
+        out.write_all(b"RIFF\0\0\0\0WAVE")?;
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&1u16.to_le_bytes())?; // mono
+        out.write_all(&(SAMPLE_RATE as u32).to_le_bytes())?;
+        out.write_all(&(SAMPLE_RATE as u32).to_le_bytes())?; // bytes per second
+        out.write_all(&1u16.to_le_bytes())?; // bytes per sample
+        out.write_all(&8u16.to_le_bytes())?; // bits per sample
+        out.write_all(b"data\0\0\0\0")?;
+
+        Ok(WavRecorder { out, samples: 0 })
+    }
+
+    // call once per emulated frame
+    pub fn record(&mut self, beeping: bool) -> io::Result<()> {
+        let mut frame = [128u8; SAMPLES_PER_FRAME];
+
+        if beeping {
+            for (offset, sample) in frame.iter_mut().enumerate() {
+                // the phase carries over between frames, so long beeps don't click
+                let half_periods = (self.samples + offset) * BEEP_FREQUENCY * 2 / SAMPLE_RATE;
+                *sample = match half_periods % 2 {
+                    0 => 192,
+                    _ => 64,
+                };
+            }
+        }
+
+        self.samples += SAMPLES_PER_FRAME;
+        self.out.write_all(&frame)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.samples as u32;
+
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&data_size.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+
+        Ok(self.out)
+    }
+}
+
+// A recording in progress: an animated GIF at `filename`, plus (optionally)
+// every frame as raw RGB24 and the beeper as WAV next to it, for muxing with
+// something like
+//   ffmpeg -f rawvideo -pix_fmt rgb24 -s 640x320 -r 60 -i clip.rgb -i clip.wav clip.mp4
+pub struct CrispAteCapture {
+    pub filename: String,
+    scale: usize,
+    palette: CrispAtePalette,
+    gif: GifRecorder<BufWriter<File>>,
+    raw: Option<BufWriter<File>>,
+    wav: Option<WavRecorder<BufWriter<File>>>,
+}
+
+impl CrispAteCapture {
+    pub fn start(
+        filename: &str,
+        scale: usize,
+        palette: &CrispAtePalette,
+        raw: bool,
+    ) -> io::Result<Self> {
+        let create = |extension: &str| -> io::Result<BufWriter<File>> {
+            let path = Path::new(filename).with_extension(extension);
+            Ok(BufWriter::new(File::create(path)?))
+        };
+
+        let (raw, wav) = match raw {
+            true => (
+                Some(create("rgb")?),
+                Some(WavRecorder::new(create("wav")?)?),
+            ),
+            false => (None, None),
+        };
+
+        Ok(CrispAteCapture {
+            filename: filename.to_owned(),
+            scale,
+            palette: *palette,
+            gif: GifRecorder::new(BufWriter::new(File::create(filename)?), scale, palette)?,
+            raw,
+            wav,
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.gif.frames
+    }
+
+    // call after every `emulation_cyle`
+    pub fn record(&mut self, vm: &CrispAte) -> io::Result<()> {
+        self.gif.record(&vm.screen)?;
+
+        if let Some(raw) = self.raw.as_mut() {
+            raw.write_all(&to_rgb(&vm.screen, self.scale, &self.palette))?;
+        }
+
+        if let Some(wav) = self.wav.as_mut() {
+            wav.record(vm.timers.sound > 0)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        self.gif.finish()?;
+
+        if let Some(mut raw) = self.raw {
+            raw.flush()?;
+        }
+
+        if let Some(wav) = self.wav {
+            wav.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod capture_tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // reference decoder, straight from the GIF spec
+    fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let end = clear + 1;
+
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut code_size = min_code_size + 1;
+        let mut previous: Option<usize> = None;
+        let mut output: Vec<u8> = Vec::new();
+        let (mut current, mut bits, mut position) = (0u32, 0u8, 0usize);
+
+        loop {
+            while bits < code_size {
+                current |= (data[position] as u32) << bits;
+                bits += 8;
+                position += 1;
+            }
+
+            let code = (current & ((1 << code_size) - 1)) as usize;
+            current >>= code_size;
+            bits -= code_size;
+
+            if code == clear {
+                table = (0..clear).map(|index| vec![index as u8]).collect();
+                table.push(Vec::new());
+                table.push(Vec::new());
+                code_size = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+
+            if code == end {
+                return output;
+            }
+
+            let entry = match (table.get(code), previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => {
+                    let mut entry = table[previous].clone();
+                    entry.push(table[previous][0]);
+                    entry
+                }
+                (None, None) => panic!("Invalid first code"),
+            };
+
+            if let Some(previous) = previous {
+                if table.len() < MAX_LZW_CODES as usize {
+                    let mut added = table[previous].clone();
+                    added.push(entry[0]);
+                    table.push(added);
+                }
+            }
+
+            if table.len() == 1 << code_size && code_size < 12 {
+                code_size += 1;
+            }
+
+            output.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trips() {
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![1],
+            vec![0, 1, 0, 1, 0, 1, 1, 1, 0, 0],
+            (0..20000).map(|i| ((i * 7919) % 13 < 5) as u8).collect(),
+            vec![1; 640 * 320],
+        ];
+
+        for input in inputs {
+            assert_eq!(lzw_decode(&lzw_encode(&input, 2), 2), input);
+        }
+    }
+
+    fn delays(gif: &[u8]) -> Vec<u16> {
+        gif.windows(4)
+            .enumerate()
+            .filter(|(_, window)| window == &[0x21, 0xF9, 0x04, 0x00])
+            .map(|(offset, _)| u16::from_le_bytes([gif[offset + 4], gif[offset + 5]]))
+            .collect()
+    }
+
+    #[test]
+    fn gif_delays_follow_the_emulation_clock() {
+        let mut sut = GifRecorder::new(Vec::new(), 1, &CrispAtePalette::new()).unwrap();
+        let mut screen = [false; 64 * 32];
+
+        for frame in 0..90 {
+            screen[0] = frame >= 30;
+            sut.record(&screen).unwrap();
+        }
+
+        let gif = sut.finish().unwrap();
+
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(gif.last(), Some(&0x3B));
+        assert_eq!(delays(&gif), vec![50, 100]);
+    }
+
+    #[test]
+    fn gif_splits_screens_that_stay_up_too_long_for_one_delay() {
+        let mut sut = GifRecorder::new(Vec::new(), 1, &CrispAtePalette::new()).unwrap();
+        let screen = [false; 64 * 32];
+
+        // 1500 seconds, a title screen waiting for a key
+        for _ in 0..1500 * FRAMES_PER_SECOND {
+            sut.record(&screen).unwrap();
+        }
+
+        assert_eq!(
+            delays(&sut.finish().unwrap()),
+            vec![u16::MAX, u16::MAX, 18930] // 150000 in all
+        );
+    }
+
+    #[test]
+    fn refuses_sizes_a_gif_cannot_hold() {
+        assert!(GifRecorder::new(Vec::new(), 1023, &CrispAtePalette::new()).is_ok());
+        assert!(GifRecorder::new(Vec::new(), 1024, &CrispAtePalette::new()).is_err());
+    }
+
+    #[test]
+    fn wav_has_a_frame_of_samples_per_emulated_frame() {
+        let mut sut = WavRecorder::new(Cursor::new(Vec::new())).unwrap();
+        sut.record(false).unwrap();
+        sut.record(true).unwrap();
+
+        let wav = sut.finish().unwrap().into_inner();
+        let data = &wav[44..];
+
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 36 + 1470);
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 1470);
+        assert!(data[..735].iter().all(|sample| *sample == 128));
+        assert!(data[735..]
+            .iter()
+            .all(|sample| *sample == 64 || *sample == 192));
+        assert!(data[735..].contains(&64));
+    }
+}
@@ -0,0 +1,121 @@
+use super::memory::MEMORY_SIZE;
+
+// an instruction that runs with bytes the program wrote since they last ran
+// (or since the ROM was loaded)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ModifiedCode {
+    pub address: u16,
+    pub writer: u16, // the instruction that wrote it
+}
+
+// Which bytes the program fetched as code and which it wrote since, to catch
+// ROMs that write instructions (with FX55 or FX33) and then run them. Only
+// the program's own writes count, debuggers poking memory don't.
+#[derive(Debug)]
+pub struct CrispAteCodeMap {
+    fetched: Vec<bool>,
+    written: Vec<Option<u16>>, // by the instruction at, since the byte was last fetched
+    pub current: u16,          // the instruction running now
+}
+
+impl Default for CrispAteCodeMap {
+    fn default() -> Self {
+        CrispAteCodeMap::new()
+    }
+}
+
+impl CrispAteCodeMap {
+    pub fn new() -> Self {
+        CrispAteCodeMap {
+            fetched: vec![false; MEMORY_SIZE],
+            written: vec![None; MEMORY_SIZE],
+            current: 0,
+        }
+    }
+
+    // the instruction at `address` is about to run
+    pub fn fetch(&mut self, address: usize) -> Option<ModifiedCode> {
+        self.current = address as u16;
+        let modified = self.modified(address);
+
+        for byte in [address, (address + 1) % MEMORY_SIZE] {
+            self.fetched[byte] = true;
+            self.written[byte] = None;
+        }
+
+        modified
+    }
+
+    // the running instruction wrote `address`
+    pub fn write(&mut self, address: usize) {
+        self.written[address] = Some(self.current);
+    }
+
+    // whether the instruction at `address` would run modified code, without
+    // fetching it
+    pub fn modified(&self, address: usize) -> Option<ModifiedCode> {
+        [address, (address + 1) % MEMORY_SIZE]
+            .iter()
+            .find_map(|byte| self.written[*byte])
+            .map(|writer| ModifiedCode {
+                address: address as u16,
+                writer,
+            })
+    }
+
+    // ran as (part of) an instruction at least once
+    pub fn is_code(&self, address: usize) -> bool {
+        self.fetched[address]
+    }
+
+    // written by the program and not run since
+    pub fn is_written(&self, address: usize) -> bool {
+        self.written[address].is_some()
+    }
+}
+
+#[cfg(test)]
+mod code_map_tests {
+    use super::*;
+    use crate::crisp_ate::cpu::CrispAte;
+
+    #[test]
+    fn catches_written_instructions() {
+        // 0x200 i := 0x208, 0x202 v0 := 0x12, 0x204 save v0,
+        // 0x206 jump 0x208, 0x208 becomes 0x1208 (jump 0x208)
+        let mut vm =
+            CrispAte::with_program(&[0xA2, 0x08, 0x60, 0x12, 0xF0, 0x55, 0x12, 0x08, 0x00, 0x08]);
+        vm.registers.trace = true;
+
+        for _ in 0..3 {
+            vm.emulation_cyle().unwrap();
+        }
+        assert!(vm.code_map.is_written(0x208));
+        assert!(!vm.code_map.is_code(0x208));
+        assert_eq!(
+            vm.code_map.modified(0x208),
+            Some(ModifiedCode {
+                address: 0x208,
+                writer: 0x204
+            })
+        );
+
+        vm.registers.history.clear();
+        vm.emulation_cyle().unwrap();
+        vm.emulation_cyle().unwrap();
+        assert!(vm.code_map.is_code(0x208));
+        assert_eq!(vm.code_map.modified(0x208), None);
+        assert!(vm.registers.history.contains(&String::from(
+            "Executing modified code at 0x208 (written by 0x204)"
+        )));
+
+        // running it again is nothing new
+        vm.registers.history.clear();
+        vm.emulation_cyle().unwrap();
+        assert!(!vm
+            .registers
+            .history
+            .iter()
+            .any(|trace| trace.starts_with("Executing modified code")));
+    }
+}
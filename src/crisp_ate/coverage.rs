@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use super::cpu::PROGRAM_ADDRESS;
+use super::disassembler::disassemble;
+use super::memory::{CrispAteMemory, MEMORY_SIZE};
+use super::symbols::CrispAteSymbols;
+
+// 3XNN, 4XNN, 5XY0 and 9XY0, the only branches that aren't jumps
+pub fn is_skip(opcode: u16) -> bool {
+    matches!(
+        (opcode >> 12, opcode & 0xF),
+        (0x3, _) | (0x4, _) | (0x5, 0x0) | (0x9, 0x0)
+    )
+}
+
+// What ran while `CrispAte::coverage` is set: how often each address was
+// executed and, for the skips, how often they did and didn't skip.
+#[derive(Debug)]
+pub struct CrispAteCoverage {
+    pub program_size: usize,
+    pub executed: Vec<u64>,
+    pub skipped: Vec<u64>,     // the next instruction was skipped
+    pub not_skipped: Vec<u64>, // it ran
+}
+
+impl CrispAteCoverage {
+    pub fn new(program_size: usize) -> Self {
+        CrispAteCoverage {
+            program_size,
+            executed: vec![0; MEMORY_SIZE],
+            skipped: vec![0; MEMORY_SIZE],
+            not_skipped: vec![0; MEMORY_SIZE],
+        }
+    }
+
+    // after the instruction at `address` ran and left the program counter at
+    // `program_counter`
+    pub fn record(&mut self, address: u16, opcode: u16, program_counter: u16) {
+        let index = address as usize % MEMORY_SIZE;
+        self.executed[index] += 1;
+
+        if is_skip(opcode) {
+            match program_counter == address.wrapping_add(4) {
+                true => self.skipped[index] += 1,
+                false => self.not_skipped[index] += 1,
+            }
+        }
+    }
+
+    // the program's addresses, by instruction: two bytes at a time, except
+    // where code that ran starts at an odd address
+    fn instructions(&self) -> Vec<usize> {
+        let end = (PROGRAM_ADDRESS + self.program_size).min(MEMORY_SIZE);
+        let mut addresses: Vec<usize> = Vec::new();
+        let mut address = PROGRAM_ADDRESS;
+
+        while address < end {
+            addresses.push(address);
+            let odd = self.executed[address] == 0
+                && self
+                    .executed
+                    .get(address + 1)
+                    .is_some_and(|count| *count > 0);
+            address += match odd {
+                true => 1,
+                false => 2,
+            };
+        }
+
+        addresses
+    }
+
+    // "3 of 4 instructions, 1 of 2 skip outcomes"
+    pub fn summary(&self, memory: &CrispAteMemory) -> String {
+        let instructions = self.instructions();
+        let ran = instructions
+            .iter()
+            .filter(|address| self.executed[**address] > 0)
+            .count();
+        let skips: Vec<&usize> = instructions
+            .iter()
+            .filter(|address| is_skip(memory.peek_word(**address)))
+            .collect();
+        let outcomes = skips
+            .iter()
+            .map(|address| {
+                (self.skipped[**address] > 0) as usize + (self.not_skipped[**address] > 0) as usize
+            })
+            .sum::<usize>();
+
+        format!(
+            "{} of {} instructions, {} of {} skip outcomes",
+            ran,
+            instructions.len(),
+            outcomes,
+            skips.len() * 2
+        )
+    }
+
+    // The program disassembled, each instruction with how often it ran
+    // (##### when it never did, like gcov) and each skip with how often it
+    // skipped and fell through. Labels start their own line.
+    pub fn annotate(&self, memory: &CrispAteMemory, symbols: &CrispAteSymbols) -> String {
+        let mut text = format!("Coverage: {}\n", self.summary(memory));
+
+        for address in self.instructions() {
+            if let Some(label) = symbols.label(address as u16) {
+                text.push_str(&format!("\n{}:\n", label));
+            }
+
+            let opcode = memory.peek_word(address);
+            let count = match self.executed[address] {
+                0 => String::from("#####"),
+                count => count.to_string(),
+            };
+            let mut line = format!(
+                "{:>10}  {:#06x}  {}",
+                count,
+                address,
+                disassemble(opcode, symbols)
+            );
+
+            if is_skip(opcode) && self.executed[address] > 0 {
+                line.push_str(&format!(
+                    "    ; skipped {}, fell through {}",
+                    self.skipped[address], self.not_skipped[address]
+                ));
+            }
+            if let Some(entry) = symbols.line_at(address as u16) {
+                let file = entry.file.file_name().unwrap_or(entry.file.as_os_str());
+                line = format!("{:<56}  {}:{}", line, file.to_string_lossy(), entry.line);
+            }
+
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+
+        text
+    }
+
+    // An lcov tracefile (what genhtml and editor coverage plugins read), with
+    // a source line counted as often as its busiest instruction ran. Every
+    // skip is a branch of two: falling through (0) and skipping (1). None
+    // without a line table in the symbols.
+    pub fn lcov(&self, memory: &CrispAteMemory, symbols: &CrispAteSymbols) -> Option<String> {
+        if symbols.lines.is_empty() {
+            return None;
+        }
+
+        // per file, per line: the count and the skips in it
+        let mut files: BTreeMap<PathBuf, BTreeMap<u32, (u64, Vec<usize>)>> = BTreeMap::new();
+
+        for address in self.instructions() {
+            let entry = match symbols.line_at(address as u16) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let line = files
+                .entry(entry.file.clone())
+                .or_default()
+                .entry(entry.line)
+                .or_insert((0, Vec::new()));
+
+            line.0 = line.0.max(self.executed[address]);
+            if is_skip(memory.peek_word(address)) {
+                line.1.push(address);
+            }
+        }
+
+        let mut text = String::new();
+        for (file, lines) in files {
+            text.push_str(&format!("TN:\nSF:{}\n", file.display()));
+            let (mut found, mut hit) = (0, 0);
+
+            for (number, (_, skips)) in &lines {
+                for address in skips {
+                    let outcomes = [self.not_skipped[*address], self.skipped[*address]];
+
+                    for (branch, taken) in outcomes.iter().enumerate() {
+                        // "-" is lcov for a branch that was never reached
+                        let taken = match self.executed[*address] {
+                            0 => String::from("-"),
+                            _ => taken.to_string(),
+                        };
+                        text.push_str(&format!(
+                            "BRDA:{},{},{},{}\n",
+                            number, address, branch, taken
+                        ));
+                    }
+                    found += outcomes.len();
+                    hit += outcomes.iter().filter(|taken| **taken > 0).count();
+                }
+            }
+            text.push_str(&format!("BRF:{}\nBRH:{}\n", found, hit));
+
+            for (number, (count, _)) in &lines {
+                text.push_str(&format!("DA:{},{}\n", number, count));
+            }
+            let covered = lines.values().filter(|(count, _)| *count > 0).count();
+            text.push_str(&format!(
+                "LF:{}\nLH:{}\nend_of_record\n",
+                lines.len(),
+                covered
+            ));
+        }
+
+        Some(text)
+    }
+
+    pub fn save_annotated(
+        &self,
+        filename: &str,
+        memory: &CrispAteMemory,
+        symbols: &CrispAteSymbols,
+    ) -> io::Result<()> {
+        fs::write(filename, self.annotate(memory, symbols))
+    }
+
+    pub fn save_lcov(
+        &self,
+        filename: &str,
+        memory: &CrispAteMemory,
+        symbols: &CrispAteSymbols,
+    ) -> io::Result<()> {
+        let text = self.lcov(memory, symbols).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "lcov needs a symbol file with source lines",
+            )
+        })?;
+
+        fs::write(filename, text)
+    }
+}
+
+#[cfg(test)]
+mod coverage_tests {
+    use super::*;
+    use crate::crisp_ate::cpu::CrispAte;
+    use std::path::Path;
+
+    // 0x200 v0 := 1, 0x202 if v0 != 1 then (3001), 0x204 v1 := 1,
+    // 0x206 jump 0x206, 0x208 v2 := 2 (never runs)
+    const PROGRAM: [u8; 10] = [0x60, 0x01, 0x30, 0x01, 0x61, 0x01, 0x12, 0x06, 0x62, 0x02];
+
+    const SYMBOLS: &str = "label 0x200 main\n\
+        line 0x200 1 test.8o\n\
+        line 0x202 2 test.8o\n\
+        line 0x206 4 test.8o\n\
+        line 0x208 6 test.8o\n";
+
+    fn run() -> CrispAte {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        vm.coverage = Some(CrispAteCoverage::new(PROGRAM.len()));
+
+        for _ in 0..5 {
+            vm.emulation_cyle().unwrap();
+        }
+        vm
+    }
+
+    #[test]
+    fn records_instructions_and_skips() {
+        let vm = run();
+        let sut = vm.coverage.as_ref().unwrap();
+
+        assert_eq!(sut.executed[0x200], 1);
+        assert_eq!(sut.executed[0x204], 0);
+        assert_eq!(sut.executed[0x206], 3);
+        assert_eq!((sut.skipped[0x202], sut.not_skipped[0x202]), (1, 0));
+        assert_eq!(
+            sut.summary(&vm.memory),
+            "3 of 5 instructions, 1 of 2 skip outcomes"
+        );
+    }
+
+    #[test]
+    fn annotates_the_disassembly() {
+        let vm = run();
+        let symbols = CrispAteSymbols::parse(SYMBOLS, Path::new("")).unwrap();
+        let annotated = vm.coverage.as_ref().unwrap().annotate(&vm.memory, &symbols);
+        let lines: Vec<&str> = annotated.lines().collect();
+
+        assert_eq!(lines[2], "main:");
+        assert!(lines[4].starts_with("         1  0x0202  if v0 != 0x01 then"));
+        assert!(lines[4].contains("; skipped 1, fell through 0"));
+        assert!(lines[4].ends_with("test.8o:2"));
+        assert!(lines[5].starts_with("     #####  0x0204  v1 := 0x01"));
+    }
+
+    #[test]
+    fn writes_lcov_by_source_line() {
+        let vm = run();
+        let sut = vm.coverage.as_ref().unwrap();
+        let symbols = CrispAteSymbols::parse(SYMBOLS, Path::new("src")).unwrap();
+
+        assert_eq!(
+            sut.lcov(&vm.memory, &symbols).unwrap(),
+            "TN:\nSF:src/test.8o\n\
+            BRDA:2,514,0,0\nBRDA:2,514,1,1\nBRF:2\nBRH:1\n\
+            DA:1,1\nDA:2,1\nDA:4,3\nDA:6,0\nLF:4\nLH:3\nend_of_record\n"
+        );
+        assert_eq!(sut.lcov(&vm.memory, &CrispAteSymbols::new()), None);
+    }
+}
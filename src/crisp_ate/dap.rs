@@ -0,0 +1,1359 @@
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+
+use super::cpu::{CrispAte, REGISTER_NAMES};
+use super::disassembler::disassemble;
+use super::json::{base64_decode, base64_encode, Json};
+use super::memory::MEMORY_SIZE;
+use super::runtime::STACK_SIZE;
+use super::symbols::CrispAteSymbols;
+
+// CHIP-8 only ever runs one thing
+const THREAD_ID: u64 = 1;
+
+// the exception breakpoint that stops before code the program wrote runs
+const MODIFIED_CODE: &str = "modifiedCode";
+
+// the variablesReference of each scope
+const REGISTERS: u64 = 1;
+const STACK: u64 = 2;
+
+// a disassembly covers all of memory at most
+const MAX_DISASSEMBLED_INSTRUCTIONS: u64 = MEMORY_SIZE as u64 / 2;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StepKind {
+    In,   // the next line (or instruction), wherever it is
+    Over, // the next one in this subroutine, calls run through
+    Out,  // back in the caller
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DapState {
+    Running,
+    Paused,
+    Stepping {
+        kind: StepKind,
+        depth: usize,                 // stack pointer when the step started
+        from: Option<(PathBuf, u32)>, // the source line it started on
+    },
+}
+
+// The editor's side of the machine, without any networking: requests come in
+// as JSON and responses and events queue up until `DapServer` sends them.
+pub struct DapSession {
+    pub state: DapState,
+    pub symbols: CrispAteSymbols,
+    pub breakpoints: BTreeSet<u16>,
+    source_breakpoints: Vec<(PathBuf, Vec<u32>)>,
+    instruction_breakpoints: Vec<u16>,
+    symbols_file: Option<PathBuf>, // from the launch configuration
+    launch: Option<String>,        // a ROM the editor wants started
+    stop_on_entry: bool,
+    stop_on_modified_code: bool,
+    configured: bool,
+    pub read_only: bool, // a movie is recording, edits would desync it
+    resuming: bool,      // don't stop at the breakpoint being continued from
+    seq: u64,
+    outgoing: Vec<Json>,
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(digits) => u16::from_str_radix(digits, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// a number, or a label when there are symbols; the label we show after an
+// address (`0x0206 <draw>`) may be left in
+fn parse_value(text: &str, symbols: &CrispAteSymbols) -> Option<u16> {
+    let text = text.split_whitespace().next()?;
+    parse_number(text).or_else(|| symbols.label_address(text))
+}
+
+fn address_text(address: u16) -> String {
+    format!("0x{:04X}", address)
+}
+
+fn pointer_text(address: u16, symbols: &CrispAteSymbols) -> String {
+    match symbols.locate(address) {
+        Some(name) => format!("{} <{}>", address_text(address), name),
+        None => address_text(address),
+    }
+}
+
+fn show_register(name: &str, value: u16, symbols: &CrispAteSymbols) -> String {
+    match name {
+        "I" | "PC" => pointer_text(value, symbols),
+        "SP" | "DT" | "ST" => value.to_string(),
+        _ => format!("0x{:02X}", value),
+    }
+}
+
+fn no_rom() -> String {
+    String::from("No ROM is running")
+}
+
+impl Default for DapSession {
+    fn default() -> Self {
+        DapSession::new()
+    }
+}
+
+impl DapSession {
+    pub fn new() -> Self {
+        DapSession {
+            state: DapState::Running,
+            symbols: CrispAteSymbols::new(),
+            breakpoints: BTreeSet::new(),
+            source_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            symbols_file: None,
+            launch: None,
+            stop_on_entry: false,
+            stop_on_modified_code: false,
+            configured: false,
+            read_only: false,
+            resuming: false,
+            seq: 0,
+            outgoing: Vec::new(),
+        }
+    }
+
+    // the machine waits until the editor has set its breakpoints
+    pub fn attach(&mut self) {
+        self.state = DapState::Paused;
+        self.configured = false;
+        self.stop_on_entry = false;
+        self.symbols_file = None;
+        self.launch = None;
+        self.outgoing.clear();
+    }
+
+    pub fn detach(&mut self) {
+        self.state = DapState::Running;
+        self.configured = false;
+        self.source_breakpoints.clear();
+        self.instruction_breakpoints.clear();
+        self.breakpoints.clear();
+    }
+
+    // a new machine was created, maybe because the editor asked for it
+    pub fn started(&mut self, vm: &mut CrispAte) {
+        self.load_symbols(vm);
+
+        if self.configured {
+            self.begin();
+        }
+    }
+
+    // the ROM from a launch request, for the frontend to start
+    pub fn take_launch(&mut self) -> Option<String> {
+        self.launch.take()
+    }
+
+    pub fn take_messages(&mut self) -> Vec<Json> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    // the window is closing, which ends the editor's debug session
+    pub fn terminated(&mut self) {
+        self.event("terminated", Json::object(vec![]));
+    }
+
+    // how many of the `wanted` cycles the frontend may run this frame
+    pub fn cycles(&self, wanted: usize) -> usize {
+        match self.state {
+            DapState::Running => wanted,
+            DapState::Paused => 0,
+            // a step may not finish in one cycle, so it goes at least as fast
+            // as the machine would run
+            DapState::Stepping { .. } => wanted.max(1),
+        }
+    }
+
+    // false when the cycle must not run
+    pub fn before_cycle(&mut self, vm: &CrispAte) -> bool {
+        if self.state == DapState::Paused {
+            return false;
+        }
+
+        if self.resuming {
+            return true;
+        }
+
+        let program_counter = vm.registers.program_counter;
+        if self.breakpoints.contains(&program_counter) {
+            self.stop("breakpoint", None);
+            return false;
+        }
+
+        let modified = vm.code_map.modified(program_counter as usize % MEMORY_SIZE);
+        if let Some(modified) = modified.filter(|_| self.stop_on_modified_code) {
+            let text = format!(
+                "Running code written by {}",
+                self.symbols.describe(modified.writer)
+            );
+            self.stop("exception", Some(text));
+            return false;
+        }
+
+        true
+    }
+
+    pub fn after_cycle(&mut self, vm: &CrispAte) {
+        self.resuming = false;
+
+        if self.state == DapState::Paused {
+            return;
+        }
+
+        if let Some(fault) = vm.fault {
+            self.stop("exception", Some(fault.to_string()));
+            return;
+        }
+
+        if let DapState::Stepping { kind, depth, from } = &self.state {
+            let now = vm.runtime.stack_pointer;
+            let deep_enough = match kind {
+                StepKind::In => true,
+                StepKind::Over => now <= *depth,
+                StepKind::Out => now < *depth,
+            };
+            // stepping by lines when there is a line table, by instructions
+            // otherwise
+            let line = self.source_line(vm.registers.program_counter);
+            let new_line = *kind == StepKind::Out || line.is_none() || line != *from;
+
+            if deep_enough && new_line {
+                self.stop("step", None);
+            }
+        }
+    }
+
+    // answers one request, along with any events it causes
+    pub fn handle(&mut self, request: &Json, vm: Option<&mut CrispAte>) {
+        let command = request.get("command").as_str().unwrap_or("");
+        let result = self.dispatch(command, request.get("arguments"), vm);
+        let success = result.is_ok();
+
+        self.seq += 1;
+        let mut response = vec![
+            ("seq", Json::from(self.seq)),
+            ("type", Json::from("response")),
+            ("request_seq", request.get("seq").clone()),
+            ("success", Json::from(success)),
+            ("command", Json::from(command)),
+        ];
+        match result {
+            Ok(body) => response.push(("body", body)),
+            Err(message) => response.push(("message", Json::from(message))),
+        }
+        self.outgoing.push(Json::object(response));
+
+        if !success {
+            return;
+        }
+
+        // events that must come after the response
+        match command {
+            "initialize" => self.event("initialized", Json::object(vec![])),
+            "configurationDone" if self.launch.is_none() => self.begin(),
+            "pause" => self.stop("pause", None),
+            _ => {}
+        }
+    }
+
+    fn dispatch(
+        &mut self,
+        command: &str,
+        arguments: &Json,
+        vm: Option<&mut CrispAte>,
+    ) -> Result<Json, String> {
+        match command {
+            "initialize" => Ok(Json::object(vec![
+                ("supportsConfigurationDoneRequest", Json::from(true)),
+                ("supportsSetVariable", Json::from(true)),
+                ("supportsReadMemoryRequest", Json::from(true)),
+                ("supportsWriteMemoryRequest", Json::from(true)),
+                ("supportsInstructionBreakpoints", Json::from(true)),
+                ("supportsDisassembleRequest", Json::from(true)),
+                (
+                    "exceptionBreakpointFilters",
+                    Json::from(vec![Json::object(vec![
+                        ("filter", Json::from(MODIFIED_CODE)),
+                        ("label", Json::from("Modified code")),
+                        (
+                            "description",
+                            Json::from("Stop before running instructions the ROM wrote itself"),
+                        ),
+                        ("default", Json::from(false)),
+                    ])]),
+                ),
+            ])),
+            "launch" | "attach" => {
+                self.stop_on_entry = arguments.get("stopOnEntry").as_bool().unwrap_or(false);
+                self.symbols_file = arguments.get("symbols").as_str().map(PathBuf::from);
+
+                match arguments.get("program").as_str() {
+                    Some(program) => self.launch = Some(program.to_owned()),
+                    None => match vm {
+                        Some(vm) => self.load_symbols(vm),
+                        None => return Err(String::from("No ROM is running, set `program`")),
+                    },
+                }
+                Ok(Json::Null)
+            }
+            "setBreakpoints" => Ok(self.set_breakpoints(arguments)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(arguments)),
+            "setExceptionBreakpoints" => {
+                self.stop_on_modified_code = arguments
+                    .get("filters")
+                    .as_array()
+                    .iter()
+                    .any(|filter| filter.as_str() == Some(MODIFIED_CODE));
+                Ok(Json::object(vec![]))
+            }
+            "configurationDone" => {
+                self.configured = true;
+                Ok(Json::Null)
+            }
+            "threads" => Ok(Json::object(vec![(
+                "threads",
+                Json::from(vec![Json::object(vec![
+                    ("id", Json::from(THREAD_ID)),
+                    ("name", Json::from("CHIP-8")),
+                ])]),
+            )])),
+            "stackTrace" => Ok(self.stack_trace(vm.ok_or_else(no_rom)?)),
+            "scopes" => Ok(Json::object(vec![(
+                "scopes",
+                Json::from(vec![
+                    Json::object(vec![
+                        ("name", Json::from("Registers")),
+                        ("variablesReference", Json::from(REGISTERS)),
+                        ("expensive", Json::from(false)),
+                    ]),
+                    Json::object(vec![
+                        ("name", Json::from("Stack")),
+                        ("variablesReference", Json::from(STACK)),
+                        ("expensive", Json::from(false)),
+                    ]),
+                ]),
+            )])),
+            "variables" => variables(vm.ok_or_else(no_rom)?, arguments),
+            "setVariable" | "writeMemory" if self.read_only => {
+                Err(String::from("Can't edit while recording a movie"))
+            }
+            "setVariable" => set_variable(vm.ok_or_else(no_rom)?, arguments),
+            "readMemory" => read_memory(vm.ok_or_else(no_rom)?, arguments),
+            "writeMemory" => write_memory(vm.ok_or_else(no_rom)?, arguments),
+            "disassemble" => self.disassemble(vm.ok_or_else(no_rom)?, arguments),
+            "continue" => {
+                self.state = DapState::Running;
+                self.resuming = true;
+                Ok(Json::object(vec![(
+                    "allThreadsContinued",
+                    Json::from(true),
+                )]))
+            }
+            "next" | "stepIn" | "stepOut" => {
+                let vm = vm.ok_or_else(no_rom)?;
+                self.state = DapState::Stepping {
+                    kind: match command {
+                        "next" => StepKind::Over,
+                        "stepIn" => StepKind::In,
+                        _ => StepKind::Out,
+                    },
+                    depth: vm.runtime.stack_pointer,
+                    from: self.source_line(vm.registers.program_counter),
+                };
+                self.resuming = true;
+                Ok(Json::Null)
+            }
+            "pause" => Ok(Json::Null),
+            "disconnect" => {
+                self.detach();
+                Ok(Json::Null)
+            }
+            _ => Err(format!("{} is not supported", command)),
+        }
+    }
+
+    // the machine's symbols, unless the launch configuration names a file,
+    // which the machine then uses as well
+    fn load_symbols(&mut self, vm: &mut CrispAte) {
+        if let Some(filename) = &self.symbols_file {
+            vm.symbols = CrispAteSymbols::load(filename).unwrap_or_else(|e| {
+                eprintln!("Could not read {}: {}", filename.display(), e);
+                CrispAteSymbols::new()
+            });
+        }
+        self.symbols = vm.symbols.clone();
+
+        // a rebuilt ROM may have moved the code of a line
+        self.resolve_breakpoints();
+    }
+
+    fn resolve_breakpoints(&mut self) {
+        let lines = self.source_breakpoints.iter().flat_map(|(file, lines)| {
+            lines
+                .iter()
+                .filter_map(|line| self.symbols.address_of(file, *line))
+                .map(|entry| entry.address)
+        });
+
+        self.breakpoints = lines
+            .chain(self.instruction_breakpoints.iter().copied())
+            .collect();
+    }
+
+    // after configuration, or when a new ROM starts
+    fn begin(&mut self) {
+        match self.stop_on_entry {
+            true => self.stop("entry", None),
+            false => self.state = DapState::Running,
+        }
+    }
+
+    fn source_line(&self, address: u16) -> Option<(PathBuf, u32)> {
+        self.symbols
+            .line_at(address)
+            .map(|entry| (entry.file.clone(), entry.line))
+    }
+
+    fn stop(&mut self, reason: &str, text: Option<String>) {
+        self.state = DapState::Paused;
+
+        let mut body = vec![
+            ("reason", Json::from(reason)),
+            ("threadId", Json::from(THREAD_ID)),
+            ("allThreadsStopped", Json::from(true)),
+        ];
+        if let Some(text) = text {
+            body.push(("description", Json::from(text.as_str())));
+            body.push(("text", Json::from(text)));
+        }
+
+        self.event("stopped", Json::object(body));
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.seq += 1;
+        self.outgoing.push(Json::object(vec![
+            ("seq", Json::from(self.seq)),
+            ("type", Json::from("event")),
+            ("event", Json::from(event)),
+            ("body", body),
+        ]));
+    }
+
+    // replaces the breakpoints of one source file
+    fn set_breakpoints(&mut self, arguments: &Json) -> Json {
+        let file = PathBuf::from(arguments.get("source").get("path").as_str().unwrap_or(""));
+        let lines: Vec<u32> = arguments
+            .get("breakpoints")
+            .as_array()
+            .iter()
+            .filter_map(|breakpoint| breakpoint.get("line").as_u64())
+            .map(|line| line as u32)
+            .collect();
+
+        let breakpoints: Vec<Json> = lines
+            .iter()
+            .map(|line| match self.symbols.address_of(&file, *line) {
+                Some(entry) => Json::object(vec![
+                    ("verified", Json::from(true)),
+                    ("line", Json::from(entry.line as u64)),
+                    (
+                        "instructionReference",
+                        Json::from(address_text(entry.address)),
+                    ),
+                ]),
+                None => Json::object(vec![
+                    ("verified", Json::from(false)),
+                    ("line", Json::from(*line as u64)),
+                    ("message", Json::from("No code at this line")),
+                ]),
+            })
+            .collect();
+
+        self.source_breakpoints.retain(|(known, _)| *known != file);
+        if !lines.is_empty() {
+            self.source_breakpoints.push((file, lines));
+        }
+        self.resolve_breakpoints();
+
+        Json::object(vec![("breakpoints", Json::from(breakpoints))])
+    }
+
+    // from the disassembly view, which works without a line table
+    fn set_instruction_breakpoints(&mut self, arguments: &Json) -> Json {
+        let addresses: Vec<Option<u16>> = arguments
+            .get("breakpoints")
+            .as_array()
+            .iter()
+            .map(|breakpoint| {
+                let reference = parse_number(breakpoint.get("instructionReference").as_str()?)?;
+                let offset = breakpoint.get("offset").as_i64().unwrap_or(0);
+                (reference as i64)
+                    .checked_add(offset)
+                    .and_then(|address| u16::try_from(address).ok())
+                    .filter(|address| (*address as usize) < MEMORY_SIZE)
+            })
+            .collect();
+
+        self.instruction_breakpoints = addresses.iter().flatten().copied().collect();
+        self.resolve_breakpoints();
+
+        let breakpoints = addresses
+            .iter()
+            .map(|address| Json::object(vec![("verified", Json::from(address.is_some()))]))
+            .collect();
+        Json::object(vec![("breakpoints", Json::Array(breakpoints))])
+    }
+
+    // the subroutine an address is in, when there are labels
+    fn frame_name(&self, address: u16) -> String {
+        self.symbols
+            .locate(address)
+            .unwrap_or_else(|| address_text(address))
+    }
+
+    // `instructionCount` instructions (no more than memory holds), two bytes
+    // each, with addresses outside of memory marked invalid as the protocol
+    // asks
+    fn disassemble(&self, vm: &CrispAte, arguments: &Json) -> Result<Json, String> {
+        let reference = arguments
+            .get("memoryReference")
+            .as_str()
+            .and_then(|reference| parse_value(reference, &self.symbols))
+            .unwrap_or(0) as i64;
+        let start = arguments
+            .get("instructionOffset")
+            .as_i64()
+            .unwrap_or(0)
+            .checked_mul(2)
+            .and_then(|instructions| {
+                reference
+                    .checked_add(arguments.get("offset").as_i64().unwrap_or(0))?
+                    .checked_add(instructions)
+            })
+            .ok_or_else(|| String::from("Outside of memory"))?;
+        let count = arguments
+            .get("instructionCount")
+            .as_u64()
+            .unwrap_or(0)
+            .min(MAX_DISASSEMBLED_INSTRUCTIONS) as i64;
+
+        let instructions: Vec<Json> = (0..count)
+            .map(|index| start.saturating_add(index * 2))
+            .map(|address| {
+                if address < 0 || address >= MEMORY_SIZE as i64 - 1 {
+                    return Json::object(vec![
+                        ("address", Json::from(format!("{}", address))),
+                        ("instruction", Json::from("")),
+                        ("presentationHint", Json::from("invalid")),
+                    ]);
+                }
+
+                let address = address as u16;
+                let opcode = vm.memory.peek_word(address as usize);
+                let mut instruction = vec![
+                    ("address", Json::from(address_text(address))),
+                    (
+                        "instructionBytes",
+                        Json::from(format!("{:02X} {:02X}", opcode >> 8, opcode & 0xFF)),
+                    ),
+                    (
+                        "instruction",
+                        Json::from(disassemble(opcode, &self.symbols)),
+                    ),
+                ];
+
+                if let Some(name) = self.symbols.label(address) {
+                    instruction.push(("symbol", Json::from(name)));
+                }
+
+                // only the first instruction of a line gets it, which is how
+                // editors know where a line starts
+                if let Some(entry) = self.symbols.lines.iter().find(|e| e.address == address) {
+                    instruction.push(("location", source(&entry.file)));
+                    instruction.push(("line", Json::from(entry.line as u64)));
+                }
+
+                Json::object(instruction)
+            })
+            .collect();
+
+        Ok(Json::object(vec![(
+            "instructions",
+            Json::from(instructions),
+        )]))
+    }
+
+    // the current instruction, then the 2NNN of every caller
+    fn stack_trace(&self, vm: &CrispAte) -> Json {
+        let callers = vm.runtime.stack[..vm.runtime.stack_pointer.min(STACK_SIZE)]
+            .iter()
+            .rev();
+        let frames: Vec<Json> = std::iter::once(&vm.registers.program_counter)
+            .chain(callers)
+            .enumerate()
+            .map(|(id, address)| {
+                let mut frame = vec![
+                    ("id", Json::from(id)),
+                    ("name", Json::from(self.frame_name(*address))),
+                    (
+                        "instructionPointerReference",
+                        Json::from(address_text(*address)),
+                    ),
+                ];
+
+                match self.symbols.line_at(*address) {
+                    Some(entry) => {
+                        frame.push(("source", source(&entry.file)));
+                        frame.push(("line", Json::from(entry.line as u64)));
+                        frame.push(("column", Json::from(1u64)));
+                    }
+                    None => {
+                        frame.push(("line", Json::from(0u64)));
+                        frame.push(("column", Json::from(0u64)));
+                    }
+                }
+
+                Json::object(frame)
+            })
+            .collect();
+
+        Json::object(vec![
+            ("totalFrames", Json::from(frames.len())),
+            ("stackFrames", Json::from(frames)),
+        ])
+    }
+}
+
+fn source(file: &Path) -> Json {
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned());
+
+    Json::object(vec![
+        ("name", Json::from(name.unwrap_or_default())),
+        ("path", Json::from(file.to_string_lossy().into_owned())),
+    ])
+}
+
+fn variable(name: &str, value: String, memory_reference: Option<u16>) -> Json {
+    let mut fields = vec![
+        ("name", Json::from(name)),
+        ("value", Json::from(value)),
+        ("variablesReference", Json::from(0u64)),
+    ];
+
+    // lets the editor open a memory view at the address
+    if let Some(address) = memory_reference {
+        fields.push(("memoryReference", Json::from(address_text(address))));
+    }
+
+    Json::object(fields)
+}
+
+fn variables(vm: &CrispAte, arguments: &Json) -> Result<Json, String> {
+    let variables: Vec<Json> = match arguments.get("variablesReference").as_u64() {
+        Some(REGISTERS) => REGISTER_NAMES
+            .iter()
+            .map(|name| {
+                let value = vm.register(name).unwrap_or(0);
+                let pointer = matches!(*name, "I" | "PC").then_some(value);
+                variable(name, show_register(name, value, &vm.symbols), pointer)
+            })
+            .collect(),
+        Some(STACK) => vm.runtime.stack[..vm.runtime.stack_pointer.min(STACK_SIZE)]
+            .iter()
+            .enumerate()
+            .map(|(slot, address)| {
+                let value = pointer_text(*address, &vm.symbols);
+                variable(&slot.to_string(), value, Some(*address))
+            })
+            .collect(),
+        _ => return Err(String::from("Unknown variablesReference")),
+    };
+
+    Ok(Json::object(vec![("variables", Json::from(variables))]))
+}
+
+fn set_variable(vm: &mut CrispAte, arguments: &Json) -> Result<Json, String> {
+    let name = arguments.get("name").as_str().unwrap_or("");
+    let value = arguments
+        .get("value")
+        .as_str()
+        .and_then(|value| parse_value(value, &vm.symbols))
+        .ok_or_else(|| String::from("Not a number or label"))?;
+
+    let shown = match arguments.get("variablesReference").as_u64() {
+        Some(REGISTERS) => {
+            vm.set_register(name, value)
+                .ok_or_else(|| format!("Can't set {}", name))?;
+            show_register(name, value, &vm.symbols)
+        }
+        Some(STACK) => {
+            let slot = name
+                .parse::<usize>()
+                .ok()
+                .filter(|slot| *slot < vm.runtime.stack_pointer.min(STACK_SIZE))
+                .ok_or_else(|| format!("No stack entry {}", name))?;
+            vm.runtime.stack[slot] = value;
+            pointer_text(value, &vm.symbols)
+        }
+        _ => return Err(String::from("Unknown variablesReference")),
+    };
+
+    Ok(Json::object(vec![("value", Json::from(shown))]))
+}
+
+// where a memory request starts, reference plus offset
+fn memory_start(arguments: &Json) -> Result<i64, String> {
+    let reference = arguments
+        .get("memoryReference")
+        .as_str()
+        .and_then(parse_number)
+        .ok_or_else(|| String::from("Invalid memoryReference"))?;
+
+    (reference as i64)
+        .checked_add(arguments.get("offset").as_i64().unwrap_or(0))
+        .ok_or_else(|| String::from("Outside of memory"))
+}
+
+// read without touching hooks or mapped devices
+fn read_memory(vm: &CrispAte, arguments: &Json) -> Result<Json, String> {
+    let start = memory_start(arguments)?;
+    let count = i64::try_from(arguments.get("count").as_u64().unwrap_or(0)).unwrap_or(i64::MAX);
+
+    let first = start.clamp(0, MEMORY_SIZE as i64);
+    let end = start.saturating_add(count).clamp(first, MEMORY_SIZE as i64);
+    let bytes: Vec<u8> = (first..end).map(|at| vm.memory.peek(at as usize)).collect();
+
+    Ok(Json::object(vec![
+        ("address", Json::from(address_text(first as u16))),
+        ("data", Json::from(base64_encode(&bytes))),
+        ("unreadableBytes", Json::from(count - bytes.len() as i64)),
+    ]))
+}
+
+fn write_memory(vm: &mut CrispAte, arguments: &Json) -> Result<Json, String> {
+    let start = memory_start(arguments)?;
+    let bytes = arguments
+        .get("data")
+        .as_str()
+        .and_then(base64_decode)
+        .ok_or_else(|| String::from("Invalid data"))?;
+
+    if start < 0 || start as usize + bytes.len() > MEMORY_SIZE {
+        return Err(String::from("Outside of memory"));
+    }
+
+    vm.memory.load(start as usize, &bytes);
+    Ok(Json::object(vec![(
+        "bytesWritten",
+        Json::from(bytes.len()),
+    )]))
+}
+
+// Content-Length: <bytes>\r\n\r\n<json>
+pub fn frame(message: &Json) -> String {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+// takes the first complete message out of `buffer`, skipping broken ones;
+// partial messages stay until the rest arrives
+pub fn next_message(buffer: &mut Vec<u8>) -> Option<Json> {
+    loop {
+        let end = buffer.windows(4).position(|window| window == b"\r\n\r\n")?;
+        let header = String::from_utf8_lossy(&buffer[..end]).into_owned();
+        let length = header.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            match name.trim().eq_ignore_ascii_case("Content-Length") {
+                true => value.trim().parse::<usize>().ok(),
+                false => None,
+            }
+        });
+
+        let length = match length {
+            Some(length) => length,
+            None => {
+                buffer.drain(..end + 4);
+                continue;
+            }
+        };
+
+        if buffer.len() < end + 4 + length {
+            return None;
+        }
+
+        let message: Vec<u8> = buffer.drain(..end + 4 + length).skip(end + 4).collect();
+        if let Some(message) = std::str::from_utf8(&message).ok().and_then(Json::parse) {
+            return Some(message);
+        }
+    }
+}
+
+// A Debug Adapter Protocol server on localhost, for editors to connect to
+// (VS Code's `debugServer`). Like `GdbServer` it never blocks: the frontend
+// polls it once a frame and asks it before and after every cycle.
+pub struct DapServer {
+    pub session: DapSession,
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    buffer: Vec<u8>,
+}
+
+impl DapServer {
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(DapServer {
+            session: DapSession::new(),
+            listener,
+            client: None,
+            buffer: Vec::new(),
+        })
+    }
+
+    // accepts an editor and answers whatever it sent since the last frame,
+    // without a machine while the launcher is showing
+    pub fn poll(&mut self, mut vm: Option<&mut CrispAte>) {
+        if self.client.is_none() && !self.accept() {
+            return;
+        }
+
+        let mut chunk = [0u8; 4096];
+        while let Some(client) = self.client.as_mut() {
+            match client.read(&mut chunk) {
+                Ok(0) => self.disconnect(),
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => self.disconnect(),
+            }
+        }
+
+        while let Some(message) = next_message(&mut self.buffer) {
+            if message.get("type").as_str() != Some("request") {
+                continue;
+            }
+
+            self.session.handle(&message, vm.as_deref_mut());
+
+            if message.get("command").as_str() == Some("disconnect") {
+                self.flush();
+                self.disconnect();
+            }
+        }
+
+        // a machine that already faulted stops again right away
+        if let Some(vm) = vm.filter(|vm| vm.fault.is_some()) {
+            self.session.after_cycle(vm);
+        }
+
+        self.flush();
+    }
+
+    pub fn started(&mut self, vm: &mut CrispAte) {
+        self.session.started(vm);
+        self.flush();
+    }
+
+    // false when the cycle must not run
+    pub fn before_cycle(&mut self, vm: &CrispAte) -> bool {
+        let run = self.session.before_cycle(vm);
+        self.flush();
+        run
+    }
+
+    pub fn after_cycle(&mut self, vm: &CrispAte) {
+        self.session.after_cycle(vm);
+        self.flush();
+    }
+
+    // the window is closing
+    pub fn terminated(&mut self) {
+        if self.client.is_some() {
+            self.session.terminated();
+            self.flush();
+        }
+    }
+
+    fn accept(&mut self) -> bool {
+        match self.listener.accept() {
+            Ok((client, address)) => {
+                if client.set_nonblocking(true).is_err() {
+                    return false;
+                }
+                let _ = client.set_nodelay(true);
+
+                println!("Editor connected from {}", address);
+                self.client = Some(client);
+                self.buffer.clear();
+                self.session.attach();
+                true
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => false,
+            Err(e) => {
+                eprintln!("Could not accept editor: {}", e);
+                false
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        if self.client.take().is_some() {
+            println!("Editor disconnected");
+        }
+        self.buffer.clear();
+        self.session.detach();
+    }
+
+    // messages are small, so they are written blocking in one go
+    fn flush(&mut self) {
+        let messages = self.session.take_messages();
+        let client = match self.client.as_mut() {
+            Some(client) => client,
+            None => return,
+        };
+
+        let mut sent = client.set_nonblocking(false);
+        for message in messages {
+            sent = sent.and_then(|_| client.write_all(frame(&message).as_bytes()));
+        }
+        sent = sent.and_then(|_| client.set_nonblocking(true));
+
+        if sent.is_err() {
+            self.disconnect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod dap_tests {
+    use super::*;
+    use crate::crisp_ate::fault::FaultPolicy;
+    use crate::crisp_ate::registers::VReg;
+
+    // 0x200 call 0x206, 0x202 6001, 0x204 jump 0x204, 0x206 6102, 0x208 return
+    const PROGRAM: [u8; 10] = [0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x61, 0x02, 0x00, 0xEE];
+
+    const SYMBOLS: &str = "label 0x200 main\n\
+        label 0x206 update\n\
+        line 0x200 1 game.8o\n\
+        line 0x202 2 game.8o\n\
+        line 0x204 3 game.8o\n\
+        line 0x206 6 game.8o\n";
+
+    fn request(sut: &mut DapSession, vm: &mut CrispAte, command: &str, arguments: Json) -> Json {
+        let message = Json::object(vec![
+            ("seq", Json::from(1u64)),
+            ("type", Json::from("request")),
+            ("command", Json::from(command)),
+            ("arguments", arguments),
+        ]);
+        sut.handle(&message, Some(vm));
+
+        let messages = sut.take_messages();
+        let response = messages
+            .iter()
+            .find(|message| message.get("type").as_str() == Some("response"))
+            .unwrap();
+        assert_eq!(response.get("command").as_str(), Some(command));
+        response.clone()
+    }
+
+    // runs frames like the window does, the stopped events that came out
+    fn run(sut: &mut DapSession, vm: &mut CrispAte, frames: usize) -> Vec<Json> {
+        let mut stops: Vec<Json> = Vec::new();
+
+        for _ in 0..frames {
+            for _ in 0..sut.cycles(1) {
+                if !sut.before_cycle(vm) {
+                    break;
+                }
+                let _ = vm.emulation_cyle();
+                sut.after_cycle(vm);
+            }
+
+            stops.extend(
+                sut.take_messages()
+                    .into_iter()
+                    .filter(|message| message.get("event").as_str() == Some("stopped")),
+            );
+        }
+
+        stops
+    }
+
+    fn configured(vm: &mut CrispAte) -> DapSession {
+        let mut sut = DapSession::new();
+        vm.symbols = CrispAteSymbols::parse(SYMBOLS, Path::new("")).unwrap();
+        sut.attach();
+        sut.started(vm);
+        request(&mut sut, vm, "configurationDone", Json::Null);
+        sut
+    }
+
+    #[test]
+    fn frames_and_unframes_messages() {
+        let message = Json::object(vec![("seq", Json::from(1u64))]);
+        assert_eq!(frame(&message), "Content-Length: 9\r\n\r\n{\"seq\":1}");
+
+        let mut buffer = b"Content-Length: 9\r\n\r\n{\"seq\":1}X-Junk: 1\r\n\r\n".to_vec();
+        buffer.extend_from_slice(b"content-length: 9\r\n\r\n{\"seq\"");
+        assert_eq!(next_message(&mut buffer), Some(message.clone()));
+        assert_eq!(next_message(&mut buffer), None);
+
+        buffer.extend_from_slice(b":1}");
+        assert_eq!(next_message(&mut buffer), Some(message));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn waits_for_the_configuration() {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        let mut sut = DapSession::new();
+        sut.attach();
+        sut.started(&mut vm);
+
+        let message = Json::parse(r#"{"seq": 1, "type": "request", "command": "initialize"}"#);
+        sut.handle(&message.unwrap(), None);
+        let messages = sut.take_messages();
+        assert_eq!(messages[0].get("success").as_bool(), Some(true));
+        assert_eq!(messages[1].get("event").as_str(), Some("initialized"));
+        assert_eq!(sut.cycles(8), 0);
+
+        let launch = Json::object(vec![("stopOnEntry", Json::from(true))]);
+        request(&mut sut, &mut vm, "launch", launch);
+        let message =
+            Json::parse(r#"{"seq": 2, "type": "request", "command": "configurationDone"}"#);
+        sut.handle(&message.unwrap(), Some(&mut vm));
+
+        let messages = sut.take_messages();
+        assert_eq!(
+            messages[1].get("body").get("reason").as_str(),
+            Some("entry")
+        );
+        assert_eq!(sut.state, DapState::Paused);
+
+        let response = request(&mut sut, &mut vm, "evaluate", Json::Null);
+        assert_eq!(response.get("success").as_bool(), Some(false));
+    }
+
+    #[test]
+    fn stops_at_source_breakpoints() {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        let mut sut = configured(&mut vm);
+
+        let arguments = Json::parse(
+            r#"{"source": {"path": "game.8o"}, "breakpoints": [{"line": 4}, {"line": 9}]}"#,
+        );
+        let response = request(&mut sut, &mut vm, "setBreakpoints", arguments.unwrap());
+        let breakpoints = response.get("body").get("breakpoints").as_array();
+
+        // line 4 has no code, so it breaks at the next line that does
+        assert_eq!(breakpoints[0].get("verified").as_bool(), Some(true));
+        assert_eq!(breakpoints[0].get("line").as_u64(), Some(6));
+        assert_eq!(breakpoints[1].get("verified").as_bool(), Some(false));
+
+        let stops = run(&mut sut, &mut vm, 4);
+        assert_eq!(stops.len(), 1);
+        assert_eq!(
+            stops[0].get("body").get("reason").as_str(),
+            Some("breakpoint")
+        );
+        assert_eq!(vm.registers.program_counter, 0x206);
+
+        let trace = request(&mut sut, &mut vm, "stackTrace", Json::Null);
+        let frames = trace.get("body").get("stackFrames").as_array();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].get("line").as_u64(), Some(6));
+        assert_eq!(frames[0].get("name").as_str(), Some("update"));
+        assert_eq!(frames[1].get("name").as_str(), Some("main"));
+        assert_eq!(
+            frames[1].get("source").get("name").as_str(),
+            Some("game.8o")
+        );
+    }
+
+    #[test]
+    fn steps_in_over_and_out() {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        let mut sut = configured(&mut vm);
+        request(&mut sut, &mut vm, "pause", Json::Null);
+
+        request(&mut sut, &mut vm, "stepIn", Json::Null);
+        assert_eq!(run(&mut sut, &mut vm, 4).len(), 1);
+        assert_eq!(vm.registers.program_counter, 0x206);
+
+        // line 6 covers the return as well, so stepping out ends in the caller
+        request(&mut sut, &mut vm, "stepOut", Json::Null);
+        assert_eq!(run(&mut sut, &mut vm, 4).len(), 1);
+        assert_eq!(vm.registers.program_counter, 0x202);
+
+        vm.registers.program_counter = 0x200;
+        request(&mut sut, &mut vm, "next", Json::Null);
+        assert_eq!(run(&mut sut, &mut vm, 8).len(), 1);
+        assert_eq!(vm.registers.program_counter, 0x202);
+        assert_eq!(vm.runtime.stack_pointer, 0);
+    }
+
+    #[test]
+    fn shows_and_edits_registers_stack_and_memory() {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        let mut sut = configured(&mut vm);
+        vm.emulation_cyle().unwrap();
+
+        let registers = Json::object(vec![("variablesReference", Json::from(REGISTERS))]);
+        let response = request(&mut sut, &mut vm, "variables", registers);
+        let variables = response.get("body").get("variables").as_array();
+        assert_eq!(variables.len(), 21);
+        assert_eq!(variables[17].get("value").as_str(), Some("0x0206 <update>"));
+        assert_eq!(
+            variables[17].get("memoryReference").as_str(),
+            Some("0x0206")
+        );
+
+        let assignment = Json::parse(r#"{"variablesReference": 1, "name": "VA", "value": "0x2a"}"#);
+        let response = request(&mut sut, &mut vm, "setVariable", assignment.unwrap());
+        assert_eq!(response.get("body").get("value").as_str(), Some("0x2A"));
+        assert_eq!(vm.registers.v[VReg::new(0xA).unwrap()], 0x2A);
+
+        let assignment = Json::parse(r#"{"variablesReference": 1, "name": "DT", "value": "300"}"#);
+        let response = request(&mut sut, &mut vm, "setVariable", assignment.unwrap());
+        assert_eq!(response.get("success").as_bool(), Some(false));
+
+        let stack = Json::object(vec![("variablesReference", Json::from(STACK))]);
+        let response = request(&mut sut, &mut vm, "variables", stack);
+        let variables = response.get("body").get("variables").as_array();
+        assert_eq!(variables.len(), 1);
+        assert_eq!(variables[0].get("value").as_str(), Some("0x0200 <main>"));
+
+        // labels work as values, with or without the address in front
+        let assignment =
+            Json::parse(r#"{"variablesReference": 1, "name": "I", "value": "update"}"#);
+        request(&mut sut, &mut vm, "setVariable", assignment.unwrap());
+        assert_eq!(vm.registers.address, 0x206);
+
+        let assignment =
+            Json::parse(r#"{"variablesReference": 2, "name": "0", "value": "0x0202 <main+2>"}"#);
+        request(&mut sut, &mut vm, "setVariable", assignment.unwrap());
+        assert_eq!(vm.runtime.stack[0], 0x202);
+
+        let read = Json::parse(r#"{"memoryReference": "0x0206", "offset": -6, "count": 4}"#);
+        let response = request(&mut sut, &mut vm, "readMemory", read.unwrap());
+        assert_eq!(response.get("body").get("data").as_str(), Some("IgZgAQ=="));
+
+        let read = Json::parse(r#"{"memoryReference": "0x0FFE", "count": 4}"#);
+        let response = request(&mut sut, &mut vm, "readMemory", read.unwrap());
+        assert_eq!(
+            response.get("body").get("unreadableBytes").as_i64(),
+            Some(2)
+        );
+
+        let write = Json::parse(r#"{"memoryReference": "0x0202", "data": "AOA="}"#);
+        request(&mut sut, &mut vm, "writeMemory", write.unwrap());
+        assert_eq!(vm.memory.peek(0x203), 0xE0);
+    }
+
+    #[test]
+    fn pauses_on_faults() {
+        let mut vm = CrispAte::with_program(&[0x00, 0xEE]);
+        vm.fault_policy = FaultPolicy::Halt;
+        let mut sut = configured(&mut vm);
+
+        let stops = run(&mut sut, &mut vm, 2);
+
+        assert_eq!(stops.len(), 1);
+        assert_eq!(
+            stops[0].get("body").get("reason").as_str(),
+            Some("exception")
+        );
+        assert!(stops[0]
+            .get("body")
+            .get("text")
+            .as_str()
+            .is_some_and(|text| text.contains("0x200")));
+    }
+
+    #[test]
+    fn stops_before_modified_code() {
+        // 0x200 i := 0x208, 0x202 v0 := 0x12, 0x204 save v0, 0x206 jump 0x208,
+        // 0x208 0x0008 until the save makes it jump 0x208
+        let mut vm =
+            CrispAte::with_program(&[0xA2, 0x08, 0x60, 0x12, 0xF0, 0x55, 0x12, 0x08, 0x00, 0x08]);
+        let mut sut = configured(&mut vm);
+        let filters = Json::object(vec![(
+            "filters",
+            Json::from(vec![Json::from(MODIFIED_CODE)]),
+        )]);
+        request(&mut sut, &mut vm, "setExceptionBreakpoints", filters);
+
+        let stops = run(&mut sut, &mut vm, 10);
+        assert_eq!(stops.len(), 1);
+        assert_eq!(vm.registers.program_counter, 0x208);
+        assert_eq!(
+            stops[0].get("body").get("text").as_str(),
+            Some("Running code written by 0x204 <main+4>")
+        );
+
+        // continuing runs it, it isn't modified anymore after that
+        request(&mut sut, &mut vm, "continue", Json::Null);
+        assert!(run(&mut sut, &mut vm, 10).is_empty());
+    }
+
+    #[test]
+    fn talks_to_an_editor_over_tcp() {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        let mut sut = DapServer::bind(0).unwrap();
+        let port = sut.listener.local_addr().unwrap().port();
+
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let initialize = Json::parse(r#"{"seq": 1, "type": "request", "command": "initialize"}"#);
+        client
+            .write_all(frame(&initialize.unwrap()).as_bytes())
+            .unwrap();
+        client
+            .set_read_timeout(Some(std::time::Duration::from_millis(20)))
+            .unwrap();
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut messages: Vec<Json> = Vec::new();
+        for _ in 0..250 {
+            sut.poll(Some(&mut vm));
+
+            let mut chunk = [0u8; 1024];
+            if let Ok(read) = client.read(&mut chunk) {
+                buffer.extend_from_slice(&chunk[..read]);
+            }
+            while let Some(message) = next_message(&mut buffer) {
+                messages.push(message);
+            }
+
+            if messages.len() >= 2 {
+                break;
+            }
+        }
+
+        assert_eq!(messages[0].get("command").as_str(), Some("initialize"));
+        assert_eq!(messages[1].get("event").as_str(), Some("initialized"));
+        assert_eq!(sut.session.state, DapState::Paused);
+    }
+
+    #[test]
+    fn disassembles_with_labels_and_lines() {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        let mut sut = configured(&mut vm);
+
+        let arguments = Json::parse(
+            r#"{"memoryReference": "update", "instructionOffset": -1, "instructionCount": 3}"#,
+        );
+        let response = request(&mut sut, &mut vm, "disassemble", arguments.unwrap());
+        let instructions = response.get("body").get("instructions").as_array();
+
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(
+            instructions[0].get("instruction").as_str(),
+            Some("jump 0x204")
+        );
+        assert_eq!(instructions[1].get("address").as_str(), Some("0x0206"));
+        assert_eq!(
+            instructions[1].get("instructionBytes").as_str(),
+            Some("61 02")
+        );
+        assert_eq!(instructions[1].get("symbol").as_str(), Some("update"));
+        assert_eq!(instructions[1].get("line").as_u64(), Some(6));
+        assert_eq!(instructions[2].get("instruction").as_str(), Some("return"));
+        assert_eq!(instructions[2].get("line"), &Json::Null);
+
+        let arguments = Json::parse(
+            r#"{"memoryReference": "0x0000", "instructionOffset": -1, "instructionCount": 2}"#,
+        );
+        let response = request(&mut sut, &mut vm, "disassemble", arguments.unwrap());
+        let instructions = response.get("body").get("instructions").as_array();
+        assert_eq!(
+            instructions[0].get("presentationHint").as_str(),
+            Some("invalid")
+        );
+    }
+
+    #[test]
+    fn refuses_addresses_past_any_number() {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        let mut sut = configured(&mut vm);
+
+        for (command, arguments) in [
+            (
+                "readMemory",
+                r#"{"memoryReference": "0x0200", "offset": 1e19, "count": 4}"#,
+            ),
+            (
+                "writeMemory",
+                r#"{"memoryReference": "0x0200", "offset": 1e19, "data": "AA=="}"#,
+            ),
+            (
+                "disassemble",
+                r#"{"memoryReference": "0x0200", "offset": 1e19}"#,
+            ),
+            (
+                "disassemble",
+                r#"{"memoryReference": "0x0200", "instructionOffset": 1e19}"#,
+            ),
+        ] {
+            let response = request(&mut sut, &mut vm, command, Json::parse(arguments).unwrap());
+            assert_eq!(response.get("success").as_bool(), Some(false));
+            assert_eq!(response.get("message").as_str(), Some("Outside of memory"));
+        }
+
+        let read = Json::parse(r#"{"memoryReference": "0x0FFE", "count": 1e19}"#);
+        let response = request(&mut sut, &mut vm, "readMemory", read.unwrap());
+        assert_eq!(response.get("body").get("data").as_str(), Some("AAA="));
+
+        let breakpoints =
+            Json::parse(r#"{"breakpoints": [{"instructionReference": "0x0200", "offset": 1e19}]}"#);
+        let response = request(
+            &mut sut,
+            &mut vm,
+            "setInstructionBreakpoints",
+            breakpoints.unwrap(),
+        );
+        let breakpoints = response.get("body").get("breakpoints").as_array();
+        assert_eq!(breakpoints[0].get("verified").as_bool(), Some(false));
+
+        let disassemble = Json::parse(r#"{"memoryReference": "0x0200", "instructionCount": 1e19}"#);
+        let response = request(&mut sut, &mut vm, "disassemble", disassemble.unwrap());
+        let instructions = response.get("body").get("instructions").as_array();
+        assert_eq!(instructions.len(), MEMORY_SIZE / 2);
+    }
+
+    #[test]
+    fn refuses_edits_when_read_only() {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        let mut sut = configured(&mut vm);
+        sut.read_only = true;
+
+        let write = Json::parse(r#"{"memoryReference": "0x0202", "data": "AOA="}"#);
+        let response = request(&mut sut, &mut vm, "writeMemory", write.unwrap());
+        assert_eq!(response.get("success").as_bool(), Some(false));
+        assert_eq!(vm.memory.peek(0x203), PROGRAM[3]);
+
+        let assignment = Json::parse(r#"{"variablesReference": 1, "name": "VA", "value": "42"}"#);
+        let response = request(&mut sut, &mut vm, "setVariable", assignment.unwrap());
+        assert_eq!(
+            response.get("message").as_str(),
+            Some("Can't edit while recording a movie")
+        );
+        assert_eq!(vm.registers.v[VReg::new(0xA).unwrap()], 0);
+    }
+}
@@ -0,0 +1,86 @@
+use super::cpu::decode_opcode;
+use super::memory::MEMORY_SIZE;
+use super::registers::CrispsAteDecodedOpcodes;
+
+// What `decode_opcode` made of the instruction at each address. An entry is
+// only used while the two bytes fetched there are still the ones it was
+// decoded from, so whatever rewrites them (FX55, FX33, a debugger, a mapped
+// device) invalidates it without having to report the write.
+#[derive(Debug)]
+pub struct CrispAteDecodeCache {
+    entries: Vec<Option<(u16, CrispsAteDecodedOpcodes)>>, // by the opcode it was decoded from
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Default for CrispAteDecodeCache {
+    fn default() -> Self {
+        CrispAteDecodeCache::new()
+    }
+}
+
+impl CrispAteDecodeCache {
+    pub fn new() -> Self {
+        CrispAteDecodeCache {
+            entries: vec![None; MEMORY_SIZE],
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    // `opcode` was just fetched from `address`
+    pub fn decode(&mut self, address: usize, opcode: u16) -> CrispsAteDecodedOpcodes {
+        let entry = &mut self.entries[address % MEMORY_SIZE];
+
+        match entry {
+            Some((cached, decoded)) if *cached == opcode => {
+                self.hits += 1;
+                *decoded
+            }
+            _ => {
+                self.misses += 1;
+                let decoded = decode_opcode(opcode);
+                *entry = Some((opcode, decoded));
+                decoded
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod decode_cache_tests {
+    use super::*;
+    use crate::crisp_ate::cpu::CrispAte;
+    use crate::crisp_ate::registers::V0;
+
+    #[test]
+    fn decodes_like_decode_opcode() {
+        let mut sut = CrispAteDecodeCache::new();
+
+        for opcode in 0..=u16::MAX {
+            assert!(sut.decode(0x200, opcode) == decode_opcode(opcode));
+        }
+        assert_eq!(sut.hits, 0);
+    }
+
+    #[test]
+    fn sees_rewritten_instructions() {
+        // 0x200 i := 0x20A, 0x202 v0 := 0x60, 0x204 jump 0x20A, 0x206 save v0,
+        // 0x208 v1 := 0x07, 0x20A jump 0x206 (then v0 := 0x06), 0x20C jump 0x20C
+        let program = [
+            0xA2, 0x0A, 0x60, 0x60, 0x12, 0x0A, 0xF0, 0x55, 0x61, 0x07, 0x12, 0x06, 0x12, 0x0C,
+        ];
+        let mut vm = CrispAte::with_program(&program);
+
+        for _ in 0..10 {
+            vm.emulation_cyle().unwrap();
+        }
+        assert_eq!(vm.registers.v[V0], 0x06);
+        assert_eq!((vm.decode_cache.hits, vm.decode_cache.misses), (2, 8));
+
+        // and what debuggers load behind its back
+        vm.memory.load(0x20C, &[0x12, 0x00]);
+        vm.emulation_cyle().unwrap();
+        assert_eq!(vm.registers.program_counter, 0x200);
+    }
+}
@@ -0,0 +1,90 @@
+use super::symbols::CrispAteSymbols;
+use crate::utils::hex;
+
+// a jump or call target, by label when it has one
+fn target(address: u16, symbols: &CrispAteSymbols) -> String {
+    match symbols.label(address) {
+        Some(name) => name.to_owned(),
+        None => hex(address),
+    }
+}
+
+// One instruction in Octo's syntax, since that is what our ROMs are written
+// in. Skips read the other way around in Octo: 3XNN (skip if equal) is
+// `if vX != NN then`, the next instruction only runs when they differ.
+// Anything that isn't an instruction comes out as its two bytes.
+pub fn disassemble(opcode: u16, symbols: &CrispAteSymbols) -> String {
+    let x = (opcode >> 8) & 0xF;
+    let y = (opcode >> 4) & 0xF;
+    let n = opcode & 0xF;
+    let nn = opcode & 0xFF;
+    let nnn = opcode & 0xFFF;
+
+    let text = match (opcode >> 12, n) {
+        (0x0, _) if opcode == 0x00E0 => String::from("clear"),
+        (0x0, _) if opcode == 0x00EE => String::from("return"),
+        (0x1, _) => format!("jump {}", target(nnn, symbols)),
+        (0x2, _) => format!(":call {}", target(nnn, symbols)),
+        (0x3, _) => format!("if v{:x} != {} then", x, hex(nn)),
+        (0x4, _) => format!("if v{:x} == {} then", x, hex(nn)),
+        (0x5, 0x0) => format!("if v{:x} != v{:x} then", x, y),
+        (0x6, _) => format!("v{:x} := {}", x, hex(nn)),
+        (0x7, _) => format!("v{:x} += {}", x, hex(nn)),
+        (0x8, 0x0) => format!("v{:x} := v{:x}", x, y),
+        (0x8, 0x1) => format!("v{:x} |= v{:x}", x, y),
+        (0x8, 0x2) => format!("v{:x} &= v{:x}", x, y),
+        (0x8, 0x3) => format!("v{:x} ^= v{:x}", x, y),
+        (0x8, 0x4) => format!("v{:x} += v{:x}", x, y),
+        (0x8, 0x5) => format!("v{:x} -= v{:x}", x, y),
+        (0x8, 0x6) => format!("v{:x} >>= v{:x}", x, y),
+        (0x8, 0x7) => format!("v{:x} =- v{:x}", x, y),
+        (0x8, 0xE) => format!("v{:x} <<= v{:x}", x, y),
+        (0x9, 0x0) => format!("if v{:x} == v{:x} then", x, y),
+        (0xA, _) => format!("i := {}", target(nnn, symbols)),
+        (0xB, _) => format!("jump0 {}", target(nnn, symbols)),
+        (0xC, _) => format!("v{:x} := random {}", x, hex(nn)),
+        (0xD, _) => format!("sprite v{:x} v{:x} {}", x, y, n),
+        (0xE, _) if nn == 0x9E => format!("if v{:x} -key then", x),
+        (0xE, _) if nn == 0xA1 => format!("if v{:x} key then", x),
+        (0xF, _) => match nn {
+            0x07 => format!("v{:x} := delay", x),
+            0x0A => format!("v{:x} := key", x),
+            0x15 => format!("delay := v{:x}", x),
+            0x18 => format!("buzzer := v{:x}", x),
+            0x1E => format!("i += v{:x}", x),
+            0x29 => format!("i := hex v{:x}", x),
+            0x33 => format!("bcd v{:x}", x),
+            0x55 => format!("save v{:x}", x),
+            0x65 => format!("load v{:x}", x),
+            _ => String::new(),
+        },
+        _ => String::new(),
+    };
+
+    match text.is_empty() {
+        true => format!("{:#04x} {:#04x}", opcode >> 8, opcode & 0xFF),
+        false => text,
+    }
+}
+
+#[cfg(test)]
+mod disassembler_tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn writes_octo() {
+        let symbols = CrispAteSymbols::parse("label 0x206 draw\n", Path::new("")).unwrap();
+
+        assert_eq!(disassemble(0x00E0, &symbols), "clear");
+        assert_eq!(disassemble(0x2206, &symbols), ":call draw");
+        assert_eq!(disassemble(0x1208, &symbols), "jump 0x208");
+        assert_eq!(disassemble(0xA206, &symbols), "i := draw");
+        assert_eq!(disassemble(0x3A05, &symbols), "if va != 0x05 then");
+        assert_eq!(disassemble(0x8126, &symbols), "v1 >>= v2");
+        assert_eq!(disassemble(0xD015, &symbols), "sprite v0 v1 5");
+        assert_eq!(disassemble(0xF265, &symbols), "load v2");
+        assert_eq!(disassemble(0x0123, &symbols), "0x01 0x23");
+        assert_eq!(disassemble(0xE1FF, &symbols), "0xe1 0xff");
+    }
+}
@@ -1,38 +1,631 @@
+use std::path::Path;
+
 use raylib::prelude::*;
 
+use super::cpu::{CrispAte, REGISTER_NAMES};
+use super::fault::CrispAteFault;
+use super::hud::CrispAteHud;
+use super::library::{is_rom, CrispAteLauncher, LauncherAction};
+use super::memory::MEMORY_SIZE;
+use super::memory_view::{
+    CrispAteMemoryView, MemoryRegion, MemoryViewAction, MemoryViewFocus, BYTES_PER_ROW,
+    WRITE_HIGHLIGHT_FRAMES,
+};
+use super::palette::CrispAtePalette;
+use super::symbols::CrispAteSymbols;
+use crate::utils::hex;
+
 const ROWS: i32 = 32;
 const COLUMNS: i32 = 64;
 
-const SCALING: i32 = 10;
+pub const SCALING: i32 = 10;
+
+// the HUD gets a bar above and below the screen, so it never covers it
+const HUD_BAR_HEIGHT: i32 = 24;
+const HUD_FONT_SIZE: i32 = 16;
+
+const LAUNCHER_FONT_SIZE: i32 = 20;
+const LAUNCHER_LINE_HEIGHT: i32 = 28;
+
+const MEMORY_FONT_SIZE: i32 = 10;
+const MEMORY_LINE_HEIGHT: i32 = 12;
 
 pub fn create_display() -> (RaylibHandle, RaylibThread) {
-    raylib::init()
+    let (mut rl, thread) = raylib::init()
         .size((COLUMNS * SCALING).into(), (ROWS * SCALING).into())
         .title("CrispAte")
-        .build()
+        .resizable()
+        .build();
+
+    rl.set_window_min_size(COLUMNS, ROWS);
+
+    (rl, thread)
+}
+
+// where the screen goes inside the window: (x, y) of its top left corner and
+// the size of one CHIP-8 pixel
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub pixel_size: f32,
+}
+
+// The largest scale at which a `columns` x `rows` screen fits the window,
+// centered between black bars. Integer scales keep every pixel the same
+// size, `smooth` fills the window as much as possible instead.
+pub fn fit_screen(
+    window_width: i32,
+    window_height: i32,
+    columns: usize,
+    rows: usize,
+    smooth: bool,
+) -> Viewport {
+    let scale = (window_width as f32 / columns as f32).min(window_height as f32 / rows as f32);
+    let pixel_size = match smooth {
+        true => scale,
+        false => scale.floor().max(1.0),
+    };
+
+    let x = (window_width as f32 - pixel_size * columns as f32) / 2.0;
+    let y = (window_height as f32 - pixel_size * rows as f32) / 2.0;
+
+    match smooth {
+        true => Viewport { x, y, pixel_size },
+        false => Viewport {
+            x: x.floor(),
+            y: y.floor(),
+            pixel_size,
+        },
+    }
+}
+
+// F11
+pub fn update_window(rl: &mut RaylibHandle) {
+    if rl.is_key_pressed(KeyboardKey::KEY_F11) {
+        rl.toggle_fullscreen();
+    }
+}
+
+// CHIP-8 keypad      keyboard
+//  1 2 3 C            1 2 3 4
+//  4 5 6 D            Q W E R
+//  7 8 9 E            A S D F
+//  A 0 B F            Z X C V
+const KEYPAD: [KeyboardKey; 16] = [
+    KeyboardKey::KEY_X,
+    KeyboardKey::KEY_ONE,
+    KeyboardKey::KEY_TWO,
+    KeyboardKey::KEY_THREE,
+    KeyboardKey::KEY_Q,
+    KeyboardKey::KEY_W,
+    KeyboardKey::KEY_E,
+    KeyboardKey::KEY_A,
+    KeyboardKey::KEY_S,
+    KeyboardKey::KEY_D,
+    KeyboardKey::KEY_Z,
+    KeyboardKey::KEY_C,
+    KeyboardKey::KEY_FOUR,
+    KeyboardKey::KEY_R,
+    KeyboardKey::KEY_F,
+    KeyboardKey::KEY_V,
+];
+
+pub fn read_keypad(rl: &RaylibHandle) -> [bool; 16] {
+    let mut keypad = [false; 16];
+
+    for (key, pressed) in keypad.iter_mut().enumerate() {
+        *pressed = rl.is_key_down(KEYPAD[key]);
+    }
+
+    keypad
+}
+
+pub fn screenshot_requested(rl: &RaylibHandle) -> bool {
+    rl.is_key_pressed(KeyboardKey::KEY_F12)
+}
+
+pub fn recording_toggled(rl: &RaylibHandle) -> bool {
+    rl.is_key_pressed(KeyboardKey::KEY_F9)
+}
+
+pub fn save_state_requested(rl: &RaylibHandle) -> bool {
+    rl.is_key_pressed(KeyboardKey::KEY_F5)
+}
+
+pub fn state_slot_switched(rl: &RaylibHandle) -> bool {
+    rl.is_key_pressed(KeyboardKey::KEY_F6)
+}
+
+pub fn load_state_requested(rl: &RaylibHandle) -> bool {
+    rl.is_key_pressed(KeyboardKey::KEY_F8)
+}
+
+pub fn palette_switched(rl: &RaylibHandle) -> bool {
+    rl.is_key_pressed(KeyboardKey::KEY_F2)
+}
+
+pub fn hud_toggled(rl: &RaylibHandle) -> bool {
+    rl.is_key_pressed(KeyboardKey::KEY_F3)
+}
+
+pub fn pause_toggled(rl: &RaylibHandle) -> bool {
+    rl.is_key_pressed(KeyboardKey::KEY_P)
+}
+
+// held down, not toggled
+pub fn turbo_held(rl: &RaylibHandle) -> bool {
+    rl.is_key_down(KeyboardKey::KEY_TAB)
+}
+
+pub fn launcher_requested(rl: &RaylibHandle) -> bool {
+    rl.is_key_pressed(KeyboardKey::KEY_F1)
+}
+
+pub fn launcher_input(rl: &RaylibHandle) -> Option<LauncherAction> {
+    if rl.is_key_pressed(KeyboardKey::KEY_UP) {
+        Some(LauncherAction::Up)
+    } else if rl.is_key_pressed(KeyboardKey::KEY_DOWN) {
+        Some(LauncherAction::Down)
+    } else if rl.is_key_pressed(KeyboardKey::KEY_TAB) {
+        Some(LauncherAction::SwitchList)
+    } else if rl.is_key_pressed(KeyboardKey::KEY_ENTER) {
+        Some(LauncherAction::Open)
+    } else {
+        None
+    }
+}
+
+pub fn memory_view_toggled(rl: &RaylibHandle) -> bool {
+    rl.is_key_pressed(KeyboardKey::KEY_F4)
+}
+
+// navigation repeats while held; digits come from the typed characters so
+// the keyboard layout doesn't matter
+pub fn memory_view_input(rl: &mut RaylibHandle) -> Vec<MemoryViewAction> {
+    let keys = [
+        (KeyboardKey::KEY_UP, MemoryViewAction::Up),
+        (KeyboardKey::KEY_DOWN, MemoryViewAction::Down),
+        (KeyboardKey::KEY_LEFT, MemoryViewAction::Left),
+        (KeyboardKey::KEY_RIGHT, MemoryViewAction::Right),
+        (KeyboardKey::KEY_PAGE_UP, MemoryViewAction::PageUp),
+        (KeyboardKey::KEY_PAGE_DOWN, MemoryViewAction::PageDown),
+        (KeyboardKey::KEY_ENTER, MemoryViewAction::SwitchFocus),
+    ];
+    let mut actions: Vec<MemoryViewAction> = keys
+        .iter()
+        .filter(|(key, _)| rl.is_key_pressed(*key) || rl.is_key_pressed_repeat(*key))
+        .map(|(_, action)| *action)
+        .collect();
+
+    while let Some(c) = rl.get_char_pressed() {
+        if let Some(digit) = c.to_digit(16) {
+            actions.push(MemoryViewAction::Digit(digit as u8));
+        }
+    }
+
+    actions
+}
+
+// the first ROM among the files dropped on the window since the last frame
+pub fn dropped_rom(rl: &RaylibHandle) -> Option<String> {
+    if !rl.is_file_dropped() {
+        return None;
+    }
+
+    rl.load_dropped_files()
+        .paths()
+        .into_iter()
+        .find(|path| is_rom(Path::new(path)))
+        .map(str::to_owned)
+}
+
+pub fn load_thumbnail(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    path: &Path,
+) -> Option<Texture2D> {
+    rl.load_texture(thread, path.to_str()?).ok()
+}
+
+// The framebuffer lives in a texture one texel per CHIP-8 pixel, so a frame
+// is drawn as a single scaled quad. It is only uploaded again when the
+// picture changes.
+pub struct ScreenTexture {
+    texture: RenderTexture2D,
+    columns: usize,
+    rows: usize,
+    pixels: Vec<u8>, // RGBA, staged for the upload
 }
 
-pub fn draw_frame(screen_state: [bool; 64 * 32], mut d: RaylibDrawHandle) {
+impl ScreenTexture {
+    pub fn new(
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        columns: usize,
+        rows: usize,
+    ) -> Result<Self, String> {
+        let texture = rl
+            .load_render_texture(thread, columns as u32, rows as u32)
+            .map_err(|error| error.to_string())?;
+
+        Ok(ScreenTexture {
+            texture,
+            columns,
+            rows,
+            pixels: vec![0; columns * rows * 4],
+        })
+    }
+
+    // `levels` come from the display filter, 0 (unlit) to 255 (lit) per
+    // pixel, `columns` pixels to a row (64 in lores, 128 in hires)
+    pub fn upload(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        levels: &[u8],
+        columns: usize,
+        palette: &CrispAtePalette,
+    ) -> Result<(), String> {
+        let rows = levels.len() / columns;
+        if (columns, rows) != (self.columns, self.rows) {
+            *self = ScreenTexture::new(rl, thread, columns, rows)?;
+        }
+
+        for (rgba, level) in self.pixels.chunks_exact_mut(4).zip(levels.iter()) {
+            rgba[..3].copy_from_slice(&palette.blend(*level));
+            rgba[3] = 255;
+        }
+
+        self.texture
+            .update_texture(&self.pixels)
+            .map_err(|error| error.to_string())
+    }
+}
+
+pub fn draw_frame(
+    screen: &ScreenTexture,
+    smooth: bool,
+    hud: &CrispAteHud,
+    d: &mut RaylibDrawHandle,
+) {
     d.clear_background(Color::BLACK);
 
-    let mut row = 0;
-    let mut col = 0;
+    let bar = match hud.visible {
+        true => HUD_BAR_HEIGHT,
+        false => 0,
+    };
+    let mut viewport = fit_screen(
+        d.get_screen_width(),
+        (d.get_screen_height() - 2 * bar).max(0),
+        screen.columns,
+        screen.rows,
+        smooth,
+    );
+    viewport.y += bar as f32;
+    let source = Rectangle::new(0.0, 0.0, screen.columns as f32, screen.rows as f32);
+    let destination = Rectangle::new(
+        viewport.x,
+        viewport.y,
+        screen.columns as f32 * viewport.pixel_size,
+        screen.rows as f32 * viewport.pixel_size,
+    );
 
-    for pixel in screen_state {
-        let target_col = col * SCALING;
-        let target_row = row * SCALING;
-        let color = match pixel {
-            true => Color::WHITE,
-            false => Color::DARKGRAY,
-        };
+    d.draw_texture_pro(
+        &screen.texture,
+        source,
+        destination,
+        Vector2::new(0.0, 0.0),
+        0.0,
+        Color::WHITE,
+    );
+
+    if hud.visible {
+        draw_hud(hud, d);
+    }
+}
+
+// ROM title and status in the top bar, the newest message in the bottom one
+fn draw_hud(hud: &CrispAteHud, d: &mut RaylibDrawHandle) {
+    let (width, height) = (d.get_screen_width(), d.get_screen_height());
+    let margin = (HUD_BAR_HEIGHT - HUD_FONT_SIZE) / 2;
+
+    d.draw_text(&hud.title, margin, margin, HUD_FONT_SIZE, Color::LIGHTGRAY);
+
+    let status = hud.status();
+    let status_width = d.measure_text(&status, HUD_FONT_SIZE);
+    d.draw_text(
+        &status,
+        width - status_width - margin,
+        margin,
+        HUD_FONT_SIZE,
+        Color::LIGHTGRAY,
+    );
+
+    if let Some(toast) = hud.toasts().first() {
+        d.draw_text(
+            toast,
+            margin,
+            height - HUD_BAR_HEIGHT + margin,
+            HUD_FONT_SIZE,
+            Color::WHITE,
+        );
+    }
+}
+
+fn memory_color(view: &CrispAteMemoryView, vm: &CrispAte, address: usize) -> Color {
+    // code the ROM wrote over since it last ran
+    let rewritten = vm.code_map.is_code(address) && vm.code_map.is_written(address);
+
+    let color = match view.region(address) {
+        _ if rewritten => Color::ORANGE,
+        MemoryRegion::Font => Color::SKYBLUE,
+        MemoryRegion::Interpreter => Color::GRAY,
+        MemoryRegion::Program => Color::GREEN,
+        MemoryRegion::Free => Color::LIGHTGRAY,
+    };
+
+    // recent writes start out yellow and fade back
+    match view.written(address) {
+        0 => color,
+        frames => Color::YELLOW.fade(0.4 + 0.6 * frames as f32 / WRITE_HIGHLIGHT_FRAMES as f32),
+    }
+}
+
+// A hex dump of all of memory over the screen, with the registers below it.
+// PC is boxed in red (both bytes of the instruction), I in yellow, and code
+// the ROM has written over is orange until it runs again.
+pub fn draw_memory_view(view: &mut CrispAteMemoryView, vm: &CrispAte, d: &mut RaylibDrawHandle) {
+    let (width, height) = (d.get_screen_width(), d.get_screen_height());
+    let margin = MEMORY_LINE_HEIGHT / 2;
+    let address_width = d.measure_text("0x000  ", MEMORY_FONT_SIZE);
+    let cell = d.measure_text("WW ", MEMORY_FONT_SIZE);
+    let register_cell = d.measure_text("VW 0xWWWW  ", MEMORY_FONT_SIZE);
+    let registers_per_line = ((width - 2 * margin) / register_cell).max(1) as usize;
+    let register_lines = REGISTER_NAMES.len().div_ceil(registers_per_line) as i32;
+
+    d.draw_rectangle(0, 0, width, height, Color::BLACK.fade(0.85));
+    d.draw_text(
+        "MEMORY  F4 hide  Enter bytes/registers  0-F edit while paused",
+        margin,
+        margin,
+        MEMORY_FONT_SIZE,
+        Color::WHITE,
+    );
+
+    let first_row = margin + MEMORY_LINE_HEIGHT * 2;
+    let rows = (height - first_row - margin - MEMORY_LINE_HEIGHT * (register_lines + 1))
+        / MEMORY_LINE_HEIGHT;
+    view.scroll(rows.max(1) as usize);
+
+    let pc = vm.registers.program_counter as usize;
+    let i = vm.registers.address as usize;
+    let memory_rows = MEMORY_SIZE / BYTES_PER_ROW;
+
+    for (line, row) in (view.top..memory_rows).take(view.rows).enumerate() {
+        let y = first_row + line as i32 * MEMORY_LINE_HEIGHT;
+        d.draw_text(
+            &format!("0x{:03X}", row * BYTES_PER_ROW),
+            margin,
+            y,
+            MEMORY_FONT_SIZE,
+            Color::DARKGRAY,
+        );
+
+        for column in 0..BYTES_PER_ROW {
+            let address = row * BYTES_PER_ROW + column;
+            let x = margin + address_width + column as i32 * cell;
+            let box_width = cell - margin / 2;
 
-        d.draw_rectangle(target_col, target_row, SCALING, SCALING, color);
+            if address == pc || address == pc + 1 {
+                d.draw_rectangle(x - 2, y - 1, box_width, MEMORY_LINE_HEIGHT, Color::MAROON);
+            }
+            if address == i {
+                d.draw_rectangle_lines(x - 2, y - 1, box_width, MEMORY_LINE_HEIGHT, Color::YELLOW);
+            }
+            if address == view.cursor && view.focus == MemoryViewFocus::Memory {
+                d.draw_rectangle_lines(x - 2, y - 1, box_width, MEMORY_LINE_HEIGHT, Color::WHITE);
+            }
 
-        col += 1;
+            let text = match view.pending {
+                Some(high) if address == view.cursor => format!("{:X}_", high),
+                _ => format!("{:02X}", vm.memory.peek(address)),
+            };
+            d.draw_text(
+                &text,
+                x,
+                y,
+                MEMORY_FONT_SIZE,
+                memory_color(view, vm, address),
+            );
+        }
+    }
 
-        if col == COLUMNS {
-            col = 0;
-            row += 1;
+    let registers_y = height - margin - MEMORY_LINE_HEIGHT * register_lines;
+    for (index, name) in REGISTER_NAMES.iter().enumerate() {
+        let x = margin + (index % registers_per_line) as i32 * register_cell;
+        let y = registers_y + (index / registers_per_line) as i32 * MEMORY_LINE_HEIGHT;
+        let value = vm.register(name).unwrap_or(0);
+        let selected = index == view.register && view.focus == MemoryViewFocus::Registers;
+
+        d.draw_text(
+            &format!("{} 0x{:02X}", name, value),
+            x,
+            y,
+            MEMORY_FONT_SIZE,
+            match selected {
+                true => Color::WHITE,
+                false => Color::LIGHTGRAY,
+            },
+        );
+        if selected {
+            d.draw_rectangle_lines(
+                x - 2,
+                y - 1,
+                register_cell - margin,
+                MEMORY_LINE_HEIGHT,
+                Color::WHITE,
+            );
         }
     }
 }
+
+// the ROM list on the left, the selected ROM's last screenshot on the right
+pub fn draw_launcher(
+    launcher: &CrispAteLauncher,
+    rom_directory: &str,
+    thumbnail: Option<&Texture2D>,
+    mut d: RaylibDrawHandle,
+) {
+    d.clear_background(Color::BLACK);
+
+    let (width, height) = (d.get_screen_width(), d.get_screen_height());
+    let heading = match launcher.showing_recent {
+        true => String::from("Recently played"),
+        false => format!("ROMs in {}", rom_directory),
+    };
+    d.draw_text(&heading, SCALING, SCALING, LAUNCHER_FONT_SIZE, Color::WHITE);
+    d.draw_text(
+        "Up/Down select  Enter play  Tab recent/all  or drop a ROM on the window",
+        SCALING,
+        height - SCALING - HUD_FONT_SIZE,
+        HUD_FONT_SIZE,
+        Color::GRAY,
+    );
+
+    let entries = launcher.entries();
+    if entries.is_empty() {
+        d.draw_text(
+            "No ROMs here",
+            SCALING,
+            SCALING + LAUNCHER_LINE_HEIGHT * 2,
+            LAUNCHER_FONT_SIZE,
+            Color::GRAY,
+        );
+    }
+
+    // scrolled so the selected ROM is always on screen
+    let top = SCALING + LAUNCHER_LINE_HEIGHT * 2;
+    let visible = ((height - top - SCALING * 2 - HUD_FONT_SIZE) / LAUNCHER_LINE_HEIGHT).max(1);
+    let first = (launcher.selected as i32 - visible + 1).max(0) as usize;
+
+    for (line, entry) in entries
+        .iter()
+        .skip(first)
+        .take(visible as usize)
+        .enumerate()
+    {
+        let y = top + line as i32 * LAUNCHER_LINE_HEIGHT;
+        let color = match first + line == launcher.selected {
+            true => {
+                d.draw_rectangle(0, y - 4, width / 2, LAUNCHER_LINE_HEIGHT, Color::DARKGRAY);
+                Color::YELLOW
+            }
+            false => Color::LIGHTGRAY,
+        };
+
+        d.draw_text(&entry.title, SCALING, y, LAUNCHER_FONT_SIZE, color);
+    }
+
+    if let Some(thumbnail) = thumbnail {
+        let area = (
+            width / 2 - SCALING * 2,
+            height - top - SCALING * 2 - HUD_FONT_SIZE,
+        );
+        let scale = (area.0 as f32 / thumbnail.width() as f32)
+            .min(area.1 as f32 / thumbnail.height() as f32)
+            .max(0.0);
+        let source = Rectangle::new(
+            0.0,
+            0.0,
+            thumbnail.width() as f32,
+            thumbnail.height() as f32,
+        );
+        let destination = Rectangle::new(
+            (width / 2 + SCALING) as f32,
+            top as f32,
+            thumbnail.width() as f32 * scale,
+            thumbnail.height() as f32 * scale,
+        );
+
+        d.draw_texture_pro(
+            thumbnail,
+            source,
+            destination,
+            Vector2::new(0.0, 0.0),
+            0.0,
+            Color::WHITE,
+        );
+    }
+}
+
+pub fn draw_fault(fault: CrispAteFault, symbols: &CrispAteSymbols, mut d: RaylibDrawHandle) {
+    d.clear_background(Color::MAROON);
+
+    d.draw_text("MACHINE FAULT", SCALING, SCALING, 4 * SCALING, Color::WHITE);
+    d.draw_text(
+        &fault.fault.describe(),
+        SCALING,
+        6 * SCALING,
+        2 * SCALING,
+        Color::WHITE,
+    );
+    d.draw_text(
+        &format!("Address: {}", symbols.location(fault.address)),
+        SCALING,
+        9 * SCALING,
+        2 * SCALING,
+        Color::WHITE,
+    );
+    d.draw_text(
+        &format!("Opcode: {}", hex(fault.opcode)),
+        SCALING,
+        12 * SCALING,
+        2 * SCALING,
+        Color::WHITE,
+    );
+}
+
+#[cfg(test)]
+mod display_tests {
+    use super::*;
+
+    #[test]
+    fn fits_the_largest_integer_scale_with_letterboxing() {
+        assert_eq!(
+            fit_screen(640, 320, 64, 32, false),
+            Viewport {
+                x: 0.0,
+                y: 0.0,
+                pixel_size: 10.0
+            }
+        );
+        assert_eq!(
+            fit_screen(1000, 400, 64, 32, false),
+            Viewport {
+                x: 116.0,
+                y: 8.0,
+                pixel_size: 12.0
+            }
+        );
+    }
+
+    #[test]
+    fn hires_screens_get_half_the_pixel_size() {
+        assert_eq!(fit_screen(1000, 400, 128, 64, false).pixel_size, 6.0);
+    }
+
+    #[test]
+    fn smooth_scaling_fills_one_side() {
+        let viewport = fit_screen(1000, 400, 64, 32, true);
+
+        assert_eq!(viewport.pixel_size, 12.5);
+        assert_eq!(viewport.x, 100.0);
+        assert_eq!(viewport.y, 0.0);
+    }
+
+    #[test]
+    fn never_scales_below_one_pixel() {
+        assert_eq!(fit_screen(10, 10, 64, 32, false).pixel_size, 1.0);
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+
+use super::symbols::CrispAteSymbols;
+use crate::utils::hex;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Fault {
+    StackOverflow,            // 2NNN with all 16 stack slots in use
+    StackUnderflow,           // 00EE with an empty stack
+    InvalidOpcode,            // opcode did not decode to anything
+    MemoryOutOfBounds(usize), // (address) read or write past 0xFFF
+    PcOutOfBounds(usize),     // (address) fetch past 0xFFF
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FaultPolicy {
+    Halt,   // stop the machine and report the fault
+    Wrap,   // wrap stack pointer and addresses around, skip invalid opcodes
+    Ignore, // drop the faulting access (reads give 0), skip invalid opcodes
+}
+
+impl FaultPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "halt" => Some(FaultPolicy::Halt),
+            "wrap" => Some(FaultPolicy::Wrap),
+            "ignore" => Some(FaultPolicy::Ignore),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FaultPolicy::Halt => "halt",
+            FaultPolicy::Wrap => "wrap",
+            FaultPolicy::Ignore => "ignore",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CrispAteFault {
+    pub fault: Fault,
+    pub address: u16,
+    pub opcode: u16,
+}
+
+impl Fault {
+    pub fn describe(&self) -> String {
+        match self {
+            Fault::StackOverflow => String::from("Stack overflow"),
+            Fault::StackUnderflow => String::from("Stack underflow"),
+            Fault::InvalidOpcode => String::from("Invalid opcode"),
+            Fault::MemoryOutOfBounds(target) => {
+                format!("Memory access out of bounds ({:#06x})", target)
+            }
+            Fault::PcOutOfBounds(target) => {
+                format!("Program counter out of bounds ({:#06x})", target)
+            }
+        }
+    }
+}
+
+impl CrispAteFault {
+    // like the Display output, with the label and source line of the address
+    pub fn explain(&self, symbols: &CrispAteSymbols) -> String {
+        format!(
+            "{} at {} (opcode: {})",
+            self.fault.describe(),
+            symbols.location(self.address),
+            hex(self.opcode)
+        )
+    }
+}
+
+impl fmt::Display for CrispAteFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {} (opcode: {})",
+            self.fault.describe(),
+            hex(self.address),
+            hex(self.opcode)
+        )
+    }
+}
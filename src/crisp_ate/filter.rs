@@ -0,0 +1,272 @@
+use super::cpu::CrispAte;
+use super::memory::MEMORY_SIZE;
+
+const PIXELS: usize = 64 * 32;
+
+// in vblank mode a program that never waits on the timer or the keypad
+// still gets its screen shown this many rendered frames
+const VBLANK_FALLBACK_FRAMES: usize = 30;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FilterMode {
+    Raw,           // show the framebuffer as it is after every cycle
+    Phosphor(f32), // (decay) unlit pixels fade out, keeping this much per rendered frame
+    Vblank,        // show the framebuffer only once the program is done drawing
+}
+
+impl FilterMode {
+    pub fn from_name(name: &str, decay: f32) -> Option<Self> {
+        match name {
+            "raw" => Some(FilterMode::Raw),
+            "phosphor" => Some(FilterMode::Phosphor(decay)),
+            "vblank" => Some(FilterMode::Vblank),
+            _ => None,
+        }
+    }
+}
+
+// Games erase sprites by XOR-ing them and draw them again a few instructions
+// later, so the raw framebuffer spends frames with things missing. The filter
+// keeps its own copy of what should be shown and leaves `CrispAte::screen`
+// alone.
+pub struct CrispAteDisplayFilter {
+    pub mode: FilterMode,
+    levels: [f32; PIXELS],
+    next_opcode: Option<u16>,
+    since_latch: usize, // rendered frames
+}
+
+impl CrispAteDisplayFilter {
+    pub fn new(mode: FilterMode) -> Self {
+        CrispAteDisplayFilter {
+            mode,
+            levels: [0.0; PIXELS],
+            next_opcode: None,
+            since_latch: 0,
+        }
+    }
+
+    // call after every `emulation_cyle`, before clearing `draw_flag`; tells
+    // whether `levels` changed, so the frontend can skip redrawing
+    pub fn observe(&mut self, vm: &CrispAte) -> bool {
+        let executed = self.next_opcode;
+        let pc = vm.registers.program_counter as usize;
+        self.next_opcode = match pc + 1 < MEMORY_SIZE {
+            true => Some(vm.memory.peek_word(pc)),
+            false => None,
+        };
+
+        match self.mode {
+            FilterMode::Raw => vm.registers.draw_flag && self.latch(&vm.screen),
+            // lit pixels light up right away, however briefly they stay lit
+            FilterMode::Phosphor(_) if vm.registers.draw_flag => {
+                let mut changed = false;
+
+                for (level, pixel) in self.levels.iter_mut().zip(vm.screen.iter()) {
+                    if *pixel {
+                        changed |= *level != 1.0;
+                        *level = 1.0;
+                    }
+                }
+
+                changed
+            }
+            FilterMode::Phosphor(_) => false,
+            FilterMode::Vblank => executed.is_some_and(is_frame_end) && self.latch(&vm.screen),
+        }
+    }
+
+    // call once per rendered frame, however many cycles ran in it; tells
+    // whether `levels` changed like `observe`
+    pub fn fade(&mut self, vm: &CrispAte) -> bool {
+        match self.mode {
+            FilterMode::Raw => false,
+            FilterMode::Phosphor(decay) => {
+                let mut changed = false;
+
+                for (level, pixel) in self.levels.iter_mut().zip(vm.screen.iter()) {
+                    let faded = match pixel {
+                        true => 1.0,
+                        // snap to black once the fade can't be seen anymore
+                        false if *level * decay < 1.0 / 255.0 => 0.0,
+                        false => *level * decay,
+                    };
+                    changed |= faded != *level;
+                    *level = faded;
+                }
+
+                changed
+            }
+            FilterMode::Vblank => {
+                self.since_latch += 1;
+
+                match self.since_latch >= VBLANK_FALLBACK_FRAMES {
+                    true => self.latch(&vm.screen),
+                    false => false,
+                }
+            }
+        }
+    }
+
+    // shows the framebuffer as it is right away, for when the machine jumped
+    // somewhere else entirely (a loaded state)
+    pub fn show(&mut self, vm: &CrispAte) -> bool {
+        self.latch(&vm.screen)
+    }
+
+    // how lit every pixel should look, 0 (off) to 255 (fully on)
+    pub fn levels(&self) -> [u8; PIXELS] {
+        let mut levels = [0u8; PIXELS];
+
+        for (out, level) in levels.iter_mut().zip(self.levels.iter()) {
+            *out = (level * 255.0).round() as u8;
+        }
+
+        levels
+    }
+
+    // for outputs that can only show a pixel as on or off
+    pub fn lit(&self) -> [bool; PIXELS] {
+        let mut lit = [false; PIXELS];
+
+        for (out, level) in lit.iter_mut().zip(self.levels.iter()) {
+            *out = *level >= 0.5;
+        }
+
+        lit
+    }
+
+    // true if anything differs from what was shown before
+    fn latch(&mut self, screen: &[bool; PIXELS]) -> bool {
+        let mut changed = false;
+
+        for (level, pixel) in self.levels.iter_mut().zip(screen.iter()) {
+            let latched = *pixel as u8 as f32;
+            changed |= latched != *level;
+            *level = latched;
+        }
+
+        self.since_latch = 0;
+        changed
+    }
+}
+
+// FX07 (read the delay timer), FX15 (set it) and FX0A (wait for a key) are
+// what programs do once a frame is drawn and they wait for the next one
+fn is_frame_end(opcode: u16) -> bool {
+    matches!(opcode & 0xF0FF, 0xF007 | 0xF015 | 0xF00A)
+}
+
+#[cfg(test)]
+mod filter_tests {
+    use super::*;
+
+    #[test]
+    fn raw_mode_mirrors_the_framebuffer() {
+        let mut vm = CrispAte::with_program(&[0x12, 0x00]);
+        let mut sut = CrispAteDisplayFilter::new(FilterMode::Raw);
+
+        vm.screen[5] = true;
+        assert!(!sut.observe(&vm));
+        assert_eq!(sut.levels()[5], 0);
+
+        vm.registers.draw_flag = true;
+        assert!(sut.observe(&vm));
+        assert_eq!(sut.levels()[5], 255);
+        assert_eq!(sut.lit(), vm.screen);
+
+        assert!(!sut.observe(&vm));
+        assert!(!sut.fade(&vm));
+    }
+
+    #[test]
+    fn phosphor_mode_settles_once_faded() {
+        let mut vm = CrispAte::with_program(&[0x12, 0x00]);
+        let mut sut = CrispAteDisplayFilter::new(FilterMode::Phosphor(0.5));
+
+        vm.screen[5] = true;
+        vm.registers.draw_flag = true;
+        assert!(sut.observe(&vm));
+        vm.screen[5] = false;
+
+        let mut frames = 0;
+        while sut.fade(&vm) {
+            frames += 1;
+        }
+
+        assert_eq!(frames, 8);
+        assert_eq!(sut.levels()[5], 0);
+    }
+
+    #[test]
+    fn phosphor_mode_fades_erased_pixels_by_rendered_frame() {
+        let mut vm = CrispAte::with_program(&[0x12, 0x00]);
+        let mut sut = CrispAteDisplayFilter::new(FilterMode::Phosphor(0.5));
+
+        vm.screen[5] = true;
+        vm.registers.draw_flag = true;
+        sut.observe(&vm);
+        vm.screen[5] = false;
+        // however many cycles run in between (turbo)
+        for _ in 0..8 {
+            sut.observe(&vm);
+        }
+        assert_eq!(sut.levels()[5], 255);
+
+        sut.fade(&vm);
+
+        assert_eq!(sut.levels()[5], 128);
+        assert!(sut.lit()[5]);
+
+        sut.fade(&vm);
+
+        assert_eq!(sut.levels()[5], 64);
+        assert!(!sut.lit()[5]);
+        assert!(!vm.screen[5]);
+    }
+
+    #[test]
+    fn vblank_mode_waits_for_the_delay_timer() {
+        // 00E0, 00E0, FX15, jump back
+        let mut vm = CrispAte::with_program(&[0x00, 0xE0, 0x00, 0xE0, 0xF0, 0x15, 0x12, 0x00]);
+        let mut sut = CrispAteDisplayFilter::new(FilterMode::Vblank);
+        sut.observe(&vm);
+
+        vm.emulation_cyle().unwrap();
+        vm.screen[5] = true;
+        sut.observe(&vm);
+
+        assert!(!sut.lit()[5]);
+
+        vm.emulation_cyle().unwrap();
+        vm.screen[7] = true;
+        sut.observe(&vm);
+
+        assert!(!sut.lit()[7]);
+
+        vm.emulation_cyle().unwrap();
+        assert!(sut.observe(&vm));
+
+        assert!(sut.lit()[7]);
+    }
+
+    #[test]
+    fn vblank_mode_falls_back_to_a_fixed_frame_rate() {
+        let mut vm = CrispAte::with_program(&[0x12, 0x00]);
+        let mut sut = CrispAteDisplayFilter::new(FilterMode::Vblank);
+        vm.screen[5] = true;
+
+        for _ in 0..VBLANK_FALLBACK_FRAMES * 8 {
+            sut.observe(&vm);
+        }
+        for _ in 0..VBLANK_FALLBACK_FRAMES - 1 {
+            sut.fade(&vm);
+        }
+
+        assert!(!sut.lit()[5]);
+
+        sut.fade(&vm);
+
+        assert!(sut.lit()[5]);
+    }
+}
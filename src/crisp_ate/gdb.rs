@@ -0,0 +1,732 @@
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use super::cpu::CrispAte;
+use super::fault::Fault;
+use super::memory::MEMORY_SIZE;
+use super::registers::VReg;
+use super::runtime::STACK_SIZE;
+use crate::utils::{from_hex, to_hex};
+
+// signals in stop replies, as gdb numbers them
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// V0-VF, I, PC, SP, DT, ST
+const REGISTER_COUNT: usize = 21;
+
+// gdb has no CHIP-8 architecture, so the registers are described to it (and
+// to any other client that asks) with a target description
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.crisp-ate.chip8">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GdbState {
+    Running,  // the frontend runs the machine as usual
+    Halted,   // stopped, waiting for the debugger
+    Stepping, // run one instruction, then stop again
+}
+
+// The debugger's side of the machine: what it asked for and the replies it
+// gets, without any networking. Packets come in and replies go out as bare
+// strings, `GdbServer` deals with framing them.
+pub struct GdbStub {
+    pub state: GdbState,
+    pub breakpoints: BTreeSet<u16>,
+    pub read_only: bool, // a movie is recording, edits would desync it
+    resuming: bool,      // don't stop at the breakpoint being continued from
+}
+
+impl Default for GdbStub {
+    fn default() -> Self {
+        GdbStub::new()
+    }
+}
+
+impl GdbStub {
+    pub fn new() -> Self {
+        GdbStub {
+            state: GdbState::Running,
+            breakpoints: BTreeSet::new(),
+            read_only: false,
+            resuming: false,
+        }
+    }
+
+    // debuggers expect the target to be stopped when they connect
+    pub fn attach(&mut self) {
+        self.state = GdbState::Halted;
+    }
+
+    pub fn detach(&mut self) {
+        self.state = GdbState::Running;
+        self.breakpoints.clear();
+    }
+
+    // how many of the `wanted` cycles the frontend may run this frame
+    pub fn cycles(&self, wanted: usize) -> usize {
+        match self.state {
+            GdbState::Running => wanted,
+            GdbState::Halted => 0,
+            GdbState::Stepping => 1,
+        }
+    }
+
+    // Ctrl-C in the debugger
+    pub fn interrupt(&mut self) -> Option<String> {
+        match self.state {
+            GdbState::Halted => None,
+            _ => self.stop(SIGINT),
+        }
+    }
+
+    // before every cycle: the stop reply if the machine is at a breakpoint
+    pub fn at_breakpoint(&mut self, vm: &CrispAte) -> Option<String> {
+        let hit = self.state == GdbState::Running
+            && !self.resuming
+            && self.breakpoints.contains(&vm.registers.program_counter);
+
+        match hit {
+            true => self.stop(SIGTRAP),
+            false => None,
+        }
+    }
+
+    // after every cycle: the stop reply if a step finished or it faulted
+    pub fn cycle_done(&mut self, vm: &CrispAte) -> Option<String> {
+        self.resuming = false;
+
+        if self.state == GdbState::Halted {
+            return None;
+        }
+
+        if let Some(fault) = vm.fault {
+            return self.stop(match fault.fault {
+                Fault::InvalidOpcode => SIGILL,
+                _ => SIGSEGV,
+            });
+        }
+
+        match self.state {
+            GdbState::Stepping => self.stop(SIGTRAP),
+            _ => None,
+        }
+    }
+
+    // the reply to one packet, None when there is none yet (continue and
+    // step answer once the machine stops)
+    pub fn handle(&mut self, packet: &str, vm: &mut CrispAte) -> Option<String> {
+        if !packet.is_ascii() {
+            return Some(String::new());
+        }
+
+        let reply = match packet.split_at(packet.len().min(1)) {
+            ("?", _) => format!("S{:02x}", SIGTRAP),
+            ("g", "") => {
+                let registers: Vec<u8> = (0..REGISTER_COUNT)
+                    .flat_map(|register| read_register(vm, register))
+                    .collect();
+                to_hex(&registers)
+            }
+            ("G", _) | ("P", _) | ("M", _) if self.read_only => error(),
+            ("c", address) | ("s", address) if self.read_only && !address.is_empty() => error(),
+            ("G", values) => write_registers(vm, values).unwrap_or_else(error),
+            ("p", register) => usize::from_str_radix(register, 16)
+                .ok()
+                .filter(|register| *register < REGISTER_COUNT)
+                .map_or_else(error, |register| to_hex(&read_register(vm, register))),
+            ("P", assignment) => write_register(vm, assignment).unwrap_or_else(error),
+            ("m", range) => read_memory(vm, range).unwrap_or_else(error),
+            ("M", write) => write_memory(vm, write).unwrap_or_else(error),
+            ("Z", breakpoint) | ("z", breakpoint) => {
+                return Some(self.breakpoint(packet.starts_with('Z'), breakpoint));
+            }
+            ("c", address) | ("s", address) => {
+                if let Ok(address) = u16::from_str_radix(address, 16) {
+                    vm.registers.program_counter = address;
+                }
+
+                self.resuming = true;
+                self.state = match packet.starts_with('s') {
+                    true => GdbState::Stepping,
+                    false => GdbState::Running,
+                };
+                return None;
+            }
+            ("D", _) => {
+                self.detach();
+                String::from("OK")
+            }
+            ("k", _) => {
+                self.detach();
+                return None;
+            }
+            ("H", _) | ("T", _) => String::from("OK"),
+            _ => match packet {
+                "qAttached" => String::from("1"),
+                "qC" => String::from("QC1"),
+                "qfThreadInfo" => String::from("m1"),
+                "qsThreadInfo" => String::from("l"),
+                "QStartNoAckMode" => String::from("OK"),
+                _ if packet.starts_with("qSupported") => {
+                    String::from("PacketSize=1000;qXfer:features:read+;QStartNoAckMode+")
+                }
+                _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                    read_target_xml(&packet["qXfer:features:read:target.xml:".len()..])
+                        .unwrap_or_else(error)
+                }
+                // anything else is unsupported, which an empty reply says
+                _ => String::new(),
+            },
+        };
+
+        Some(reply)
+    }
+
+    // Z0/Z1 (software and hardware breakpoints are the same thing here),
+    // watchpoints aren't supported
+    fn breakpoint(&mut self, insert: bool, arguments: &str) -> String {
+        let mut arguments = arguments.split(',');
+        let kind = arguments.next();
+        let address = arguments
+            .next()
+            .and_then(|address| u16::from_str_radix(address, 16).ok());
+
+        match (kind, address) {
+            (Some("0") | Some("1"), Some(address)) => {
+                match insert {
+                    true => self.breakpoints.insert(address),
+                    false => self.breakpoints.remove(&address),
+                };
+                String::from("OK")
+            }
+            (Some("0") | Some("1"), None) => error(),
+            _ => String::new(),
+        }
+    }
+
+    fn stop(&mut self, signal: u8) -> Option<String> {
+        self.state = GdbState::Halted;
+        Some(format!("S{:02x}", signal))
+    }
+}
+
+fn error() -> String {
+    String::from("E01")
+}
+
+// 16-bit registers are sent big endian, like CHIP-8 keeps words in memory
+fn read_register(vm: &CrispAte, register: usize) -> Vec<u8> {
+    match register {
+        0..=15 => vec![vm.registers.v[VReg::from_nibble(register as u16)]],
+        16 => vm.registers.address.to_be_bytes().to_vec(),
+        17 => vm.registers.program_counter.to_be_bytes().to_vec(),
+        18 => vec![vm.runtime.stack_pointer as u8],
+        19 => vec![vm.timers.delay],
+        _ => vec![vm.timers.sound],
+    }
+}
+
+fn set_register(vm: &mut CrispAte, register: usize, bytes: &[u8]) -> Option<()> {
+    let byte = *bytes.first()?;
+    let word = match bytes {
+        [high, low] => u16::from_be_bytes([*high, *low]),
+        _ => byte as u16,
+    };
+
+    match register {
+        0..=15 => vm.registers.v[VReg::from_nibble(register as u16)] = byte,
+        16 => vm.registers.address = word,
+        17 => vm.registers.program_counter = word,
+        18 => vm.runtime.stack_pointer = (byte as usize).min(STACK_SIZE),
+        19 => vm.timers.delay = byte,
+        20 => vm.timers.sound = byte,
+        _ => return None,
+    }
+
+    Some(())
+}
+
+fn register_size(register: usize) -> usize {
+    match register {
+        16 | 17 => 2,
+        _ => 1,
+    }
+}
+
+// G<all registers>
+fn write_registers(vm: &mut CrispAte, values: &str) -> Option<String> {
+    let bytes = from_hex(values)?;
+    let mut at = 0;
+
+    for register in 0..REGISTER_COUNT {
+        let size = register_size(register);
+        set_register(vm, register, bytes.get(at..at + size)?)?;
+        at += size;
+    }
+
+    Some(String::from("OK"))
+}
+
+// P<register>=<value>
+fn write_register(vm: &mut CrispAte, assignment: &str) -> Option<String> {
+    let (register, value) = assignment.split_once('=')?;
+    let register = usize::from_str_radix(register, 16).ok()?;
+    let bytes = from_hex(value)?;
+
+    if bytes.len() != register_size(register) {
+        return None;
+    }
+
+    set_register(vm, register, &bytes)?;
+    Some(String::from("OK"))
+}
+
+// "<address>,<length>", inside memory
+fn memory_range(range: &str) -> Option<(usize, usize)> {
+    let (address, length) = range.split_once(',')?;
+    let address = usize::from_str_radix(address, 16).ok()?;
+    let length = usize::from_str_radix(length, 16).ok()?;
+
+    match address.checked_add(length)? <= MEMORY_SIZE {
+        true => Some((address, length)),
+        false => None,
+    }
+}
+
+// m<address>,<length>, read without touching hooks or mapped devices
+fn read_memory(vm: &CrispAte, range: &str) -> Option<String> {
+    let (address, length) = memory_range(range)?;
+    let bytes: Vec<u8> = (address..address + length)
+        .map(|at| vm.memory.peek(at))
+        .collect();
+
+    Some(to_hex(&bytes))
+}
+
+// M<address>,<length>:<bytes>
+fn write_memory(vm: &mut CrispAte, write: &str) -> Option<String> {
+    let (range, values) = write.split_once(':')?;
+    let (address, length) = memory_range(range)?;
+    let bytes = from_hex(values)?;
+
+    if bytes.len() != length {
+        return None;
+    }
+
+    vm.memory.load(address, &bytes);
+    Some(String::from("OK"))
+}
+
+// <offset>,<length> of the target description
+fn read_target_xml(range: &str) -> Option<String> {
+    let (offset, length) = range.split_once(',')?;
+    let offset = usize::from_str_radix(offset, 16).ok()?;
+    let length = usize::from_str_radix(length, 16).ok()?;
+
+    let rest = TARGET_XML.get(offset..)?;
+    match rest.len() > length {
+        true => Some(format!("m{}", &rest[..length])),
+        false => Some(format!("l{}", rest)),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Message {
+    Packet(String),
+    Corrupt, // checksum mismatch, asks for it again
+    Interrupt,
+}
+
+fn sum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+// $<data>#<checksum>
+pub fn frame(data: &str) -> String {
+    format!("${}#{:02x}", data, sum(data))
+}
+
+// takes the first complete message out of `buffer`, skipping acks; partial
+// packets stay until the rest arrives
+pub fn next_message(buffer: &mut Vec<u8>) -> Option<Message> {
+    let start = buffer
+        .iter()
+        .position(|byte| *byte == b'$' || *byte == 0x03);
+    let start = match start {
+        Some(start) => start,
+        None => {
+            buffer.clear();
+            return None;
+        }
+    };
+    buffer.drain(..start);
+
+    if buffer[0] == 0x03 {
+        buffer.remove(0);
+        return Some(Message::Interrupt);
+    }
+
+    let end = buffer.iter().position(|byte| *byte == b'#')?;
+    if buffer.len() < end + 3 {
+        return None;
+    }
+
+    let data = String::from_utf8_lossy(&buffer[1..end]).into_owned();
+    let checksum = std::str::from_utf8(&buffer[end + 1..end + 3])
+        .ok()
+        .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+    buffer.drain(..end + 3);
+
+    match checksum == Some(sum(&data)) {
+        true => Some(Message::Packet(data)),
+        false => Some(Message::Corrupt),
+    }
+}
+
+// A GDB remote serial protocol server on localhost. It never blocks: the
+// frontend polls it once a frame and asks it before and after every cycle.
+pub struct GdbServer {
+    pub stub: GdbStub,
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    buffer: Vec<u8>,
+    no_ack: bool,
+}
+
+impl GdbServer {
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(GdbServer {
+            stub: GdbStub::new(),
+            listener,
+            client: None,
+            buffer: Vec::new(),
+            no_ack: false,
+        })
+    }
+
+    // accepts a debugger and answers whatever it sent since the last frame
+    pub fn poll(&mut self, vm: &mut CrispAte) {
+        if self.client.is_none() && !self.accept() {
+            return;
+        }
+
+        let mut chunk = [0u8; 1024];
+        while let Some(client) = self.client.as_mut() {
+            match client.read(&mut chunk) {
+                Ok(0) => self.disconnect(),
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => self.disconnect(),
+            }
+        }
+
+        while let Some(message) = next_message(&mut self.buffer) {
+            match message {
+                Message::Interrupt => {
+                    if let Some(reply) = self.stub.interrupt() {
+                        self.send(&reply);
+                    }
+                }
+                Message::Corrupt => self.send_raw(b"-"),
+                Message::Packet(packet) => {
+                    if !self.no_ack {
+                        self.send_raw(b"+");
+                    }
+
+                    if let Some(reply) = self.stub.handle(&packet, vm) {
+                        self.send(&reply);
+                    }
+
+                    match packet.as_str() {
+                        "QStartNoAckMode" => self.no_ack = true,
+                        "k" => self.disconnect(),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // a machine that already faulted stops again right away
+        if vm.fault.is_some() {
+            self.after_cycle(vm);
+        }
+    }
+
+    // false when the cycle must not run, the machine is at a breakpoint
+    pub fn before_cycle(&mut self, vm: &CrispAte) -> bool {
+        match self.stub.at_breakpoint(vm) {
+            Some(reply) => {
+                self.send(&reply);
+                false
+            }
+            None => true,
+        }
+    }
+
+    pub fn after_cycle(&mut self, vm: &CrispAte) {
+        if let Some(reply) = self.stub.cycle_done(vm) {
+            self.send(&reply);
+        }
+    }
+
+    fn accept(&mut self) -> bool {
+        match self.listener.accept() {
+            Ok((client, address)) => {
+                if client.set_nonblocking(true).is_err() {
+                    return false;
+                }
+                let _ = client.set_nodelay(true);
+
+                println!("Debugger connected from {}", address);
+                self.client = Some(client);
+                self.buffer.clear();
+                self.no_ack = false;
+                self.stub.attach();
+                true
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => false,
+            Err(e) => {
+                eprintln!("Could not accept debugger: {}", e);
+                false
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        if self.client.take().is_some() {
+            println!("Debugger disconnected");
+        }
+        self.buffer.clear();
+        self.stub.detach();
+    }
+
+    fn send(&mut self, reply: &str) {
+        self.send_raw(frame(reply).as_bytes());
+    }
+
+    // replies are small, so they are written blocking in one go
+    fn send_raw(&mut self, bytes: &[u8]) {
+        let sent = match self.client.as_mut() {
+            Some(client) => client
+                .set_nonblocking(false)
+                .and_then(|_| client.write_all(bytes))
+                .and_then(|_| client.set_nonblocking(true)),
+            None => return,
+        };
+
+        if sent.is_err() {
+            self.disconnect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod gdb_tests {
+    use super::*;
+    use crate::crisp_ate::fault::FaultPolicy;
+
+    #[test]
+    fn frames_and_unframes_packets() {
+        assert_eq!(frame("OK"), "$OK#9a");
+
+        let mut buffer = b"+$g#67$m0,2#".to_vec();
+        assert_eq!(
+            next_message(&mut buffer),
+            Some(Message::Packet(String::from("g")))
+        );
+        assert_eq!(next_message(&mut buffer), None);
+
+        buffer.extend_from_slice(b"fb\x03$g#00");
+        assert_eq!(
+            next_message(&mut buffer),
+            Some(Message::Packet(String::from("m0,2")))
+        );
+        assert_eq!(next_message(&mut buffer), Some(Message::Interrupt));
+        assert_eq!(next_message(&mut buffer), Some(Message::Corrupt));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn reads_and_writes_registers() {
+        let mut vm = CrispAte::with_program(&[0x12, 0x00]);
+        let mut sut = GdbStub::new();
+        vm.registers.v[VReg::new(0xA).unwrap()] = 0x42;
+        vm.registers.address = 0x0ABC;
+        vm.timers.delay = 7;
+
+        let registers = sut.handle("g", &mut vm).unwrap();
+        assert_eq!(registers.len(), (16 + 2 + 2 + 1 + 1 + 1) * 2);
+        assert_eq!(&registers[20..22], "42");
+        assert_eq!(&registers[32..40], "0abc0200");
+        assert_eq!(&registers[42..44], "07");
+
+        assert_eq!(sut.handle("P11=0300", &mut vm).unwrap(), "OK");
+        assert_eq!(vm.registers.program_counter, 0x300);
+        assert_eq!(sut.handle("p11", &mut vm).unwrap(), "0300");
+        assert_eq!(sut.handle("p15", &mut vm).unwrap(), "E01");
+
+        let mut written = registers.clone();
+        written.replace_range(0..2, "ff");
+        assert_eq!(sut.handle(&format!("G{}", written), &mut vm).unwrap(), "OK");
+        assert_eq!(vm.registers.v[VReg::new(0).unwrap()], 0xFF);
+        assert_eq!(vm.registers.program_counter, 0x200);
+    }
+
+    #[test]
+    fn reads_and_writes_memory() {
+        let mut vm = CrispAte::with_program(&[0x12, 0x00]);
+        let mut sut = GdbStub::new();
+
+        assert_eq!(sut.handle("m200,2", &mut vm).unwrap(), "1200");
+        assert_eq!(sut.handle("M202,2:00e0", &mut vm).unwrap(), "OK");
+        assert_eq!(vm.memory.peek(0x203), 0xE0);
+        assert_eq!(sut.handle("mfff,2", &mut vm).unwrap(), "E01");
+        assert_eq!(sut.handle("M202,2:00", &mut vm).unwrap(), "E01");
+    }
+
+    #[test]
+    fn refuses_edits_when_read_only() {
+        let mut vm = CrispAte::with_program(&[0x12, 0x00]);
+        let mut sut = GdbStub::new();
+        sut.read_only = true;
+        let registers = sut.handle("g", &mut vm).unwrap();
+
+        assert_eq!(sut.handle("M202,2:00e0", &mut vm).unwrap(), "E01");
+        assert_eq!(sut.handle("P11=0300", &mut vm).unwrap(), "E01");
+        assert_eq!(
+            sut.handle(&format!("G{}", registers), &mut vm).unwrap(),
+            "E01"
+        );
+        assert_eq!(sut.handle("c300", &mut vm).unwrap(), "E01");
+        assert_eq!(vm.memory.peek(0x203), 0x00);
+        assert_eq!(vm.registers.program_counter, 0x200);
+
+        assert_eq!(sut.handle("m200,2", &mut vm).unwrap(), "1200");
+        assert_eq!(sut.handle("c", &mut vm), None);
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_after_steps() {
+        // 6001, 6102, jump back
+        let mut vm = CrispAte::with_program(&[0x60, 0x01, 0x61, 0x02, 0x12, 0x00]);
+        let mut sut = GdbStub::new();
+        sut.attach();
+        assert_eq!(sut.cycles(8), 0);
+
+        assert_eq!(sut.handle("Z0,202,2", &mut vm).unwrap(), "OK");
+        assert_eq!(sut.handle("c", &mut vm), None);
+        assert_eq!(sut.cycles(8), 8);
+
+        assert_eq!(sut.at_breakpoint(&vm), None);
+        vm.emulation_cyle().unwrap();
+        assert_eq!(sut.cycle_done(&vm), None);
+        assert_eq!(sut.at_breakpoint(&vm).unwrap(), "S05");
+        assert_eq!(sut.state, GdbState::Halted);
+
+        // continuing from the breakpoint doesn't stop at it again
+        sut.handle("c", &mut vm);
+        assert_eq!(sut.at_breakpoint(&vm), None);
+
+        sut.handle("s", &mut vm);
+        assert_eq!(sut.cycles(8), 1);
+        vm.emulation_cyle().unwrap();
+        assert_eq!(sut.cycle_done(&vm).unwrap(), "S05");
+        assert_eq!(vm.registers.program_counter, 0x204);
+
+        assert_eq!(sut.handle("z0,202,2", &mut vm).unwrap(), "OK");
+        assert!(sut.breakpoints.is_empty());
+        assert_eq!(sut.handle("Z2,300,1", &mut vm).unwrap(), "");
+    }
+
+    #[test]
+    fn reports_faults_as_signals() {
+        let mut vm = CrispAte::with_program(&[0x00, 0xEE]);
+        vm.fault_policy = FaultPolicy::Halt;
+        let mut sut = GdbStub::new();
+
+        let _ = vm.emulation_cyle();
+
+        assert_eq!(sut.cycle_done(&vm).unwrap(), "S0b");
+        assert_eq!(sut.state, GdbState::Halted);
+        assert_eq!(sut.cycle_done(&vm), None);
+    }
+
+    #[test]
+    fn serves_the_target_description() {
+        let mut vm = CrispAte::with_program(&[]);
+        let mut sut = GdbStub::new();
+
+        let first = sut
+            .handle("qXfer:features:read:target.xml:0,10", &mut vm)
+            .unwrap();
+        assert_eq!(first, "m<?xml version=\"1");
+
+        let rest = sut
+            .handle("qXfer:features:read:target.xml:10,fff", &mut vm)
+            .unwrap();
+        assert!(rest.starts_with('l') && rest.ends_with("</target>\n"));
+    }
+
+    #[test]
+    fn talks_to_a_client_over_tcp() {
+        let mut vm = CrispAte::with_program(&[0x12, 0x00]);
+        let mut sut = GdbServer::bind(0).unwrap();
+        let port = sut.listener.local_addr().unwrap().port();
+
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        client.write_all(frame("m200,2").as_bytes()).unwrap();
+        client
+            .set_read_timeout(Some(std::time::Duration::from_millis(20)))
+            .unwrap();
+
+        let mut reply: Vec<u8> = Vec::new();
+        for _ in 0..250 {
+            sut.poll(&mut vm);
+
+            let mut chunk = [0u8; 64];
+            if let Ok(read) = client.read(&mut chunk) {
+                reply.extend_from_slice(&chunk[..read]);
+            }
+
+            if reply.len() >= 9 {
+                break;
+            }
+        }
+
+        assert_eq!(reply, b"+$1200#c3");
+        assert_eq!(sut.stub.state, GdbState::Halted);
+    }
+}
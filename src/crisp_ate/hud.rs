@@ -0,0 +1,167 @@
+use std::time::{Duration, Instant};
+
+// how long a toast message stays up
+pub const TOAST_TIME: Duration = Duration::from_secs(3);
+
+// save state slots go from 1 to this
+pub const STATE_SLOTS: usize = 9;
+
+// rates are averaged over this long, so the numbers don't jitter every frame
+const RATE_WINDOW: Duration = Duration::from_millis(500);
+
+// counts events (frames, instructions) and works out how many happen per
+// second
+pub struct RateMeter {
+    since: Instant,
+    count: u64,
+    pub rate: f64,
+}
+
+impl RateMeter {
+    pub fn new(now: Instant) -> Self {
+        RateMeter {
+            since: now,
+            count: 0,
+            rate: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, count: u64, now: Instant) {
+        self.count += count;
+
+        let elapsed = now.duration_since(self.since);
+        if elapsed >= RATE_WINDOW {
+            self.rate = self.count as f64 / elapsed.as_secs_f64();
+            self.count = 0;
+            self.since = now;
+        }
+    }
+}
+
+// What the overlay shows on top of the raylib frontend. It only keeps the
+// numbers and messages, `display::draw_frame` does the drawing.
+pub struct CrispAteHud {
+    pub visible: bool,
+    pub title: String,
+    pub paused: bool,
+    pub turbo: bool,
+    pub slot: usize, // the save state slot F5 and F8 use
+    frames: RateMeter,
+    instructions: RateMeter,
+    toasts: Vec<(String, Instant)>, // with the time they go away
+}
+
+impl CrispAteHud {
+    pub fn new(title: &str, now: Instant) -> Self {
+        CrispAteHud {
+            visible: true,
+            title: title.to_owned(),
+            paused: false,
+            turbo: false,
+            slot: 1,
+            frames: RateMeter::new(now),
+            instructions: RateMeter::new(now),
+            toasts: Vec::new(),
+        }
+    }
+
+    // messages are kept while the HUD is hidden, so showing it again still
+    // shows whatever is recent
+    pub fn toast(&mut self, message: &str, now: Instant) {
+        self.toasts.push((message.to_owned(), now + TOAST_TIME));
+    }
+
+    // once per rendered frame, with the instructions executed during it
+    pub fn frame(&mut self, instructions: u64, now: Instant) {
+        self.frames.add(1, now);
+        self.instructions.add(instructions, now);
+        self.toasts.retain(|(_, until)| *until > now);
+    }
+
+    pub fn next_slot(&mut self) {
+        self.slot = self.slot % STATE_SLOTS + 1;
+    }
+
+    // "60 FPS  600 IPS  SLOT 2  TURBO"
+    pub fn status(&self) -> String {
+        let mut status = format!(
+            "{:.0} FPS  {:.0} IPS  SLOT {}",
+            self.frames.rate, self.instructions.rate, self.slot
+        );
+
+        if self.paused {
+            status.push_str("  PAUSED");
+        }
+
+        if self.turbo {
+            status.push_str("  TURBO");
+        }
+
+        status
+    }
+
+    // newest first
+    pub fn toasts(&self) -> Vec<&str> {
+        self.toasts
+            .iter()
+            .rev()
+            .map(|(message, _)| message.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod hud_tests {
+    use super::*;
+
+    #[test]
+    fn measures_rates_over_a_window() {
+        let start = Instant::now();
+        let mut sut = RateMeter::new(start);
+
+        for frame in 1..30 {
+            sut.add(10, start + Duration::from_millis(frame * 1000 / 60));
+        }
+
+        assert_eq!(sut.rate, 0.0);
+
+        sut.add(10, start + RATE_WINDOW);
+
+        assert_eq!(sut.rate, 600.0);
+    }
+
+    #[test]
+    fn shows_the_emulation_state() {
+        let start = Instant::now();
+        let mut sut = CrispAteHud::new("pong", start);
+
+        sut.frame(10, start + RATE_WINDOW);
+        assert_eq!(sut.status(), "2 FPS  20 IPS  SLOT 1");
+
+        sut.paused = true;
+        sut.turbo = true;
+        sut.next_slot();
+        assert_eq!(sut.status(), "2 FPS  20 IPS  SLOT 2  PAUSED  TURBO");
+
+        for _ in 0..STATE_SLOTS - 1 {
+            sut.next_slot();
+        }
+        assert_eq!(sut.slot, 1);
+    }
+
+    #[test]
+    fn toasts_expire() {
+        let start = Instant::now();
+        let mut sut = CrispAteHud::new("pong", start);
+
+        sut.toast("Screenshot saved", start);
+        sut.toast("Palette: amber", start + Duration::from_secs(1));
+        assert_eq!(sut.toasts(), vec!["Palette: amber", "Screenshot saved"]);
+
+        sut.frame(1, start + TOAST_TIME);
+        assert_eq!(sut.toasts(), vec!["Palette: amber"]);
+
+        sut.frame(1, start + TOAST_TIME + Duration::from_secs(1));
+        assert!(sut.toasts().is_empty());
+    }
+}
@@ -0,0 +1,440 @@
+use std::fmt;
+
+// Just enough JSON for the debug adapter protocol, which keeps the emulator
+// free of serialization crates. Objects keep their keys in order.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Self {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    // Null for missing keys, so lookups can be chained
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map_or(&Json::Null, |(_, value)| value),
+            _ => &Json::Null,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(number) if *number >= 0.0 && number.fract() == 0.0 => Some(*number as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(number) if number.fract() == 0.0 => Some(*number as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Json> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            position: 0,
+        };
+
+        let value = parser.value()?;
+        parser.whitespace();
+
+        match parser.position == parser.bytes.len() {
+            true => Some(value),
+            false => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(text: &str) -> Self {
+        Json::String(text.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(text: String) -> Self {
+        Json::String(text)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(number: u64) -> Self {
+        Json::Number(number as f64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(number: usize) -> Self {
+        Json::Number(number as f64)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(number: i64) -> Self {
+        Json::Number(number as f64)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Self {
+        Json::Array(items)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(number) if number.is_finite() => write!(f, "{}", number),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(text) => write_string(f, text),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(|byte| byte.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Option<()> {
+        match self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            true => {
+                self.position += literal.len();
+                Some(())
+            }
+            false => None,
+        }
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.whitespace();
+
+        match self.bytes.get(self.position)? {
+            b'n' => self.expect("null").map(|_| Json::Null),
+            b't' => self.expect("true").map(|_| Json::Bool(true)),
+            b'f' => self.expect("false").map(|_| Json::Bool(false)),
+            b'"' => self.string().map(Json::String),
+            b'[' => self.array(),
+            b'{' => self.object(),
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.position;
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(|byte| b"+-.eE0123456789".contains(byte))
+        {
+            self.position += 1;
+        }
+
+        let text = std::str::from_utf8(&self.bytes[start..self.position]).ok()?;
+        text.parse().ok().map(Json::Number)
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = self.bytes.get(self.position..self.position + 4)?;
+        self.position += 4;
+        u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect("\"")?;
+        let mut text: Vec<u8> = Vec::new();
+
+        loop {
+            let byte = *self.bytes.get(self.position)?;
+            self.position += 1;
+
+            match byte {
+                b'"' => return String::from_utf8(text).ok(),
+                b'\\' => {
+                    let escaped = *self.bytes.get(self.position)?;
+                    self.position += 1;
+
+                    let c = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let unit = self.hex4()?;
+                            // surrogate pairs come as two escapes
+                            let code = match (0xD800..0xDC00).contains(&unit) {
+                                true => {
+                                    self.expect("\\u")?;
+                                    let low = self.hex4()?;
+                                    0x10000 + ((unit - 0xD800) << 10) + (low.checked_sub(0xDC00)?)
+                                }
+                                false => unit,
+                            };
+                            char::from_u32(code)?
+                        }
+                        _ => return None,
+                    };
+
+                    let mut buffer = [0u8; 4];
+                    text.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                }
+                byte => text.push(byte),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<Json> {
+        self.expect("[")?;
+        let mut items: Vec<Json> = Vec::new();
+
+        self.whitespace();
+        if self.expect("]").is_some() {
+            return Some(Json::Array(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+            self.whitespace();
+
+            if self.expect("]").is_some() {
+                return Some(Json::Array(items));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn object(&mut self) -> Option<Json> {
+        self.expect("{")?;
+        let mut fields: Vec<(String, Json)> = Vec::new();
+
+        self.whitespace();
+        if self.expect("}").is_some() {
+            return Some(Json::Object(fields));
+        }
+
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.whitespace();
+            self.expect(":")?;
+            fields.push((key, self.value()?));
+            self.whitespace();
+
+            if self.expect("}").is_some() {
+                return Some(Json::Object(fields));
+            }
+            self.expect(",")?;
+        }
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// DAP sends memory contents as base64
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut text = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| {
+            group | (*byte as u32) << (16 - index * 8)
+        });
+
+        for index in 0..4 {
+            match index <= chunk.len() {
+                true => text.push(BASE64[(group >> (18 - index * 6)) as usize & 0x3F] as char),
+                false => text.push('='),
+            }
+        }
+    }
+
+    text
+}
+
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut group: u32 = 0;
+    let mut bits = 0;
+
+    for c in text.bytes() {
+        let value = BASE64.iter().position(|digit| *digit == c)? as u32;
+        group = group << 6 | value;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((group >> bits) as u8);
+        }
+    }
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod json_tests {
+    use super::*;
+
+    #[test]
+    fn parses_requests() {
+        let request = Json::parse(
+            r#" {"seq": 3, "type": "request", "command": "setBreakpoints",
+                "arguments": {"source": {"path": "C:\\games\\pong.8o"},
+                "breakpoints": [{"line": 12}, {"line": -1}], "flag": true, "none": null}} "#,
+        )
+        .unwrap();
+
+        assert_eq!(request.get("seq").as_u64(), Some(3));
+        assert_eq!(request.get("command").as_str(), Some("setBreakpoints"));
+
+        let arguments = request.get("arguments");
+        assert_eq!(
+            arguments.get("source").get("path").as_str(),
+            Some("C:\\games\\pong.8o")
+        );
+        assert_eq!(arguments.get("breakpoints").as_array().len(), 2);
+        assert_eq!(
+            arguments.get("breakpoints").as_array()[1]
+                .get("line")
+                .as_i64(),
+            Some(-1)
+        );
+        assert_eq!(arguments.get("flag").as_bool(), Some(true));
+        assert_eq!(arguments.get("missing").get("deeper"), &Json::Null);
+
+        assert!(Json::parse("{\"a\": 1,}").is_none());
+        assert!(Json::parse("[1, 2] 3").is_none());
+    }
+
+    #[test]
+    fn handles_escapes_both_ways() {
+        let text = Json::parse(r#""tab\there \"quoted\" \u00e9 \ud83d\ude00""#).unwrap();
+        assert_eq!(text.as_str(), Some("tab\there \"quoted\" é 😀"));
+
+        let value = Json::object(vec![
+            ("text", Json::from("line\nbreak \"x\"")),
+            (
+                "items",
+                Json::from(vec![Json::from(1u64), Json::Null, Json::from(false)]),
+            ),
+        ]);
+        let written = value.to_string();
+
+        assert_eq!(
+            written,
+            r#"{"text":"line\nbreak \"x\"","items":[1,null,false]}"#
+        );
+        assert_eq!(Json::parse(&written), Some(value));
+    }
+
+    #[test]
+    fn round_trips_base64() {
+        assert_eq!(base64_encode(b"CHIP-8"), "Q0hJUC04");
+        assert_eq!(base64_encode(&[0x12, 0x00]), "EgA=");
+        assert_eq!(base64_encode(&[0xFF]), "/w==");
+
+        assert_eq!(base64_decode("EgA=").unwrap(), vec![0x12, 0x00]);
+        assert_eq!(base64_decode("Q0hJUC04").unwrap(), b"CHIP-8");
+        assert!(base64_decode("not base64!").is_none());
+    }
+}
@@ -0,0 +1,337 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub const ROM_EXTENSIONS: [&str; 2] = ["ch8", "c8"];
+
+// "<file name> = <title>" per line, in the ROM directory
+pub const TITLES_FILE: &str = "titles.txt";
+
+const MAX_RECENT_FILES: usize = 10;
+
+pub fn is_rom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ROM_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RomEntry {
+    pub path: PathBuf,
+    pub title: String,
+}
+
+impl RomEntry {
+    // named from the titles database when it has an entry, from the file
+    // name otherwise
+    pub fn new(path: &Path, titles: &[(String, String)]) -> Self {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        let title = titles
+            .iter()
+            .find(|(name, _)| name == file_name)
+            .map(|(_, title)| title.clone())
+            .unwrap_or_else(|| {
+                let stem = path.file_stem().and_then(|stem| stem.to_str());
+                stem.unwrap_or(file_name).to_owned()
+            });
+
+        RomEntry {
+            path: path.to_owned(),
+            title,
+        }
+    }
+}
+
+pub fn parse_titles(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, title)| (name.trim().to_owned(), title.trim().to_owned()))
+        .collect()
+}
+
+// every ROM in `directory` (not its subdirectories), sorted by title
+pub fn scan_roms(directory: &Path) -> io::Result<Vec<RomEntry>> {
+    let titles = fs::read_to_string(directory.join(TITLES_FILE))
+        .map(|text| parse_titles(&text))
+        .unwrap_or_default();
+
+    let mut entries: Vec<RomEntry> = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_file() && is_rom(&path) {
+            entries.push(RomEntry::new(&path, &titles));
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.title.to_lowercase());
+    Ok(entries)
+}
+
+// the newest screenshot of a ROM, as saved by F12 (`<rom name>-<frame>.png`)
+pub fn find_thumbnail(screenshot_directory: &Path, rom: &Path) -> Option<PathBuf> {
+    let stem = rom.file_stem()?.to_str()?;
+    let mut newest: Option<(SystemTime, PathBuf)> = None;
+
+    for entry in fs::read_dir(screenshot_directory).ok()?.flatten() {
+        let path = entry.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let frame = name
+            .strip_prefix(stem)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .and_then(|rest| rest.strip_suffix(".png"));
+        if !frame
+            .is_some_and(|frame| !frame.is_empty() && frame.bytes().all(|b| b.is_ascii_digit()))
+        {
+            continue;
+        }
+
+        let modified = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        if newest.as_ref().is_none_or(|(time, _)| modified >= *time) {
+            newest = Some((modified, path));
+        }
+    }
+
+    newest.map(|(_, path)| path)
+}
+
+// Recently played ROMs, newest first, kept in the home directory so they are
+// the same wherever the emulator is started from.
+pub struct RecentFiles {
+    pub paths: Vec<PathBuf>,
+    filename: Option<PathBuf>,
+}
+
+impl RecentFiles {
+    pub fn new(filename: Option<PathBuf>) -> Self {
+        RecentFiles {
+            paths: Vec::new(),
+            filename,
+        }
+    }
+
+    // ~/.crisp-ate-recent, an empty list when there is no home directory
+    pub fn load() -> Self {
+        let filename = env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(".crisp-ate-recent"));
+
+        let mut recent = RecentFiles::new(filename);
+        if let Some(text) = recent
+            .filename
+            .as_ref()
+            .and_then(|f| fs::read_to_string(f).ok())
+        {
+            recent.paths = text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(PathBuf::from)
+                .take(MAX_RECENT_FILES)
+                .collect();
+        }
+
+        recent
+    }
+
+    pub fn add(&mut self, path: &Path) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+
+        self.paths.retain(|recent| *recent != path);
+        self.paths.insert(0, path);
+        self.paths.truncate(MAX_RECENT_FILES);
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let filename = match &self.filename {
+            Some(filename) => filename,
+            None => return Ok(()),
+        };
+
+        let mut text = String::new();
+        for path in &self.paths {
+            text.push_str(&path.to_string_lossy());
+            text.push('\n');
+        }
+
+        fs::write(filename, text)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LauncherAction {
+    Up,
+    Down,
+    SwitchList, // between the ROM directory and the recent files
+    Open,
+}
+
+// the in-window ROM browser, shown when no ROM was given and on F1
+pub struct CrispAteLauncher {
+    pub roms: Vec<RomEntry>,
+    pub recent: Vec<RomEntry>,
+    pub showing_recent: bool,
+    pub selected: usize,
+}
+
+impl CrispAteLauncher {
+    pub fn new(roms: Vec<RomEntry>, recent: &RecentFiles) -> Self {
+        // recent ROMs get the same titles as in the directory listing
+        let recent: Vec<RomEntry> = recent
+            .paths
+            .iter()
+            .filter(|path| path.is_file())
+            .map(|path| {
+                let titles = path
+                    .parent()
+                    .and_then(|directory| fs::read_to_string(directory.join(TITLES_FILE)).ok())
+                    .map(|text| parse_titles(&text))
+                    .unwrap_or_default();
+                RomEntry::new(path, &titles)
+            })
+            .collect();
+
+        CrispAteLauncher {
+            showing_recent: roms.is_empty() && !recent.is_empty(),
+            roms,
+            recent,
+            selected: 0,
+        }
+    }
+
+    pub fn entries(&self) -> &[RomEntry] {
+        match self.showing_recent {
+            true => &self.recent,
+            false => &self.roms,
+        }
+    }
+
+    pub fn selected_entry(&self) -> Option<&RomEntry> {
+        self.entries().get(self.selected)
+    }
+
+    // the ROM to start, if the action was to open one
+    pub fn apply(&mut self, action: LauncherAction) -> Option<PathBuf> {
+        let count = self.entries().len();
+
+        match action {
+            LauncherAction::Up => self.selected = self.selected.saturating_sub(1),
+            LauncherAction::Down => {
+                self.selected = (self.selected + 1).min(count.saturating_sub(1))
+            }
+            LauncherAction::SwitchList => {
+                self.showing_recent = !self.showing_recent;
+                self.selected = 0;
+            }
+            LauncherAction::Open => return self.selected_entry().map(|entry| entry.path.clone()),
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod library_tests {
+    use super::*;
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("crisp-ate-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn scans_roms_with_titles() {
+        let directory = temp_directory("scan");
+        fs::write(directory.join("pong.ch8"), [0x12, 0x00]).unwrap();
+        fs::write(directory.join("BRIX.C8"), [0x12, 0x00]).unwrap();
+        fs::write(directory.join("notes.txt"), "").unwrap();
+        fs::write(
+            directory.join(TITLES_FILE),
+            "# titles\npong.ch8 = Pong (1 player)\n",
+        )
+        .unwrap();
+
+        let roms = scan_roms(&directory).unwrap();
+        let titles: Vec<&str> = roms.iter().map(|rom| rom.title.as_str()).collect();
+
+        assert_eq!(titles, vec!["BRIX", "Pong (1 player)"]);
+        assert_eq!(roms[1].path, directory.join("pong.ch8"));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn finds_the_newest_screenshot() {
+        let directory = temp_directory("thumbnail");
+        fs::write(directory.join("pong-10.png"), "").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(directory.join("pong-5.png"), "").unwrap();
+        fs::write(directory.join("pong-two-1.png"), "").unwrap();
+        fs::write(directory.join("pong-.png"), "").unwrap();
+
+        assert_eq!(
+            find_thumbnail(&directory, Path::new("roms/pong.ch8")),
+            Some(directory.join("pong-5.png"))
+        );
+        assert_eq!(find_thumbnail(&directory, Path::new("brix.ch8")), None);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn keeps_recent_files_newest_first() {
+        let directory = temp_directory("recent");
+        let filename = directory.join("recent");
+        let mut sut = RecentFiles::new(Some(filename.clone()));
+
+        for index in 0..MAX_RECENT_FILES + 2 {
+            sut.add(Path::new(&format!("/roms/{}.ch8", index)));
+        }
+        sut.add(Path::new("/roms/5.ch8"));
+        sut.save().unwrap();
+
+        assert_eq!(sut.paths.len(), MAX_RECENT_FILES);
+        assert_eq!(sut.paths[0], PathBuf::from("/roms/5.ch8"));
+        assert_eq!(sut.paths[1], PathBuf::from("/roms/11.ch8"));
+        assert!(fs::read_to_string(&filename)
+            .unwrap()
+            .starts_with("/roms/5.ch8\n/roms/11.ch8\n"));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn moves_through_both_lists() {
+        let roms = vec![
+            RomEntry::new(Path::new("a.ch8"), &[]),
+            RomEntry::new(Path::new("b.ch8"), &[]),
+        ];
+        let mut sut = CrispAteLauncher::new(roms, &RecentFiles::new(None));
+
+        assert_eq!(sut.apply(LauncherAction::Up), None);
+        sut.apply(LauncherAction::Down);
+        sut.apply(LauncherAction::Down);
+        assert_eq!(
+            sut.apply(LauncherAction::Open),
+            Some(PathBuf::from("b.ch8"))
+        );
+
+        sut.apply(LauncherAction::SwitchList);
+        assert!(sut.showing_recent);
+        assert_eq!(sut.apply(LauncherAction::Open), None);
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+
+pub const MEMORY_SIZE: usize = 4096;
+
+// (address, value) of every byte going through the bus
+pub type MemoryHook = Box<dyn FnMut(usize, u8)>;
+
+// something living on the bus instead of plain RAM, addresses are given
+// relative to the start of the region it was mapped at
+pub trait MemoryDevice {
+    fn read(&mut self, offset: usize) -> u8;
+    fn write(&mut self, offset: usize, value: u8);
+}
+
+struct MappedRegion {
+    start: usize,
+    end: usize,
+    device: Box<dyn MemoryDevice>,
+}
+
+pub struct CrispAteMemory {
+    bytes: [u8; MEMORY_SIZE],
+    regions: Vec<MappedRegion>,
+    read_hooks: Vec<MemoryHook>,
+    write_hooks: Vec<MemoryHook>,
+}
+
+impl Default for CrispAteMemory {
+    fn default() -> Self {
+        CrispAteMemory::new()
+    }
+}
+
+impl CrispAteMemory {
+    pub fn new() -> Self {
+        CrispAteMemory {
+            bytes: [0; MEMORY_SIZE],
+            regions: Vec::new(),
+            read_hooks: Vec::new(),
+            write_hooks: Vec::new(),
+        }
+    }
+
+    // addresses must already be inside 0x000..=0xFFF, bounds (and what to do
+    // when they are broken) are the cpu's business
+    pub fn read(&mut self, address: usize) -> u8 {
+        let value = match self.region_at(address) {
+            Some(index) => {
+                let region = &mut self.regions[index];
+                region.device.read(address - region.start)
+            }
+            None => self.bytes[address],
+        };
+
+        for hook in self.read_hooks.iter_mut() {
+            hook(address, value);
+        }
+
+        value
+    }
+
+    pub fn write(&mut self, address: usize, value: u8) {
+        match self.region_at(address) {
+            Some(index) => {
+                let region = &mut self.regions[index];
+                region.device.write(address - region.start, value);
+            }
+            None => self.bytes[address] = value,
+        }
+
+        for hook in self.write_hooks.iter_mut() {
+            hook(address, value);
+        }
+    }
+
+    // reads RAM without going through hooks or mapped devices, for
+    // debuggers and tests that must not disturb the machine
+    pub fn peek(&self, address: usize) -> u8 {
+        self.bytes[address]
+    }
+
+    // the big-endian word at `address` read like `peek`, running on past the
+    // end of memory to its start
+    pub fn peek_word(&self, address: usize) -> u16 {
+        u16::from_be_bytes([
+            self.bytes[address % MEMORY_SIZE],
+            self.bytes[(address + 1) % MEMORY_SIZE],
+        ])
+    }
+
+    // copies bytes straight into RAM, skipping hooks and mapped devices
+    pub fn load(&mut self, start: usize, bytes: &[u8]) {
+        self.bytes[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+
+    // maps [start, end) to a device, later mappings win over earlier ones
+    pub fn map(&mut self, start: usize, end: usize, device: Box<dyn MemoryDevice>) {
+        self.regions.insert(0, MappedRegion { start, end, device });
+    }
+
+    pub fn on_read(&mut self, hook: MemoryHook) {
+        self.read_hooks.push(hook);
+    }
+
+    pub fn on_write(&mut self, hook: MemoryHook) {
+        self.write_hooks.push(hook);
+    }
+
+    fn region_at(&self, address: usize) -> Option<usize> {
+        self.regions
+            .iter()
+            .position(|region| address >= region.start && address < region.end)
+    }
+}
+
+impl fmt::Debug for CrispAteMemory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CrispAteMemory")
+            .field("bytes", &self.bytes)
+            .field("mapped_regions", &self.regions.len())
+            .field("read_hooks", &self.read_hooks.len())
+            .field("write_hooks", &self.write_hooks.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod memory_tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Latch {
+        value: u8,
+    }
+
+    impl MemoryDevice for Latch {
+        fn read(&mut self, _offset: usize) -> u8 {
+            self.value
+        }
+
+        fn write(&mut self, offset: usize, value: u8) {
+            self.value = value.wrapping_add(offset as u8);
+        }
+    }
+
+    #[test]
+    fn can_read_and_write_bytes() {
+        let mut sut = CrispAteMemory::new();
+        sut.write(0x200, 0xAB);
+
+        assert_eq!(sut.read(0x200), 0xAB);
+        assert_eq!(sut.peek(0x200), 0xAB);
+        assert_eq!(sut.read(0x201), 0x00);
+    }
+
+    #[test]
+    fn calls_hooks_with_address_and_value() {
+        let mut sut = CrispAteMemory::new();
+        let seen: Rc<RefCell<Vec<(usize, u8)>>> = Rc::new(RefCell::new(Vec::new()));
+
+        let writes = Rc::clone(&seen);
+        sut.on_write(Box::new(move |address, value| {
+            writes.borrow_mut().push((address, value))
+        }));
+        let reads = Rc::clone(&seen);
+        sut.on_read(Box::new(move |address, value| {
+            reads.borrow_mut().push((address, value))
+        }));
+
+        sut.write(0x300, 0x12);
+        sut.read(0x300);
+        sut.load(0x400, &[0xFF]);
+
+        assert_eq!(*seen.borrow(), vec![(0x300, 0x12), (0x300, 0x12)]);
+    }
+
+    #[test]
+    fn routes_mapped_regions_to_their_device() {
+        let mut sut = CrispAteMemory::new();
+        sut.map(0xF00, 0xF10, Box::new(Latch { value: 0x42 }));
+
+        assert_eq!(sut.read(0xF00), 0x42);
+
+        sut.write(0xF02, 0x10);
+
+        assert_eq!(sut.read(0xF0F), 0x12);
+        assert_eq!(sut.peek(0xF02), 0x00);
+        assert_eq!(sut.read(0xF10), 0x00);
+    }
+
+    #[test]
+    fn peeks_big_endian_words_that_wrap_around() {
+        let mut sut = CrispAteMemory::new();
+        sut.load(0x200, &[0x12, 0x34]);
+        sut.load(MEMORY_SIZE - 1, &[0xAB]);
+        sut.load(0, &[0xCD]);
+
+        assert_eq!(sut.peek_word(0x200), 0x1234);
+        assert_eq!(sut.peek_word(MEMORY_SIZE - 1), 0xABCD);
+    }
+}
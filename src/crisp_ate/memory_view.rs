@@ -0,0 +1,268 @@
+use super::cpu::{CrispAte, FONTSET_ADDRESS, FONTSET_SIZE, PROGRAM_ADDRESS, REGISTER_NAMES};
+use super::memory::MEMORY_SIZE;
+
+pub const BYTES_PER_ROW: usize = 16;
+
+// how many frames a byte stays highlighted after it was written
+pub const WRITE_HIGHLIGHT_FRAMES: u8 = 30;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MemoryRegion {
+    Font,
+    Interpreter, // the rest of what sits below the program
+    Program,
+    Free, // after the program
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MemoryViewFocus {
+    Memory,
+    Registers,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MemoryViewAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    SwitchFocus, // between the bytes and the registers
+    Digit(u8),   // a hex digit typed over the selection
+}
+
+// The hex dump shown in debug mode (and on F4). It keeps the cursor and which
+// bytes changed lately, `display::draw_memory_view` does the drawing.
+pub struct CrispAteMemoryView {
+    pub visible: bool,
+    pub focus: MemoryViewFocus,
+    pub cursor: usize,   // the selected byte
+    pub register: usize, // the selected entry of REGISTER_NAMES
+    pub top: usize,      // the first row on screen
+    pub rows: usize,     // how many fit on screen, set by `scroll`
+    pub program_size: usize,
+    pub pending: Option<u8>, // the high digit of a byte being typed
+    previous: Vec<u8>,       // memory as of the last `update`
+    written: Vec<u8>,        // frames left to highlight each byte
+}
+
+impl CrispAteMemoryView {
+    pub fn new(program_size: usize) -> Self {
+        CrispAteMemoryView {
+            visible: false,
+            focus: MemoryViewFocus::Memory,
+            cursor: PROGRAM_ADDRESS,
+            register: 0,
+            top: PROGRAM_ADDRESS / BYTES_PER_ROW,
+            rows: 1,
+            program_size,
+            pending: None,
+            previous: Vec::new(),
+            written: vec![0; MEMORY_SIZE],
+        }
+    }
+
+    pub fn region(&self, address: usize) -> MemoryRegion {
+        if (FONTSET_ADDRESS..FONTSET_ADDRESS + FONTSET_SIZE).contains(&address) {
+            MemoryRegion::Font
+        } else if address < PROGRAM_ADDRESS {
+            MemoryRegion::Interpreter
+        } else if address < PROGRAM_ADDRESS + self.program_size {
+            MemoryRegion::Program
+        } else {
+            MemoryRegion::Free
+        }
+    }
+
+    // once per frame: finds the bytes written since the last one, however
+    // they were written (FX55, FX33, a debugger, the editing here)
+    pub fn update(&mut self, vm: &CrispAte) {
+        let first = self.previous.is_empty();
+        self.previous.resize(MEMORY_SIZE, 0);
+
+        for address in 0..MEMORY_SIZE {
+            let byte = vm.memory.peek(address);
+
+            if byte != self.previous[address] && !first {
+                self.written[address] = WRITE_HIGHLIGHT_FRAMES;
+            } else {
+                self.written[address] = self.written[address].saturating_sub(1);
+            }
+            self.previous[address] = byte;
+        }
+    }
+
+    // frames left, 0 for bytes that weren't written lately
+    pub fn written(&self, address: usize) -> u8 {
+        self.written[address]
+    }
+
+    // edits only happen while the machine is paused, so they can't race a
+    // running ROM
+    pub fn apply(&mut self, action: MemoryViewAction, vm: &mut CrispAte, paused: bool) {
+        if !matches!(action, MemoryViewAction::Digit(_)) {
+            self.pending = None;
+        }
+
+        let page = self.rows.max(1) * BYTES_PER_ROW;
+        let last = REGISTER_NAMES.len() - 1;
+
+        match (self.focus, action) {
+            (_, MemoryViewAction::SwitchFocus) => {
+                self.focus = match self.focus {
+                    MemoryViewFocus::Memory => MemoryViewFocus::Registers,
+                    MemoryViewFocus::Registers => MemoryViewFocus::Memory,
+                }
+            }
+            (_, MemoryViewAction::Digit(_)) if !paused => {}
+            (MemoryViewFocus::Memory, MemoryViewAction::Digit(digit)) => match self.pending {
+                None => self.pending = Some(digit & 0xF),
+                Some(high) => {
+                    vm.memory.load(self.cursor, &[high << 4 | digit & 0xF]);
+                    self.pending = None;
+                    self.cursor = (self.cursor + 1).min(MEMORY_SIZE - 1);
+                }
+            },
+            (MemoryViewFocus::Registers, MemoryViewAction::Digit(digit)) => {
+                // digits shift in from the right, dropping whatever no
+                // longer fits the register
+                let name = REGISTER_NAMES[self.register];
+                let value = vm.register(name).unwrap_or(0);
+                let shifted = value << 4 | digit as u16 & 0xF;
+
+                if vm.set_register(name, shifted).is_none() {
+                    vm.set_register(name, shifted & 0xFF);
+                }
+            }
+            (MemoryViewFocus::Memory, MemoryViewAction::Up) => {
+                self.cursor = self.cursor.saturating_sub(BYTES_PER_ROW)
+            }
+            (MemoryViewFocus::Memory, MemoryViewAction::Down) => {
+                self.cursor = (self.cursor + BYTES_PER_ROW).min(MEMORY_SIZE - 1)
+            }
+            (MemoryViewFocus::Memory, MemoryViewAction::Left) => {
+                self.cursor = self.cursor.saturating_sub(1)
+            }
+            (MemoryViewFocus::Memory, MemoryViewAction::Right) => {
+                self.cursor = (self.cursor + 1).min(MEMORY_SIZE - 1)
+            }
+            (MemoryViewFocus::Memory, MemoryViewAction::PageUp) => {
+                self.cursor = self.cursor.saturating_sub(page)
+            }
+            (MemoryViewFocus::Memory, MemoryViewAction::PageDown) => {
+                self.cursor = (self.cursor + page).min(MEMORY_SIZE - 1)
+            }
+            (MemoryViewFocus::Registers, MemoryViewAction::Up | MemoryViewAction::Left) => {
+                self.register = self.register.saturating_sub(1)
+            }
+            (MemoryViewFocus::Registers, MemoryViewAction::Down | MemoryViewAction::Right) => {
+                self.register = (self.register + 1).min(last)
+            }
+            (MemoryViewFocus::Registers, MemoryViewAction::PageUp) => self.register = 0,
+            (MemoryViewFocus::Registers, MemoryViewAction::PageDown) => self.register = last,
+        }
+    }
+
+    // keeps the cursor's row among the `rows` on screen
+    pub fn scroll(&mut self, rows: usize) {
+        self.rows = rows.max(1);
+        let row = self.cursor / BYTES_PER_ROW;
+
+        if row < self.top {
+            self.top = row;
+        } else if row >= self.top + self.rows {
+            self.top = row + 1 - self.rows;
+        }
+        self.top = self
+            .top
+            .min((MEMORY_SIZE / BYTES_PER_ROW).saturating_sub(self.rows));
+    }
+}
+
+#[cfg(test)]
+mod memory_view_tests {
+    use super::*;
+
+    #[test]
+    fn tells_regions_apart() {
+        let sut = CrispAteMemoryView::new(4);
+
+        assert_eq!(sut.region(0x00), MemoryRegion::Interpreter);
+        assert_eq!(sut.region(FONTSET_ADDRESS), MemoryRegion::Font);
+        assert_eq!(sut.region(FONTSET_ADDRESS + 79), MemoryRegion::Font);
+        assert_eq!(sut.region(0x1FF), MemoryRegion::Interpreter);
+        assert_eq!(sut.region(0x203), MemoryRegion::Program);
+        assert_eq!(sut.region(0x204), MemoryRegion::Free);
+    }
+
+    #[test]
+    fn highlights_writes_for_a_while() {
+        let mut vm = CrispAte::with_program(&[]);
+        let mut sut = CrispAteMemoryView::new(2);
+        sut.update(&vm);
+        assert_eq!(sut.written(0x50), 0);
+
+        // FX33 of 123 at I = 0x300
+        vm.memory.load(0x200, &[0xF0, 0x33]);
+        vm.registers.address = 0x300;
+        vm.set_register("V0", 123);
+        vm.emulation_cyle().unwrap();
+        sut.update(&vm);
+
+        assert_eq!(sut.written(0x300), WRITE_HIGHLIGHT_FRAMES);
+        assert_eq!(sut.written(0x302), WRITE_HIGHLIGHT_FRAMES);
+        assert_eq!(sut.written(0x303), 0);
+
+        for _ in 0..WRITE_HIGHLIGHT_FRAMES {
+            sut.update(&vm);
+        }
+        assert_eq!(sut.written(0x300), 0);
+    }
+
+    #[test]
+    fn edits_bytes_and_registers_only_while_paused() {
+        let mut vm = CrispAte::with_program(&[]);
+        let mut sut = CrispAteMemoryView::new(2);
+
+        sut.apply(MemoryViewAction::Digit(0xA), &mut vm, false);
+        sut.apply(MemoryViewAction::Digit(0xB), &mut vm, false);
+        assert_eq!(vm.memory.peek(0x200), 0);
+
+        sut.apply(MemoryViewAction::Down, &mut vm, true);
+        sut.apply(MemoryViewAction::Digit(0xA), &mut vm, true);
+        assert_eq!(sut.pending, Some(0xA));
+        sut.apply(MemoryViewAction::Digit(0xB), &mut vm, true);
+        assert_eq!(vm.memory.peek(0x210), 0xAB);
+        assert_eq!(sut.cursor, 0x211);
+
+        sut.apply(MemoryViewAction::SwitchFocus, &mut vm, true);
+        sut.apply(MemoryViewAction::Right, &mut vm, true);
+        for digit in [0x1, 0x2, 0x3] {
+            sut.apply(MemoryViewAction::Digit(digit), &mut vm, true);
+        }
+        // V1 keeps the last two digits
+        assert_eq!(vm.register("V1"), Some(0x23));
+
+        sut.apply(MemoryViewAction::PageDown, &mut vm, true);
+        sut.apply(MemoryViewAction::Digit(0x5), &mut vm, true);
+        assert_eq!(vm.register("ST"), Some(0x05));
+    }
+
+    #[test]
+    fn scrolls_to_the_cursor() {
+        let mut vm = CrispAte::with_program(&[]);
+        let mut sut = CrispAteMemoryView::new(2);
+        sut.scroll(8);
+        assert_eq!(sut.top, 0x20);
+
+        sut.apply(MemoryViewAction::PageDown, &mut vm, false);
+        sut.scroll(8);
+        assert_eq!(sut.cursor, 0x280);
+        assert_eq!(sut.top, 0x21);
+
+        sut.cursor = MEMORY_SIZE - 1;
+        sut.scroll(8);
+        assert_eq!(sut.top, 0xF8);
+    }
+}
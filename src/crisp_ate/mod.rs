@@ -1,4 +1,29 @@
+pub mod analyzer;
+pub mod capture;
+pub mod code_map;
+pub mod coverage;
 pub mod cpu;
+pub mod dap;
+pub mod decode_cache;
+pub mod disassembler;
 pub mod display;
+pub mod fault;
+pub mod filter;
+pub mod gdb;
+pub mod hud;
+pub mod json;
+pub mod library;
+pub mod memory;
+pub mod memory_view;
+pub mod movie;
+pub mod palette;
+pub mod profiler;
+pub mod random;
+pub mod recompiler;
 pub mod registers;
 mod runtime;
+pub mod savestate;
+pub mod screenshot;
+pub mod symbols;
+pub mod terminal;
+pub mod watch;
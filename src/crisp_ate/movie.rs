@@ -0,0 +1,374 @@
+use std::fs;
+use std::io;
+
+use super::cpu::CrispAte;
+use super::fault::FaultPolicy;
+use super::recompiler::CrispAteRecompiler;
+
+// A movie is a plain text file:
+//
+//   crisp-ate-movie 1
+//   core 0.1.0
+//   rom 9a3f...          (fnv1a of the ROM bytes)
+//   rng splitmix
+//   seed 1234
+//   fault-policy halt
+//   quirks default       (the only instruction behaviour this core has)
+//   checksum-interval 60
+//   frames
+//   0000                 (keypad bitmask for one frame, bit N = key N)
+//   0010 5e1c...         (keypad bitmask + machine checksum after that frame)
+//
+// A frame is one call to `emulation_cyle`. The frontend records every one it
+// runs, which is one per rendered frame normally and several in turbo.
+
+const MOVIE_MAGIC: &str = "crisp-ate-movie 1";
+
+pub const CORE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// how ambiguous instructions (shifts, FX55/FX65, jumps with offset) behave;
+// there is no way to change it yet
+pub const QUIRKS: &str = "default";
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MovieHeader {
+    pub core_version: String,
+    pub rom_hash: u64,
+    pub rng: String,
+    pub seed: u64,
+    pub fault_policy: FaultPolicy,
+    pub quirks: String,
+    pub checksum_interval: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct MovieFrame {
+    pub keys: u16,
+    pub checksum: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: usize,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CrispAteMovie {
+    pub header: MovieHeader,
+    pub frames: Vec<MovieFrame>,
+}
+
+pub fn keys_to_mask(keypad: &[bool; 16]) -> u16 {
+    keypad
+        .iter()
+        .enumerate()
+        .fold(0, |mask, (key, pressed)| mask | ((*pressed as u16) << key))
+}
+
+pub fn mask_to_keys(mask: u16) -> [bool; 16] {
+    let mut keypad = [false; 16];
+
+    for (key, pressed) in keypad.iter_mut().enumerate() {
+        *pressed = mask & (1 << key) != 0;
+    }
+
+    keypad
+}
+
+impl CrispAteMovie {
+    pub fn new(header: MovieHeader) -> Self {
+        CrispAteMovie {
+            header,
+            frames: Vec::new(),
+        }
+    }
+
+    // call after every emulated frame, with the keypad that frame ran with
+    pub fn record(&mut self, vm: &CrispAte) {
+        let frame_number = self.frames.len() + 1;
+        let checksum = match frame_number % self.header.checksum_interval {
+            0 => Some(vm.checksum()),
+            _ => None,
+        };
+
+        self.frames.push(MovieFrame {
+            keys: keys_to_mask(&vm.keypad),
+            checksum,
+        });
+    }
+
+    // replays every frame into `vm`, which must have been set up from the
+    // header (same ROM, rng, seed and fault policy)
+    // with a recompiler, frames run through it instead of the interpreter
+    pub fn play(
+        &self,
+        vm: &mut CrispAte,
+        mut recompiler: Option<&mut CrispAteRecompiler>,
+    ) -> Vec<Desync> {
+        let mut desyncs: Vec<Desync> = Vec::new();
+        let mut start = 0;
+
+        while start < self.frames.len() {
+            // frames holding the same keys run in one go, up to the next
+            // checksum
+            let keys = self.frames[start].keys;
+            let mut end = start + 1;
+            while end < self.frames.len()
+                && self.frames[end - 1].checksum.is_none()
+                && self.frames[end].keys == keys
+            {
+                end += 1;
+            }
+
+            vm.keypad = mask_to_keys(keys);
+            // a halted machine keeps reporting the same fault, the
+            // checksums below still tell whether that matches the recording
+            match recompiler.as_mut() {
+                Some(recompiler) => {
+                    let _ = recompiler.run(vm, end - start);
+                }
+                None => {
+                    for _ in start..end {
+                        let _ = vm.emulation_cyle();
+                    }
+                }
+            }
+
+            if let Some(expected) = self.frames[end - 1].checksum {
+                let actual = vm.checksum();
+
+                if actual != expected {
+                    desyncs.push(Desync {
+                        frame: end,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+
+            start = end;
+        }
+
+        desyncs
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        text.push_str(&format!("{}\n", MOVIE_MAGIC));
+        text.push_str(&format!("core {}\n", self.header.core_version));
+        text.push_str(&format!("rom {:016x}\n", self.header.rom_hash));
+        text.push_str(&format!("rng {}\n", self.header.rng));
+        text.push_str(&format!("seed {}\n", self.header.seed));
+        text.push_str(&format!(
+            "fault-policy {}\n",
+            self.header.fault_policy.name()
+        ));
+        text.push_str(&format!("quirks {}\n", self.header.quirks));
+        text.push_str(&format!(
+            "checksum-interval {}\n",
+            self.header.checksum_interval
+        ));
+        text.push_str("frames\n");
+
+        for frame in &self.frames {
+            let line = match frame.checksum {
+                Some(checksum) => format!("{:04x} {:016x}\n", frame.keys, checksum),
+                None => format!("{:04x}\n", frame.keys),
+            };
+
+            text.push_str(&line);
+        }
+
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+
+        if lines.next() != Some(MOVIE_MAGIC) {
+            return Err(String::from("Not a crisp-ate movie file"));
+        }
+
+        let mut field = |name: &str| -> Result<String, String> {
+            let line = lines.next().unwrap_or_default();
+
+            match line.split_once(' ') {
+                Some((key, value)) if key == name => Ok(value.to_owned()),
+                _ => Err(format!(
+                    "Expected '{}' in movie header, got '{}'",
+                    name, line
+                )),
+            }
+        };
+
+        let core_version = field("core")?;
+        let rom_hash = u64::from_str_radix(&field("rom")?, 16)
+            .map_err(|_| String::from("Invalid ROM hash in movie header"))?;
+        let rng = field("rng")?;
+        let seed = field("seed")?
+            .parse()
+            .map_err(|_| String::from("Invalid seed in movie header"))?;
+        let fault_policy = FaultPolicy::from_name(&field("fault-policy")?)
+            .ok_or_else(|| String::from("Invalid fault policy in movie header"))?;
+        let quirks = field("quirks")?;
+        let checksum_interval = field("checksum-interval")?
+            .parse()
+            .ok()
+            .filter(|interval| *interval > 0)
+            .ok_or_else(|| String::from("Invalid checksum interval in movie header"))?;
+
+        if lines.next() != Some("frames") {
+            return Err(String::from("Missing 'frames' in movie file"));
+        }
+
+        let mut frames: Vec<MovieFrame> = Vec::new();
+        for (index, line) in lines.enumerate() {
+            let invalid = || format!("Invalid movie frame {}: '{}'", index + 1, line);
+            let mut parts = line.split(' ');
+
+            let keys = parts
+                .next()
+                .and_then(|keys| u16::from_str_radix(keys, 16).ok())
+                .ok_or_else(invalid)?;
+            let checksum = match parts.next() {
+                Some(checksum) => Some(u64::from_str_radix(checksum, 16).map_err(|_| invalid())?),
+                None => None,
+            };
+
+            frames.push(MovieFrame { keys, checksum });
+        }
+
+        Ok(CrispAteMovie {
+            header: MovieHeader {
+                core_version,
+                rom_hash,
+                rng,
+                seed,
+                fault_policy,
+                quirks,
+                checksum_interval,
+            },
+            frames,
+        })
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        fs::write(filename, self.to_text())
+    }
+
+    pub fn load(filename: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(filename)
+            .map_err(|e| format!("Could not read movie file: {}", e))?;
+
+        CrispAteMovie::from_text(&text)
+    }
+}
+
+#[cfg(test)]
+mod movie_tests {
+    use super::*;
+    use crate::crisp_ate::random::create_random;
+
+    // waits for a key, picks a random byte, draws with both, loops
+    const PROGRAM: [u8; 12] = [
+        0xF0, 0x0A, // FX0A -> V0 = key
+        0xC1, 0xFF, // CXNN -> V1 = random
+        0xF1, 0x29, // FX29 -> I = char(V1)
+        0xD0, 0x15, // DXYN -> draw at (V0, V1)
+        0x12, 0x00, // 1NNN -> jump to 0x200
+        0x00, 0x00,
+    ];
+
+    fn create_vm(seed: u64) -> CrispAte {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        vm.random = create_random("splitmix", seed).unwrap();
+
+        vm
+    }
+
+    fn record(vm: &mut CrispAte, frames: usize) -> CrispAteMovie {
+        let mut movie = CrispAteMovie::new(MovieHeader {
+            core_version: String::from(CORE_VERSION),
+            rom_hash: 0xABCD,
+            rng: String::from("splitmix"),
+            seed: 7,
+            fault_policy: FaultPolicy::Halt,
+            quirks: String::from(QUIRKS),
+            checksum_interval: 4,
+        });
+
+        for frame in 0..frames {
+            vm.keypad = mask_to_keys(if frame % 3 == 0 { 1 << (frame % 16) } else { 0 });
+            vm.emulation_cyle().unwrap();
+            movie.record(vm);
+        }
+
+        movie
+    }
+
+    #[test]
+    fn can_convert_keypad_to_mask_and_back() {
+        let mut keypad = [false; 16];
+        keypad[0x1] = true;
+        keypad[0xF] = true;
+
+        assert_eq!(keys_to_mask(&keypad), 0x8002);
+        assert_eq!(mask_to_keys(0x8002), keypad);
+    }
+
+    #[test]
+    fn can_save_and_load_movie_text() {
+        let movie = record(&mut create_vm(7), 10);
+        let loaded = CrispAteMovie::from_text(&movie.to_text()).unwrap();
+
+        assert_eq!(loaded, movie);
+        assert_eq!(
+            loaded
+                .frames
+                .iter()
+                .filter(|f| f.checksum.is_some())
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn playback_matches_recording() {
+        let movie = record(&mut create_vm(7), 40);
+
+        assert_eq!(movie.play(&mut create_vm(7), None), vec![]);
+    }
+
+    #[test]
+    fn recompiled_playback_matches_recording() {
+        let movie = record(&mut create_vm(7), 40);
+        let mut vm = create_vm(7);
+        let mut recompiler = CrispAteRecompiler::new();
+
+        assert_eq!(movie.play(&mut vm, Some(&mut recompiler)), vec![]);
+        assert!(recompiler.compiled > 0);
+    }
+
+    #[test]
+    fn playback_flags_desyncs() {
+        let movie = record(&mut create_vm(7), 40);
+        let desyncs = movie.play(&mut create_vm(8), None);
+
+        assert!(!desyncs.is_empty());
+        assert_eq!(desyncs[0].frame % 4, 0);
+    }
+
+    #[test]
+    fn rejects_broken_movie_files() {
+        assert!(CrispAteMovie::from_text("hello").is_err());
+
+        let mut text = record(&mut create_vm(7), 2).to_text();
+        assert!(CrispAteMovie::from_text(&text.replace("quirks default\n", "")).is_err());
+        text.push_str("zzzz\n");
+
+        assert!(CrispAteMovie::from_text(&text).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::Path;
+
+pub type Rgb = [u8; 3];
+
+pub const PALETTE_NAMES: [&str; 4] = ["classic", "amber", "green", "octo"];
+
+// colors used to show the framebuffer, both on screen and in exported images.
+// There are four to cover XO-CHIP's two bitplanes, indexed by the planes a
+// pixel is lit in; plain CHIP-8 only ever uses the first two.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CrispAtePalette {
+    pub name: &'static str,
+    pub colors: [Rgb; 4], // unlit, plane 1, plane 2, both planes
+}
+
+impl Default for CrispAtePalette {
+    fn default() -> Self {
+        CrispAtePalette::new()
+    }
+}
+
+impl CrispAtePalette {
+    pub fn new() -> Self {
+        CrispAtePalette {
+            name: "classic",
+            colors: [[80, 80, 80], [255, 255, 255], [170, 170, 170], [30, 30, 30]],
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (name, colors) = match name {
+            "classic" => return Some(CrispAtePalette::new()),
+            "amber" => (
+                "amber",
+                [[40, 24, 0], [255, 176, 0], [176, 96, 0], [96, 48, 0]],
+            ),
+            "green" => (
+                "green",
+                [[0, 24, 0], [51, 255, 51], [0, 160, 64], [0, 96, 32]],
+            ),
+            // Octo's defaults: background, fill, fill2, blend
+            "octo" => (
+                "octo",
+                [
+                    [0x99, 0x66, 0x00],
+                    [0xFF, 0xCC, 0x00],
+                    [0xFF, 0x66, 0x00],
+                    [0x66, 0x22, 0x00],
+                ],
+            ),
+            _ => return None,
+        };
+
+        Some(CrispAtePalette { name, colors })
+    }
+
+    // a palette name, or 2 or 4 comma separated hex colors
+    // ("#000000,#ffffff" or "000000,ffffff,ff0000,00ff00")
+    pub fn parse(spec: &str) -> Option<Self> {
+        let spec = spec.trim();
+
+        if let Some(palette) = CrispAtePalette::from_name(spec) {
+            return Some(palette);
+        }
+
+        let parsed: Option<Vec<Rgb>> = spec.split(',').map(parse_hex_color).collect();
+        let colors = match parsed?.as_slice() {
+            [off, on] => [*off, *on, *on, *on],
+            [off, on, plane2, both] => [*off, *on, *plane2, *both],
+            _ => return None,
+        };
+
+        Some(CrispAtePalette {
+            name: "custom",
+            colors,
+        })
+    }
+
+    // per-ROM palettes live next to the ROM, `pong.ch8` -> `pong.palette`,
+    // holding anything `parse` accepts
+    pub fn for_rom(rom_filename: &str) -> Option<Self> {
+        let path = Path::new(rom_filename).with_extension("palette");
+        let spec = fs::read_to_string(path).ok()?;
+
+        CrispAtePalette::parse(&spec)
+    }
+
+    // the named palette after this one, for switching at runtime
+    pub fn next(&self) -> Self {
+        let index = PALETTE_NAMES
+            .iter()
+            .position(|name| *name == self.name)
+            .map_or(0, |index| (index + 1) % PALETTE_NAMES.len());
+
+        CrispAtePalette::from_name(PALETTE_NAMES[index]).unwrap_or_default()
+    }
+
+    // plain CHIP-8 pixels are lit in plane 1 only
+    pub fn color(&self, pixel: bool) -> Rgb {
+        self.plane_color(pixel as u8)
+    }
+
+    // somewhere between unlit (0) and lit (255), for fading pixels
+    pub fn blend(&self, level: u8) -> Rgb {
+        let (off, on) = (self.colors[0], self.colors[1]);
+        let mut rgb = [0u8; 3];
+
+        for channel in 0..3 {
+            let (from, to) = (off[channel] as u32, on[channel] as u32);
+            rgb[channel] = ((from * (255 - level as u32) + to * level as u32) / 255) as u8;
+        }
+
+        rgb
+    }
+
+    // `planes` has bit 0 set for plane 1 and bit 1 for plane 2
+    pub fn plane_color(&self, planes: u8) -> Rgb {
+        self.colors[(planes & 0b11) as usize]
+    }
+}
+
+fn parse_hex_color(text: &str) -> Option<Rgb> {
+    let text = text.trim();
+    let hex = text.strip_prefix('#').unwrap_or(text);
+
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).ok();
+
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod palette_tests {
+    use super::*;
+
+    #[test]
+    fn knows_every_named_palette() {
+        for name in PALETTE_NAMES {
+            assert_eq!(CrispAtePalette::from_name(name).unwrap().name, name);
+        }
+
+        assert!(CrispAtePalette::from_name("pink").is_none());
+    }
+
+    #[test]
+    fn parses_custom_hex_palettes() {
+        let two = CrispAtePalette::parse("#102030, ffffff").unwrap();
+        assert_eq!(two.name, "custom");
+        assert_eq!(two.color(false), [0x10, 0x20, 0x30]);
+        assert_eq!(two.plane_color(3), [0xFF, 0xFF, 0xFF]);
+
+        let four = CrispAtePalette::parse("000000,111111,222222,333333").unwrap();
+        assert_eq!(four.plane_color(2), [0x22, 0x22, 0x22]);
+
+        assert!(CrispAtePalette::parse("#fff,#000").is_none());
+        assert!(CrispAtePalette::parse("000000,111111,222222").is_none());
+        assert!(CrispAtePalette::parse("zzzzzz,111111").is_none());
+    }
+
+    #[test]
+    fn blends_between_unlit_and_lit() {
+        let palette = CrispAtePalette::parse("000000,ff8040").unwrap();
+
+        assert_eq!(palette.blend(0), [0x00, 0x00, 0x00]);
+        assert_eq!(palette.blend(255), [0xFF, 0x80, 0x40]);
+        assert_eq!(palette.blend(128), [0x80, 0x40, 0x20]);
+    }
+
+    #[test]
+    fn cycles_through_named_palettes() {
+        let mut palette = CrispAtePalette::parse("000000,ffffff").unwrap();
+        let mut seen: Vec<&str> = Vec::new();
+
+        for _ in 0..PALETTE_NAMES.len() {
+            palette = palette.next();
+            seen.push(palette.name);
+        }
+
+        assert_eq!(seen, PALETTE_NAMES);
+        assert_eq!(palette.next().name, "classic");
+    }
+}
@@ -0,0 +1,227 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+
+use super::disassembler::disassemble;
+use super::memory::{CrispAteMemory, MEMORY_SIZE};
+use super::runtime::{CrispAteRuntime, STACK_SIZE};
+use super::symbols::CrispAteSymbols;
+use crate::utils::hex;
+
+// where execution starts, the root of every call stack
+const ENTRY_ADDRESS: u16 = 0x200;
+
+// how many addresses the report lists
+const HOT_ADDRESSES: usize = 20;
+
+// Counts what `CrispAte::emulation_cyle` runs while `CrispAte::profiler` is
+// set: every address, every kind of instruction and every chain of
+// subroutines the instructions ran in.
+#[derive(Debug)]
+pub struct CrispAteProfiler {
+    pub instructions: u64,
+    pub addresses: Vec<u64>,                  // executions of each address
+    pub classes: BTreeMap<&'static str, u64>, // by `CrispsAteDecodedOpcodes::name`
+    pub stacks: HashMap<Vec<u16>, u64>,       // by the subroutines called, outermost first
+    frames: Vec<u16>,                         // reused for every instruction
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        total => count as f64 * 100.0 / total as f64,
+    }
+}
+
+fn frame_name(address: u16, symbols: &CrispAteSymbols) -> String {
+    match symbols.label(address) {
+        Some(name) => name.to_owned(),
+        None => hex(address),
+    }
+}
+
+impl Default for CrispAteProfiler {
+    fn default() -> Self {
+        CrispAteProfiler::new()
+    }
+}
+
+impl CrispAteProfiler {
+    pub fn new() -> Self {
+        CrispAteProfiler {
+            instructions: 0,
+            addresses: vec![0; MEMORY_SIZE],
+            classes: BTreeMap::new(),
+            stacks: HashMap::new(),
+            frames: Vec::with_capacity(STACK_SIZE + 1),
+        }
+    }
+
+    // Before the instruction at `address` runs. The stack holds the address
+    // of each 2NNN that is still running, the subroutine is the one it calls.
+    pub fn record(
+        &mut self,
+        address: u16,
+        class: &'static str,
+        memory: &CrispAteMemory,
+        runtime: &CrispAteRuntime,
+    ) {
+        self.instructions += 1;
+        self.addresses[address as usize % MEMORY_SIZE] += 1;
+        *self.classes.entry(class).or_insert(0) += 1;
+
+        self.frames.clear();
+        self.frames.push(ENTRY_ADDRESS);
+        for caller in &runtime.stack[..runtime.stack_pointer.min(STACK_SIZE)] {
+            let caller = *caller as usize % MEMORY_SIZE;
+            let opcode = memory.peek_word(caller);
+
+            self.frames.push(match opcode >> 12 {
+                0x2 => opcode & 0xFFF,
+                _ => caller as u16, // rewritten since, the call site will do
+            });
+        }
+
+        match self.stacks.get_mut(&self.frames) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.frames.clone(), 1);
+            }
+        }
+    }
+
+    // the most executed addresses and the instruction classes, busiest first
+    pub fn report(&self, memory: &CrispAteMemory, symbols: &CrispAteSymbols) -> String {
+        let mut report = format!("Instructions executed: {}\n", self.instructions);
+
+        let mut addresses: Vec<(usize, u64)> = self
+            .addresses
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        report.push_str("\nHot addresses:\n");
+        for (address, count) in addresses.iter().take(HOT_ADDRESSES) {
+            let opcode = memory.peek_word(*address);
+
+            report.push_str(&format!(
+                "{:>12} {:>6.2}%  {:<24} {}\n",
+                count,
+                percent(*count, self.instructions),
+                symbols.describe(*address as u16),
+                disassemble(opcode, symbols)
+            ));
+        }
+
+        let mut classes: Vec<(&str, u64)> = self
+            .classes
+            .iter()
+            .map(|(class, count)| (*class, *count))
+            .collect();
+        classes.sort_by_key(|(_, count)| Reverse(*count));
+
+        report.push_str("\nInstructions:\n");
+        for (class, count) in classes {
+            report.push_str(&format!(
+                "{:>12} {:>6.2}%  {}\n",
+                count,
+                percent(count, self.instructions),
+                class
+            ));
+        }
+
+        report
+    }
+
+    // One line per call stack, `main;update;draw 1234`, which is what
+    // flamegraph.pl and inferno read. Subroutines without a label go by
+    // their address.
+    pub fn folded(&self, symbols: &CrispAteSymbols) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(frames, count)| {
+                let names: Vec<String> = frames
+                    .iter()
+                    .map(|address| frame_name(*address, symbols))
+                    .collect();
+                format!("{} {}", names.join(";"), count)
+            })
+            .collect();
+        lines.sort();
+
+        let mut folded = lines.join("\n");
+        folded.push('\n');
+        folded
+    }
+
+    pub fn save_report(
+        &self,
+        filename: &str,
+        memory: &CrispAteMemory,
+        symbols: &CrispAteSymbols,
+    ) -> io::Result<()> {
+        fs::write(filename, self.report(memory, symbols))
+    }
+
+    pub fn save_folded(&self, filename: &str, symbols: &CrispAteSymbols) -> io::Result<()> {
+        fs::write(filename, self.folded(symbols))
+    }
+}
+
+#[cfg(test)]
+mod profiler_tests {
+    use super::*;
+    use crate::crisp_ate::cpu::CrispAte;
+    use std::path::Path;
+
+    // 0x200 call 0x206, 0x202 call 0x206, 0x204 jump 0x204,
+    // 0x206 6001, 0x208 return
+    const PROGRAM: [u8; 10] = [0x22, 0x06, 0x22, 0x06, 0x12, 0x04, 0x60, 0x01, 0x00, 0xEE];
+
+    fn run(cycles: usize) -> CrispAte {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        vm.profiler = Some(CrispAteProfiler::new());
+
+        for _ in 0..cycles {
+            vm.emulation_cyle().unwrap();
+        }
+        vm
+    }
+
+    #[test]
+    fn counts_addresses_and_classes() {
+        let vm = run(9);
+        let sut = vm.profiler.as_ref().unwrap();
+
+        assert_eq!(sut.instructions, 9);
+        assert_eq!(sut.addresses[0x206], 2);
+        assert_eq!(sut.addresses[0x204], 3);
+        assert_eq!(sut.classes["CallSubRoutine (2NNN)"], 2);
+        assert_eq!(sut.classes["Jump (1NNN)"], 3);
+
+        let report = sut.report(&vm.memory, &CrispAteSymbols::new());
+        assert!(report.starts_with("Instructions executed: 9\n"));
+        assert!(report.contains("33.33%  0x204"));
+        assert!(report.contains("jump 0x204"));
+    }
+
+    #[test]
+    fn folds_call_stacks() {
+        let vm = run(9);
+        let sut = vm.profiler.as_ref().unwrap();
+        let symbols =
+            CrispAteSymbols::parse("label 0x200 main\nlabel 0x206 update\n", Path::new(""))
+                .unwrap();
+
+        assert_eq!(sut.folded(&symbols), "main 5\nmain;update 4\n");
+        assert_eq!(
+            sut.folded(&CrispAteSymbols::new()),
+            "0x200 5\n0x200;0x206 4\n"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+
+use super::memory::CrispAteMemory;
+
+// where CXNN gets its random bytes from. Everything a source needs to
+// continue a sequence must fit in `state`, so it can be stored and restored
+// alongside the rest of the machine
+pub trait RandomSource: fmt::Debug {
+    fn name(&self) -> &'static str;
+    fn seed(&self) -> u64;
+    fn state(&self) -> u64;
+    fn restore(&mut self, state: u64);
+
+    // called once per fetched instruction
+    fn tick(&mut self) {}
+
+    fn next_byte(&mut self, memory: &CrispAteMemory) -> u8;
+}
+
+pub fn create_random(name: &str, seed: u64) -> Option<Box<dyn RandomSource>> {
+    match name {
+        "splitmix" => Some(Box::new(SplitMixRandom::new(seed))),
+        "vip" => Some(Box::new(CosmacVipRandom::new(seed))),
+        _ => None,
+    }
+}
+
+// SplitMix64, any seed (including 0) gives a good sequence
+#[derive(Debug)]
+pub struct SplitMixRandom {
+    seed: u64,
+    state: u64,
+}
+
+impl SplitMixRandom {
+    pub fn new(seed: u64) -> Self {
+        SplitMixRandom { seed, state: seed }
+    }
+}
+
+impl RandomSource for SplitMixRandom {
+    fn name(&self) -> &'static str {
+        "splitmix"
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn restore(&mut self, state: u64) {
+        self.state = state;
+    }
+
+    fn next_byte(&mut self, _memory: &CrispAteMemory) -> u8 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        (z >> 56) as u8
+    }
+}
+
+// Modelled on the COSMAC VIP interpreter: a counter that moves with every
+// instruction picks a byte out of a page of code, which is added into the
+// running value CXNN hands out. Results depend on how many instructions ran
+// before, just like on the real machine. The VIP reads its own interpreter
+// (0x100-0x1FF); there is no interpreter in memory here, so the bytes come
+// from the first page of the program (0x200-0x2FF) instead.
+#[derive(Debug)]
+pub struct CosmacVipRandom {
+    seed: u64,
+    counter: u8,
+    value: u8,
+}
+
+impl CosmacVipRandom {
+    pub fn new(seed: u64) -> Self {
+        CosmacVipRandom {
+            seed,
+            counter: seed as u8,
+            value: (seed >> 8) as u8,
+        }
+    }
+}
+
+impl RandomSource for CosmacVipRandom {
+    fn name(&self) -> &'static str {
+        "vip"
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn state(&self) -> u64 {
+        (self.value as u64) << 8 | self.counter as u64
+    }
+
+    fn restore(&mut self, state: u64) {
+        self.counter = state as u8;
+        self.value = (state >> 8) as u8;
+    }
+
+    fn tick(&mut self) {
+        self.counter = self.counter.wrapping_add(1);
+    }
+
+    fn next_byte(&mut self, memory: &CrispAteMemory) -> u8 {
+        let page_byte = memory.peek(0x200 + self.counter as usize);
+        self.value = self
+            .value
+            .wrapping_add(page_byte)
+            .wrapping_add(self.counter);
+
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod random_tests {
+    use super::*;
+
+    fn sequence(random: &mut dyn RandomSource, memory: &CrispAteMemory) -> Vec<u8> {
+        (0..16)
+            .map(|_| {
+                random.tick();
+                random.next_byte(memory)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_sequence() {
+        let memory = CrispAteMemory::new();
+
+        for name in ["splitmix", "vip"] {
+            let mut first = create_random(name, 1234).unwrap();
+            let mut second = create_random(name, 1234).unwrap();
+
+            assert_eq!(
+                sequence(first.as_mut(), &memory),
+                sequence(second.as_mut(), &memory)
+            );
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_sequences() {
+        let memory = CrispAteMemory::new();
+        let mut first = SplitMixRandom::new(1);
+        let mut second = SplitMixRandom::new(2);
+
+        assert_ne!(
+            sequence(&mut first, &memory),
+            sequence(&mut second, &memory)
+        );
+    }
+
+    #[test]
+    fn vip_mixes_in_the_program() {
+        let empty = CrispAteMemory::new();
+        let mut program = CrispAteMemory::new();
+        program.load(0x200, &[0x60, 0x01, 0xC1, 0x0F, 0x12, 0x02]);
+
+        assert_ne!(
+            sequence(&mut CosmacVipRandom::new(0xFF), &empty),
+            sequence(&mut CosmacVipRandom::new(0xFF), &program)
+        );
+    }
+
+    #[test]
+    fn can_restore_state_mid_sequence() {
+        let memory = CrispAteMemory::new();
+
+        for name in ["splitmix", "vip"] {
+            let mut sut = create_random(name, 42).unwrap();
+            sequence(sut.as_mut(), &memory);
+
+            let state = sut.state();
+            let expected = sequence(sut.as_mut(), &memory);
+            sut.restore(state);
+
+            assert_eq!(sequence(sut.as_mut(), &memory), expected);
+            assert_eq!(sut.seed(), 42);
+        }
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        assert!(create_random("dice", 0).is_none());
+    }
+}
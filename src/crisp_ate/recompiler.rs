@@ -0,0 +1,333 @@
+use super::cpu::{decode_opcode, CrispAte};
+use super::fault::{CrispAteFault, Fault};
+use super::memory::MEMORY_SIZE;
+use super::registers::{CrispsAteDecodedOpcodes, VF};
+
+// the most instructions compiled into one block
+const MAX_BLOCK_SIZE: usize = 64;
+
+type CompiledInstruction = Box<dyn Fn(&mut CrispAte) -> Result<(), Fault>>;
+
+// A run of instructions that only leaves through its last one: straight-line
+// code up to the first jump, call, return, skip, key wait or memory write.
+struct CompiledBlock {
+    opcodes: Vec<u16>, // what it was compiled from
+    instructions: Vec<CompiledInstruction>,
+}
+
+// An execution engine for batch runs that compiles the ROM, block by block,
+// into closures and runs those instead of fetching and decoding every
+// instruction. It gives the same results as `CrispAte::emulation_cyle`, and
+// falls back to it for code the ROM wrote itself and altogether while the
+// history, the profiler or coverage are on. Instruction fetches don't go through the
+// memory bus, so read hooks don't see them.
+pub struct CrispAteRecompiler {
+    blocks: Vec<Option<CompiledBlock>>, // by the address they start at
+    pub compiled: u64,                  // blocks compiled
+    pub interpreted: u64,               // instructions left to the interpreter
+}
+
+// rewritten by the ROM and not run since
+fn is_written(vm: &CrispAte, address: usize) -> bool {
+    vm.code_map.is_written(address) || vm.code_map.is_written(address + 1)
+}
+
+// whether the block ends with this instruction
+fn ends_block(decoded: CrispsAteDecodedOpcodes) -> bool {
+    matches!(
+        decoded,
+        CrispsAteDecodedOpcodes::Jump(_)
+            | CrispsAteDecodedOpcodes::JumpToAddress(_)
+            | CrispsAteDecodedOpcodes::CallSubRoutine(_)
+            | CrispsAteDecodedOpcodes::Return
+            | CrispsAteDecodedOpcodes::SkipIfVXEquals(_, _)
+            | CrispsAteDecodedOpcodes::SkipIfVXNotEqual(_, _)
+            | CrispsAteDecodedOpcodes::SkipIfVXEqualsVY(_, _)
+            | CrispsAteDecodedOpcodes::SkipIfVXNotEqualVY(_, _)
+            | CrispsAteDecodedOpcodes::SkipIfKeyAtVXIsPressed(_)
+            | CrispsAteDecodedOpcodes::SkipIfKeyAtVXIsNotPressed(_)
+            | CrispsAteDecodedOpcodes::GetKeyToVX(_)
+            | CrispsAteDecodedOpcodes::StoreBinaryCodedDecimalVX(_)
+            | CrispsAteDecodedOpcodes::StoreFromV0ToVXStartingFromI(_)
+    )
+}
+
+// The instructions that make up most of a ROM get a closure of their own,
+// everything else goes through `CrispAte::execute`.
+fn compile(decoded: CrispsAteDecodedOpcodes) -> CompiledInstruction {
+    match decoded {
+        CrispsAteDecodedOpcodes::SetVX(x, value) => Box::new(move |vm| {
+            vm.registers.v[x] = value;
+            vm.registers.program_counter += 2;
+            Ok(())
+        }),
+        CrispsAteDecodedOpcodes::AddToVX(x, value) => Box::new(move |vm| {
+            vm.registers.v[x] = vm.registers.v[x].wrapping_add(value);
+            vm.registers.program_counter += 2;
+            Ok(())
+        }),
+        CrispsAteDecodedOpcodes::SetVXToVY(x, y) => Box::new(move |vm| {
+            vm.registers.v[x] = vm.registers.v[y];
+            vm.registers.program_counter += 2;
+            Ok(())
+        }),
+        CrispsAteDecodedOpcodes::SetVXToVXorVY(x, y) => Box::new(move |vm| {
+            vm.registers.v[x] |= vm.registers.v[y];
+            vm.registers.program_counter += 2;
+            Ok(())
+        }),
+        CrispsAteDecodedOpcodes::SetVXToVXandVY(x, y) => Box::new(move |vm| {
+            vm.registers.v[x] &= vm.registers.v[y];
+            vm.registers.program_counter += 2;
+            Ok(())
+        }),
+        CrispsAteDecodedOpcodes::SetVXToVXxorVY(x, y) => Box::new(move |vm| {
+            vm.registers.v[x] ^= vm.registers.v[y];
+            vm.registers.program_counter += 2;
+            Ok(())
+        }),
+        CrispsAteDecodedOpcodes::AddVYtoVX(x, y) => Box::new(move |vm| {
+            let (sum, carry) = vm.registers.v[x].overflowing_add(vm.registers.v[y]);
+            vm.registers.v[x] = sum;
+            vm.registers.v[VF] = carry as u8;
+            vm.registers.program_counter += 2;
+            Ok(())
+        }),
+        CrispsAteDecodedOpcodes::SetIAddress(address) => Box::new(move |vm| {
+            vm.registers.address = address;
+            vm.registers.program_counter += 2;
+            Ok(())
+        }),
+        CrispsAteDecodedOpcodes::Jump(address) => Box::new(move |vm| {
+            vm.registers.program_counter = address;
+            Ok(())
+        }),
+        CrispsAteDecodedOpcodes::SkipIfVXEquals(x, value) => Box::new(move |vm| {
+            vm.registers.program_counter += match vm.registers.v[x] == value {
+                true => 4,
+                false => 2,
+            };
+            Ok(())
+        }),
+        CrispsAteDecodedOpcodes::SkipIfVXNotEqual(x, value) => Box::new(move |vm| {
+            vm.registers.program_counter += match vm.registers.v[x] != value {
+                true => 4,
+                false => 2,
+            };
+            Ok(())
+        }),
+        decoded => Box::new(move |vm| vm.execute(decoded)),
+    }
+}
+
+impl Default for CrispAteRecompiler {
+    fn default() -> Self {
+        CrispAteRecompiler::new()
+    }
+}
+
+impl CrispAteRecompiler {
+    pub fn new() -> Self {
+        CrispAteRecompiler {
+            blocks: (0..MEMORY_SIZE).map(|_| None).collect(),
+            compiled: 0,
+            interpreted: 0,
+        }
+    }
+
+    // None when the ROM rewrote the first instruction
+    fn compile_block(vm: &CrispAte, start: usize) -> Option<CompiledBlock> {
+        let mut block = CompiledBlock {
+            opcodes: Vec::new(),
+            instructions: Vec::new(),
+        };
+        let mut address = start;
+
+        while block.instructions.len() < MAX_BLOCK_SIZE
+            && address + 1 < MEMORY_SIZE
+            && !is_written(vm, address)
+        {
+            let opcode = vm.memory.peek_word(address);
+            let decoded = decode_opcode(opcode);
+
+            block.opcodes.push(opcode);
+            block.instructions.push(compile(decoded));
+            address += 2;
+
+            if ends_block(decoded) {
+                break;
+            }
+        }
+
+        match block.instructions.is_empty() {
+            true => None,
+            false => Some(block),
+        }
+    }
+
+    // the block starting at `start`, compiled again when its code changed
+    fn block(&mut self, vm: &CrispAte, start: usize) -> Option<&CompiledBlock> {
+        let stale = match &self.blocks[start] {
+            Some(block) => block.opcodes.iter().enumerate().any(|(index, opcode)| {
+                let address = start + index * 2;
+                is_written(vm, address) || vm.memory.peek_word(address) != *opcode
+            }),
+            None => true,
+        };
+
+        if stale {
+            self.blocks[start] = CrispAteRecompiler::compile_block(vm, start);
+            self.compiled += self.blocks[start].is_some() as u64;
+        }
+
+        self.blocks[start].as_ref()
+    }
+
+    // the same as `cycles` calls to `vm.emulation_cyle()`
+    pub fn run(&mut self, vm: &mut CrispAte, cycles: usize) -> Result<(), CrispAteFault> {
+        let interpret = vm.registers.trace || vm.profiler.is_some() || vm.coverage.is_some();
+        let mut remaining = cycles;
+
+        while remaining > 0 {
+            if let Some(fault) = vm.fault {
+                return Err(fault);
+            }
+
+            let start = vm.registers.program_counter as usize;
+            let block = match interpret || start + 1 >= MEMORY_SIZE {
+                true => None,
+                false => self.block(vm, start),
+            };
+
+            let block = match block {
+                Some(block) => block,
+                None => {
+                    self.interpreted += 1;
+                    remaining -= 1;
+                    vm.emulation_cyle()?;
+                    continue;
+                }
+            };
+
+            let ran = block.instructions.len().min(remaining);
+            for (index, instruction) in block.instructions[..ran].iter().enumerate() {
+                let address = start + index * 2;
+
+                vm.code_map.fetch(address);
+                vm.random.tick();
+                if let Err(fault) = instruction(vm) {
+                    return vm.raise(fault, address as u16, block.opcodes[index]);
+                }
+                vm.tick_timers();
+            }
+            remaining -= ran;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod recompiler_tests {
+    use super::*;
+    use crate::crisp_ate::fault::FaultPolicy;
+    use crate::crisp_ate::memory::CrispAteMemory;
+    use crate::crisp_ate::random::{create_random, RandomSource, SplitMixRandom};
+
+    fn vm(program: &[u8], fault_policy: FaultPolicy) -> CrispAte {
+        let mut vm = CrispAte::with_program(program);
+        vm.fault_policy = fault_policy;
+        vm.random = create_random("vip", 7).unwrap();
+        vm
+    }
+
+    // runs the program on the interpreter and the recompiler side by side,
+    // comparing the whole machine every `step` instructions
+    fn differential(program: &[u8], fault_policy: FaultPolicy, cycles: usize, step: usize) {
+        let mut interpreter = vm(program, fault_policy);
+        let mut recompiled = vm(program, fault_policy);
+        let mut sut = CrispAteRecompiler::new();
+
+        for _ in 0..cycles / step {
+            let expected = (0..step).try_for_each(|_| interpreter.emulation_cyle());
+            let actual = sut.run(&mut recompiled, step);
+
+            assert_eq!(actual, expected);
+            assert_eq!(recompiled.checksum(), interpreter.checksum());
+            assert_eq!(recompiled.registers.v, interpreter.registers.v);
+            assert_eq!(
+                recompiled.registers.program_counter,
+                interpreter.registers.program_counter
+            );
+        }
+        assert!(sut.compiled > 0);
+    }
+
+    #[test]
+    fn matches_the_interpreter() {
+        // a countdown drawing digits with BCD, calling a subroutine
+        // and pulling random numbers
+        let program = [
+            0x60, 0x40, // 0x200 v0 := 0x40
+            0x6E, 0x00, // 0x202 ve := 0x00
+            0xA3, 0x00, // 0x204 i := 0x300
+            0xF0, 0x33, // 0x206 bcd v0
+            0xF2, 0x65, // 0x208 load v2
+            0xF1, 0x29, // 0x20A i := hex v1
+            0xD5, 0xE5, // 0x20C sprite v5 ve 5
+            0x22, 0x1E, // 0x20E call 0x21E
+            0x70, 0xFF, // 0x210 v0 += 0xFF
+            0x84, 0x04, // 0x212 v4 += v0
+            0x84, 0x06, // 0x214 v4 >>= v4
+            0x30, 0x00, // 0x216 if v0 != 0x00 then
+            0x12, 0x04, // 0x218 jump 0x204
+            0x12, 0x1A, // 0x21A jump 0x21A
+            0x00, 0x00, // 0x21C
+            0xC3, 0x0F, // 0x21E v3 := random 0x0F
+            0x85, 0x34, // 0x220 v5 += v3
+            0xF3, 0x15, // 0x222 delay := v3
+            0x00, 0xEE, // 0x224 return
+        ];
+
+        differential(&program, FaultPolicy::Halt, 3000, 7);
+        differential(&program, FaultPolicy::Halt, 3000, 100);
+    }
+
+    #[test]
+    fn falls_back_on_self_modifying_code() {
+        // 0x200 i := 0x20A, 0x202 v0 := 0x60, 0x204 jump 0x20A, 0x206 save v0,
+        // 0x208 v1 := 0x07, 0x20A jump 0x206 (then v0 := 0x06), 0x20C jump 0x20C
+        let program = [
+            0xA2, 0x0A, 0x60, 0x60, 0x12, 0x0A, 0xF0, 0x55, 0x61, 0x07, 0x12, 0x06, 0x12, 0x0C,
+        ];
+        differential(&program, FaultPolicy::Halt, 20, 1);
+
+        let mut vm = vm(&program, FaultPolicy::Halt);
+        let mut sut = CrispAteRecompiler::new();
+        sut.run(&mut vm, 20).unwrap();
+        assert_eq!(vm.registers.program_counter, 0x20C);
+        assert_eq!(sut.interpreted, 1);
+    }
+
+    #[test]
+    fn faults_like_the_interpreter() {
+        // 0x200 v0 := 0x01, 0x202 call 0x202
+        let program = [0x60, 0x01, 0x22, 0x02];
+        differential(&program, FaultPolicy::Halt, 20, 5);
+        differential(&program, FaultPolicy::Wrap, 100, 3);
+    }
+
+    #[test]
+    fn matches_the_interpreter_on_random_programs() {
+        let mut random = SplitMixRandom::new(1);
+        let memory = CrispAteMemory::new();
+
+        for _ in 0..20 {
+            let program: Vec<u8> = (0..512).map(|_| random.next_byte(&memory)).collect();
+
+            for fault_policy in [FaultPolicy::Halt, FaultPolicy::Wrap, FaultPolicy::Ignore] {
+                differential(&program, fault_policy, 1000, 50);
+            }
+        }
+    }
+}
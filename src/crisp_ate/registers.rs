@@ -1,8 +1,9 @@
 use std::fmt;
+use std::ops::{Index, IndexMut};
 
 use crate::utils::hex;
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone, Copy)]
 pub enum CrispsAteDecodedOpcodes {
     // TO-DO -> fix: 0NNN, 1NNN, 2NNN, ANNN, BNNN, DXYN
     // 12-bit max! (0-4095) 16-bit is too large (0-65535)
@@ -11,42 +12,43 @@ pub enum CrispsAteDecodedOpcodes {
     Return,                                       // 00EE
     Jump(u16),                                    // 1NNN (NNN)
     CallSubRoutine(u16),                          // 2NNN (NNN)
-    SkipIfVXEquals(u16, u16),                     // 3XNN (X, NN)
-    SkipIfVXNotEqual(u16, u16),                   // 4XNN (X, NN)
-    SkipIfVXEqualsVY(u16, u16),                   // 5XY0 (X, Y)
-    SetVX(u16, u16),                              // 6XNN (X, NN)
-    AddToVX(u16, u16),                            // 7XNN (X, NN)
-    SetVXToVY(u16, u16),                          // 8XY0 (X, Y)
-    SetVXToVXorVY(u16, u16),                      // 8XY1 (X, Y)
-    SetVXToVXandVY(u16, u16),                     // 8XY2 (X, Y)
-    SetVXToVXxorVY(u16, u16),                     // 8XY3 (X, Y)
-    AddVYtoVX(u16, u16),                          // 8XY4 (X, Y)
-    SubtractVYFromVX(u16, u16),                   // 8XY5 (X, Y)
-    StoreLeastBitOfVXAndShiftVXRight(u16),        // 8XY6 (X)
-    SetVXToVYMinusVX(u16, u16),                   // 8XY7 (X, Y)
-    StoreMostBitOfVXAndShiftVXLeft(u16),          // 8XYE (X)
-    SkipIfVXNotEqualVY(u16, u16),                 // 9XY0 (X, y)
+    SkipIfVXEquals(VReg, u8),                     // 3XNN (X, NN)
+    SkipIfVXNotEqual(VReg, u8),                   // 4XNN (X, NN)
+    SkipIfVXEqualsVY(VReg, VReg),                 // 5XY0 (X, Y)
+    SetVX(VReg, u8),                              // 6XNN (X, NN)
+    AddToVX(VReg, u8),                            // 7XNN (X, NN)
+    SetVXToVY(VReg, VReg),                        // 8XY0 (X, Y)
+    SetVXToVXorVY(VReg, VReg),                    // 8XY1 (X, Y)
+    SetVXToVXandVY(VReg, VReg),                   // 8XY2 (X, Y)
+    SetVXToVXxorVY(VReg, VReg),                   // 8XY3 (X, Y)
+    AddVYtoVX(VReg, VReg),                        // 8XY4 (X, Y)
+    SubtractVYFromVX(VReg, VReg),                 // 8XY5 (X, Y)
+    StoreLeastBitOfVXAndShiftVXRight(VReg),       // 8XY6 (X)
+    SetVXToVYMinusVX(VReg, VReg),                 // 8XY7 (X, Y)
+    StoreMostBitOfVXAndShiftVXLeft(VReg),         // 8XYE (X)
+    SkipIfVXNotEqualVY(VReg, VReg),               // 9XY0 (X, y)
     SetIAddress(u16),                             // ANNN (NNN)
     JumpToAddress(u16),                           // BNNN (NNN)
-    SetVXToBitwiseANDWithSaltAndRandom(u16, u16), // CXNN (X, NN)
-    DrawSpriteAt(u16, u16, u16),                  // DXYN (X, Y, N)
-    SkipIfKeyAtVXIsPressed(u16),                  // EX9E (X)
-    SkipIfKeyAtVXIsNotPressed(u16),               // EXA1 (X)
-    SetVXToDelayValue(u16),                       // FX07 (X)
-    GetKeyToVX(u16),                              // FX0A (X)
-    SetDelayToVX(u16),                            // FX15 (X)
-    SetSoundToVX(u16),                            // FX18 (X)
-    AddVXToI(u16),                                // FX1E (X)
-    SetIToLocationOfVXChar(u16),                  // FX29 (X)
-    StoreBinaryCodedDecimalVX(u16),               // FX33 (X)
-    StoreFromV0ToVXStartingFromI(u16),            // FX55 (X)
-    FillFromV0ToVXStartingFromI(u16),             // FX65 (X)
+    SetVXToBitwiseANDWithSaltAndRandom(VReg, u8), // CXNN (X, NN)
+    DrawSpriteAt(VReg, VReg, u16),                // DXYN (X, Y, N)
+    SkipIfKeyAtVXIsPressed(VReg),                 // EX9E (X)
+    SkipIfKeyAtVXIsNotPressed(VReg),              // EXA1 (X)
+    SetVXToDelayValue(VReg),                      // FX07 (X)
+    GetKeyToVX(VReg),                             // FX0A (X)
+    SetDelayToVX(VReg),                           // FX15 (X)
+    SetSoundToVX(VReg),                           // FX18 (X)
+    AddVXToI(VReg),                               // FX1E (X)
+    SetIToLocationOfVXChar(VReg),                 // FX29 (X)
+    StoreBinaryCodedDecimalVX(VReg),              // FX33 (X)
+    StoreFromV0ToVXStartingFromI(VReg),           // FX55 (X)
+    FillFromV0ToVXStartingFromI(VReg),            // FX65 (X)
     None(u16),                                    // Unknown
 }
 
-impl fmt::Debug for CrispsAteDecodedOpcodes {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let name: &str = match self {
+impl CrispsAteDecodedOpcodes {
+    // "DrawSpriteAt (DXYN)", the same for every instance of the instruction
+    pub fn name(&self) -> &'static str {
+        match self {
             CrispsAteDecodedOpcodes::Call(_) => "Call (0NNN)",
             CrispsAteDecodedOpcodes::ClearDisplay => "ClearDisplay (00E0)",
             CrispsAteDecodedOpcodes::Return => "Return (00EE)",
@@ -97,7 +99,13 @@ impl fmt::Debug for CrispsAteDecodedOpcodes {
                 "FillFromV0ToVXStartingFromI (FX65)"
             }
             CrispsAteDecodedOpcodes::None(_) => "None (Unknown)",
-        };
+        }
+    }
+}
+
+impl fmt::Debug for CrispsAteDecodedOpcodes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.name();
 
         let values_dec: Vec<u16> = match self {
             CrispsAteDecodedOpcodes::Call(val1) => vec![*val1],
@@ -106,57 +114,57 @@ impl fmt::Debug for CrispsAteDecodedOpcodes {
             CrispsAteDecodedOpcodes::Jump(val1) => vec![*val1],
             CrispsAteDecodedOpcodes::CallSubRoutine(val1) => vec![*val1],
             CrispsAteDecodedOpcodes::SkipIfVXEquals(val1, val2) => {
-                vec![(*val1 as u16), (*val2 as u16)]
+                vec![val1.nibble(), (*val2 as u16)]
             }
             CrispsAteDecodedOpcodes::SkipIfVXNotEqual(val1, val2) => {
-                vec![(*val1 as u16), (*val2 as u16)]
+                vec![val1.nibble(), (*val2 as u16)]
             }
             CrispsAteDecodedOpcodes::SkipIfVXEqualsVY(val1, val2) => {
-                vec![(*val1 as u16), (*val2 as u16)]
+                vec![val1.nibble(), val2.nibble()]
             }
-            CrispsAteDecodedOpcodes::SetVX(val1, val2) => vec![(*val1 as u16), (*val2 as u16)],
-            CrispsAteDecodedOpcodes::AddToVX(val1, val2) => vec![(*val1 as u16), (*val2 as u16)],
-            CrispsAteDecodedOpcodes::SetVXToVY(val1, val2) => vec![(*val1 as u16), (*val2 as u16)],
+            CrispsAteDecodedOpcodes::SetVX(val1, val2) => vec![val1.nibble(), (*val2 as u16)],
+            CrispsAteDecodedOpcodes::AddToVX(val1, val2) => vec![val1.nibble(), (*val2 as u16)],
+            CrispsAteDecodedOpcodes::SetVXToVY(val1, val2) => vec![val1.nibble(), val2.nibble()],
             CrispsAteDecodedOpcodes::SetVXToVXorVY(val1, val2) => {
-                vec![(*val1 as u16), (*val2 as u16)]
+                vec![val1.nibble(), val2.nibble()]
             }
             CrispsAteDecodedOpcodes::SetVXToVXandVY(val1, val2) => {
-                vec![(*val1 as u16), (*val2 as u16)]
+                vec![val1.nibble(), val2.nibble()]
             }
             CrispsAteDecodedOpcodes::SetVXToVXxorVY(val1, val2) => {
-                vec![(*val1 as u16), (*val2 as u16)]
+                vec![val1.nibble(), val2.nibble()]
             }
-            CrispsAteDecodedOpcodes::AddVYtoVX(val1, val2) => vec![(*val1 as u16), (*val2 as u16)],
+            CrispsAteDecodedOpcodes::AddVYtoVX(val1, val2) => vec![val1.nibble(), val2.nibble()],
             CrispsAteDecodedOpcodes::SubtractVYFromVX(val1, val2) => {
-                vec![(*val1 as u16), (*val2 as u16)]
+                vec![val1.nibble(), val2.nibble()]
             }
-            CrispsAteDecodedOpcodes::StoreLeastBitOfVXAndShiftVXRight(val1) => vec![(*val1 as u16)],
+            CrispsAteDecodedOpcodes::StoreLeastBitOfVXAndShiftVXRight(val1) => vec![val1.nibble()],
             CrispsAteDecodedOpcodes::SetVXToVYMinusVX(val1, val2) => {
-                vec![(*val1 as u16), (*val2 as u16)]
+                vec![val1.nibble(), val2.nibble()]
             }
-            CrispsAteDecodedOpcodes::StoreMostBitOfVXAndShiftVXLeft(val1) => vec![(*val1 as u16)],
+            CrispsAteDecodedOpcodes::StoreMostBitOfVXAndShiftVXLeft(val1) => vec![val1.nibble()],
             CrispsAteDecodedOpcodes::SkipIfVXNotEqualVY(val1, val2) => {
-                vec![(*val1 as u16), (*val2 as u16)]
+                vec![val1.nibble(), val2.nibble()]
             }
             CrispsAteDecodedOpcodes::SetIAddress(val1) => vec![(*val1)],
             CrispsAteDecodedOpcodes::JumpToAddress(val1) => vec![(*val1)],
             CrispsAteDecodedOpcodes::SetVXToBitwiseANDWithSaltAndRandom(val1, val2) => {
-                vec![(*val1 as u16), (*val2 as u16)]
+                vec![val1.nibble(), (*val2 as u16)]
             }
             CrispsAteDecodedOpcodes::DrawSpriteAt(val1, val2, val3) => {
-                vec![(*val1 as u16), (*val2 as u16), (*val3 as u16)]
-            }
-            CrispsAteDecodedOpcodes::SkipIfKeyAtVXIsPressed(val1) => vec![(*val1 as u16)],
-            CrispsAteDecodedOpcodes::SkipIfKeyAtVXIsNotPressed(val1) => vec![(*val1 as u16)],
-            CrispsAteDecodedOpcodes::SetVXToDelayValue(val1) => vec![(*val1 as u16)],
-            CrispsAteDecodedOpcodes::GetKeyToVX(val1) => vec![(*val1 as u16)],
-            CrispsAteDecodedOpcodes::SetDelayToVX(val1) => vec![(*val1 as u16)],
-            CrispsAteDecodedOpcodes::SetSoundToVX(val1) => vec![(*val1 as u16)],
-            CrispsAteDecodedOpcodes::AddVXToI(val1) => vec![(*val1 as u16)],
-            CrispsAteDecodedOpcodes::SetIToLocationOfVXChar(val1) => vec![(*val1 as u16)],
-            CrispsAteDecodedOpcodes::StoreBinaryCodedDecimalVX(val1) => vec![(*val1 as u16)],
-            CrispsAteDecodedOpcodes::StoreFromV0ToVXStartingFromI(val1) => vec![(*val1 as u16)],
-            CrispsAteDecodedOpcodes::FillFromV0ToVXStartingFromI(val1) => vec![(*val1 as u16)],
+                vec![val1.nibble(), val2.nibble(), *val3]
+            }
+            CrispsAteDecodedOpcodes::SkipIfKeyAtVXIsPressed(val1) => vec![val1.nibble()],
+            CrispsAteDecodedOpcodes::SkipIfKeyAtVXIsNotPressed(val1) => vec![val1.nibble()],
+            CrispsAteDecodedOpcodes::SetVXToDelayValue(val1) => vec![val1.nibble()],
+            CrispsAteDecodedOpcodes::GetKeyToVX(val1) => vec![val1.nibble()],
+            CrispsAteDecodedOpcodes::SetDelayToVX(val1) => vec![val1.nibble()],
+            CrispsAteDecodedOpcodes::SetSoundToVX(val1) => vec![val1.nibble()],
+            CrispsAteDecodedOpcodes::AddVXToI(val1) => vec![val1.nibble()],
+            CrispsAteDecodedOpcodes::SetIToLocationOfVXChar(val1) => vec![val1.nibble()],
+            CrispsAteDecodedOpcodes::StoreBinaryCodedDecimalVX(val1) => vec![val1.nibble()],
+            CrispsAteDecodedOpcodes::StoreFromV0ToVXStartingFromI(val1) => vec![val1.nibble()],
+            CrispsAteDecodedOpcodes::FillFromV0ToVXStartingFromI(val1) => vec![val1.nibble()],
             CrispsAteDecodedOpcodes::None(val1) => vec![(*val1)],
         };
 
@@ -166,8 +174,8 @@ impl fmt::Debug for CrispsAteDecodedOpcodes {
 
 #[derive(Debug)]
 pub struct CrispAteTimers {
-    pub delay: u16,
-    pub sound: u16,
+    pub delay: u8,
+    pub sound: u8,
 }
 
 impl CrispAteTimers {
@@ -176,24 +184,126 @@ impl CrispAteTimers {
     }
 }
 
+// index of one of the sixteen V registers, always inside 0x0..=0xF
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct VReg(u8);
+
+pub const V0: VReg = VReg(0x0);
+pub const V1: VReg = VReg(0x1);
+pub const V2: VReg = VReg(0x2);
+pub const V3: VReg = VReg(0x3);
+pub const V4: VReg = VReg(0x4);
+pub const V5: VReg = VReg(0x5);
+pub const V6: VReg = VReg(0x6);
+pub const V7: VReg = VReg(0x7);
+pub const V8: VReg = VReg(0x8);
+pub const V9: VReg = VReg(0x9);
+pub const VA: VReg = VReg(0xA);
+pub const VB: VReg = VReg(0xB);
+pub const VC: VReg = VReg(0xC);
+pub const VD: VReg = VReg(0xD);
+pub const VE: VReg = VReg(0xE);
+pub const VF: VReg = VReg(0xF);
+
+impl VReg {
+    pub fn new(index: u16) -> Option<Self> {
+        match index {
+            0x0..=0xF => Some(VReg(index as u8)),
+            _ => None,
+        }
+    }
+
+    // only the low nibble is kept, which is what the decoder hands us
+    pub fn from_nibble(nibble: u16) -> Self {
+        VReg((nibble & 0xF) as u8)
+    }
+
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn nibble(&self) -> u16 {
+        self.0 as u16
+    }
+
+    // registers from `self` to `to`, both included, walking downwards when
+    // `to` comes first (5XY2/5XY3 store them in that order)
+    pub fn span(&self, to: VReg) -> Vec<VReg> {
+        if self.0 <= to.0 {
+            (self.0..=to.0).map(VReg).collect()
+        } else {
+            (to.0..=self.0).rev().map(VReg).collect()
+        }
+    }
+}
+
+impl fmt::Display for VReg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "V{:X}", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CrispAteVRegisters {
+    values: [u8; 16],
+}
+
+impl Default for CrispAteVRegisters {
+    fn default() -> Self {
+        CrispAteVRegisters::new()
+    }
+}
+
+impl CrispAteVRegisters {
+    pub fn new() -> Self {
+        CrispAteVRegisters { values: [0; 16] }
+    }
+
+    pub fn read_range(&self, from: VReg, to: VReg) -> Vec<u8> {
+        from.span(to).iter().map(|v| self[*v]).collect()
+    }
+
+    // writes `values` in the same order `read_range` would return them,
+    // stopping early if there are fewer values than registers
+    pub fn write_range(&mut self, from: VReg, to: VReg, values: &[u8]) {
+        for (v, value) in from.span(to).iter().zip(values) {
+            self[*v] = *value;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (VReg, u8)> + '_ {
+        self.values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (VReg(index as u8), *value))
+    }
+
+    // (register, old, new) for every register that differs from `past`
+    pub fn diff(&self, past: &CrispAteVRegisters) -> Vec<(VReg, u8, u8)> {
+        self.iter()
+            .filter(|(v, value)| past[*v] != *value)
+            .map(|(v, value)| (v, past[v], value))
+            .collect()
+    }
+}
+
+impl Index<VReg> for CrispAteVRegisters {
+    type Output = u8;
+
+    fn index(&self, v: VReg) -> &u8 {
+        &self.values[v.index()]
+    }
+}
+
+impl IndexMut<VReg> for CrispAteVRegisters {
+    fn index_mut(&mut self, v: VReg) -> &mut u8 {
+        &mut self.values[v.index()]
+    }
+}
+
 #[derive(Debug)]
 pub struct PastCrispAteRegisters {
-    pub v_0: u16,
-    pub v_1: u16,
-    pub v_2: u16,
-    pub v_3: u16,
-    pub v_4: u16,
-    pub v_5: u16,
-    pub v_6: u16,
-    pub v_7: u16,
-    pub v_8: u16,
-    pub v_9: u16,
-    pub v_a: u16,
-    pub v_b: u16,
-    pub v_c: u16,
-    pub v_d: u16,
-    pub v_e: u16,
-    pub v_f: u16,
+    pub v: CrispAteVRegisters,
     pub address: u16,
     pub program_counter: u16,
     pub draw_flag: bool,
@@ -201,53 +311,72 @@ pub struct PastCrispAteRegisters {
 
 #[derive(Debug)]
 pub struct CurrentCrispAteRegisters {
-    pub v_0: u16,
-    pub v_1: u16,
-    pub v_2: u16,
-    pub v_3: u16,
-    pub v_4: u16,
-    pub v_5: u16,
-    pub v_6: u16,
-    pub v_7: u16,
-    pub v_8: u16,
-    pub v_9: u16,
-    pub v_a: u16,
-    pub v_b: u16,
-    pub v_c: u16,
-    pub v_d: u16,
-    pub v_e: u16,
-    pub v_f: u16,
+    pub v: CrispAteVRegisters,
     pub address: u16,
     pub program_counter: u16,
     pub draw_flag: bool,
     pub history: Vec<String>,
+    pub trace: bool, // whether the cpu fills `history`
     pub debug_mode: bool,
 }
 
 impl CurrentCrispAteRegisters {
     pub fn new(debug_mode: bool) -> Self {
         CurrentCrispAteRegisters {
-            v_0: 0,
-            v_1: 0,
-            v_2: 0,
-            v_3: 0,
-            v_4: 0,
-            v_5: 0,
-            v_6: 0,
-            v_7: 0,
-            v_8: 0,
-            v_9: 0,
-            v_a: 0,
-            v_b: 0,
-            v_c: 0,
-            v_d: 0,
-            v_e: 0,
-            v_f: 0,
+            v: CrispAteVRegisters::new(),
             address: 0,
             program_counter: 0,
             draw_flag: false,
             history: Vec::new(),
+            // tracing costs more than the rest of the cycle together, only
+            // debug mode shows the history
+            trace: debug_mode,
             debug_mode,
         }
     }
+
+    pub fn snapshot(&self) -> PastCrispAteRegisters {
+        PastCrispAteRegisters {
+            v: self.v,
+            address: self.address,
+            program_counter: self.program_counter,
+            draw_flag: self.draw_flag,
+        }
+    }
+}
+
+#[cfg(test)]
+mod register_tests {
+    use super::*;
+
+    #[test]
+    fn only_accepts_sixteen_registers() {
+        assert_eq!(VReg::new(0xF), Some(VF));
+        assert_eq!(VReg::new(0x10), None);
+        assert_eq!(VReg::from_nibble(0x1A), VA);
+    }
+
+    #[test]
+    fn can_read_and_write_ranges_in_both_directions() {
+        let mut sut = CrispAteVRegisters::new();
+        sut.write_range(V0, V3, &[1, 2, 3, 4]);
+
+        assert_eq!(sut.read_range(V0, V3), vec![1, 2, 3, 4]);
+        assert_eq!(sut.read_range(V3, V1), vec![4, 3, 2]);
+
+        sut.write_range(V2, V0, &[9, 8, 7]);
+
+        assert_eq!(sut.read_range(V0, V2), vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn can_diff_against_past_registers() {
+        let past = CrispAteVRegisters::new();
+        let mut sut = past;
+        sut[V4] = 0x10;
+        sut[VF] = 0x01;
+
+        assert_eq!(sut.diff(&past), vec![(V4, 0x00, 0x10), (VF, 0x00, 0x01)]);
+        assert_eq!(sut.iter().count(), 16);
+    }
 }
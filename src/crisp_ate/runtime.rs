@@ -1,14 +1,16 @@
+pub const STACK_SIZE: usize = 16;
+
 #[derive(Debug)]
 pub struct CrispAteRuntime {
     pub stack_pointer: usize,
-    pub stack: [u16; 16],
+    pub stack: [u16; STACK_SIZE],
 }
 
 impl CrispAteRuntime {
     pub fn new() -> Self {
         CrispAteRuntime {
             stack_pointer: 0,
-            stack: [0; 16],
+            stack: [0; STACK_SIZE],
         }
     }
 }
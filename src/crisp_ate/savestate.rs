@@ -0,0 +1,285 @@
+use std::fs;
+use std::io;
+
+use super::code_map::CrispAteCodeMap;
+use super::cpu::CrispAte;
+use super::memory::MEMORY_SIZE;
+use super::movie::CORE_VERSION;
+use super::registers::VReg;
+use super::runtime::STACK_SIZE;
+use crate::utils::{from_hex, to_hex};
+
+// A save state is a plain text file with everything the machine needs to
+// carry on exactly where it was:
+//
+//   crisp-ate-state 1
+//   core 0.1.0
+//   rom 9a3f...          (fnv1a of the ROM bytes)
+//   rng splitmix
+//   seed 1234
+//   rng-state 5e1c...    (`RandomSource::state`)
+//   v 00 1f ...          (V0 to VF)
+//   i 0300
+//   pc 0208
+//   sp 1
+//   stack 0204 0000 ...  (all 16 entries)
+//   timers 3c 00         (delay, sound)
+//   memory 00f0...       (all 4 KB, hex)
+//   screen 0000...       (64x32 pixels, 8 to a byte, hex)
+
+const STATE_MAGIC: &str = "crisp-ate-state 1";
+
+const SCREEN_SIZE: usize = 64 * 32;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CrispAteSaveState {
+    pub core_version: String,
+    pub rom_hash: u64,
+    pub rng: String,
+    pub seed: u64,
+    pub random_state: u64,
+    pub v: [u8; 16],
+    pub address: u16,
+    pub program_counter: u16,
+    pub stack_pointer: usize,
+    pub stack: [u16; STACK_SIZE],
+    pub delay: u8,
+    pub sound: u8,
+    pub memory: Vec<u8>,
+    pub screen: Vec<bool>,
+}
+
+impl CrispAteSaveState {
+    pub fn capture(vm: &CrispAte, rom_hash: u64) -> Self {
+        let mut v = [0; 16];
+        for (register, value) in vm.registers.v.iter() {
+            v[register.index()] = value;
+        }
+
+        CrispAteSaveState {
+            core_version: String::from(CORE_VERSION),
+            rom_hash,
+            rng: String::from(vm.random.name()),
+            seed: vm.random.seed(),
+            random_state: vm.random.state(),
+            v,
+            address: vm.registers.address,
+            program_counter: vm.registers.program_counter,
+            stack_pointer: vm.runtime.stack_pointer,
+            stack: vm.runtime.stack,
+            delay: vm.timers.delay,
+            sound: vm.timers.sound,
+            memory: (0..MEMORY_SIZE)
+                .map(|address| vm.memory.peek(address))
+                .collect(),
+            screen: vm.screen.to_vec(),
+        }
+    }
+
+    // puts the machine back as it was, clearing any fault it ran into since.
+    // The random number generator must be the same kind, its sequence picks
+    // up where the state left it
+    pub fn restore(&self, vm: &mut CrispAte) -> Result<(), String> {
+        if self.rng != vm.random.name() {
+            return Err(format!(
+                "State was saved with the {} random number generator, this is {}",
+                self.rng,
+                vm.random.name()
+            ));
+        }
+
+        vm.random.restore(self.random_state);
+        for (index, value) in self.v.iter().enumerate() {
+            vm.registers.v[VReg::from_nibble(index as u16)] = *value;
+        }
+        vm.registers.address = self.address;
+        vm.registers.program_counter = self.program_counter;
+        vm.registers.draw_flag = true;
+        vm.runtime.stack_pointer = self.stack_pointer;
+        vm.runtime.stack = self.stack;
+        vm.timers.delay = self.delay;
+        vm.timers.sound = self.sound;
+        vm.memory.load(0, &self.memory);
+        vm.screen.copy_from_slice(&self.screen);
+        vm.fault = None;
+        // whatever ran or was written before doesn't carry over
+        vm.code_map = CrispAteCodeMap::new();
+
+        Ok(())
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let words = |words: &[u16]| -> Vec<String> {
+            words.iter().map(|word| format!("{:04x}", word)).collect()
+        };
+        let screen: Vec<u8> = self
+            .screen
+            .chunks(8)
+            .map(|pixels| {
+                pixels
+                    .iter()
+                    .fold(0, |byte, pixel| byte << 1 | *pixel as u8)
+            })
+            .collect();
+
+        text.push_str(&format!("{}\n", STATE_MAGIC));
+        text.push_str(&format!("core {}\n", self.core_version));
+        text.push_str(&format!("rom {:016x}\n", self.rom_hash));
+        text.push_str(&format!("rng {}\n", self.rng));
+        text.push_str(&format!("seed {}\n", self.seed));
+        text.push_str(&format!("rng-state {:016x}\n", self.random_state));
+        text.push_str(&format!(
+            "v {}\n",
+            self.v
+                .iter()
+                .map(|value| format!("{:02x}", value))
+                .collect::<Vec<String>>()
+                .join(" ")
+        ));
+        text.push_str(&format!("i {:04x}\n", self.address));
+        text.push_str(&format!("pc {:04x}\n", self.program_counter));
+        text.push_str(&format!("sp {}\n", self.stack_pointer));
+        text.push_str(&format!("stack {}\n", words(&self.stack).join(" ")));
+        text.push_str(&format!("timers {:02x} {:02x}\n", self.delay, self.sound));
+        text.push_str(&format!("memory {}\n", to_hex(&self.memory)));
+        text.push_str(&format!("screen {}\n", to_hex(&screen)));
+
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+
+        if lines.next() != Some(STATE_MAGIC) {
+            return Err(String::from("Not a crisp-ate save state"));
+        }
+
+        let mut field = |name: &str| -> Result<String, String> {
+            let line = lines.next().unwrap_or_default();
+
+            match line.split_once(' ') {
+                Some((key, value)) if key == name => Ok(value.to_owned()),
+                _ => Err(format!("Expected '{}' in save state, got '{}'", name, line)),
+            }
+        };
+        let invalid = |name: &str| format!("Invalid {} in save state", name);
+
+        let core_version = field("core")?;
+        let rom_hash = u64::from_str_radix(&field("rom")?, 16).map_err(|_| invalid("ROM hash"))?;
+        let rng = field("rng")?;
+        let seed = field("seed")?.parse().map_err(|_| invalid("seed"))?;
+        let random_state =
+            u64::from_str_radix(&field("rng-state")?, 16).map_err(|_| invalid("rng state"))?;
+        let v: [u8; 16] = from_hex(&field("v")?.replace(' ', ""))
+            .and_then(|v| v.try_into().ok())
+            .ok_or_else(|| invalid("V registers"))?;
+        let address = u16::from_str_radix(&field("i")?, 16).map_err(|_| invalid("I"))?;
+        let program_counter = u16::from_str_radix(&field("pc")?, 16).map_err(|_| invalid("PC"))?;
+        let stack_pointer = field("sp")?
+            .parse()
+            .ok()
+            .filter(|pointer| *pointer <= STACK_SIZE)
+            .ok_or_else(|| invalid("stack pointer"))?;
+        let stack: [u16; STACK_SIZE] = field("stack")?
+            .split(' ')
+            .map(|word| u16::from_str_radix(word, 16).ok())
+            .collect::<Option<Vec<u16>>>()
+            .and_then(|stack| stack.try_into().ok())
+            .ok_or_else(|| invalid("stack"))?;
+        let (delay, sound) = match from_hex(&field("timers")?.replace(' ', "")).as_deref() {
+            Some([delay, sound]) => (*delay, *sound),
+            _ => return Err(invalid("timers")),
+        };
+        let memory = from_hex(&field("memory")?)
+            .filter(|memory| memory.len() == MEMORY_SIZE)
+            .ok_or_else(|| invalid("memory"))?;
+        let screen = from_hex(&field("screen")?)
+            .filter(|screen| screen.len() * 8 == SCREEN_SIZE)
+            .ok_or_else(|| invalid("screen"))?
+            .iter()
+            .flat_map(|byte| (0..8).map(move |bit| byte & (0x80 >> bit) != 0))
+            .collect();
+
+        Ok(CrispAteSaveState {
+            core_version,
+            rom_hash,
+            rng,
+            seed,
+            random_state,
+            v,
+            address,
+            program_counter,
+            stack_pointer,
+            stack,
+            delay,
+            sound,
+            memory,
+            screen,
+        })
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        fs::write(filename, self.to_text())
+    }
+
+    pub fn load(filename: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(filename)
+            .map_err(|e| format!("Could not read save state: {}", e))?;
+
+        CrispAteSaveState::from_text(&text)
+    }
+}
+
+#[cfg(test)]
+mod savestate_tests {
+    use super::*;
+    use crate::crisp_ate::random::create_random;
+
+    // 0x200 v0 := random 0xFF, 0x202 call 0x206, 0x204 jump 0x200,
+    // 0x206 draw the font's 0 at (v0, v0), 0x208 return
+    const PROGRAM: [u8; 10] = [0xC0, 0xFF, 0x22, 0x06, 0x12, 0x00, 0xD0, 0x05, 0x00, 0xEE];
+
+    fn create_vm() -> CrispAte {
+        let mut vm = CrispAte::with_program(&PROGRAM);
+        vm.random = create_random("vip", 3).unwrap();
+        vm.timers.delay = 200;
+        vm
+    }
+
+    #[test]
+    fn carries_on_where_it_was_saved() {
+        let mut vm = create_vm();
+        for _ in 0..7 {
+            vm.emulation_cyle().unwrap();
+        }
+        let state = CrispAteSaveState::capture(&vm, 42);
+        for _ in 0..50 {
+            vm.emulation_cyle().unwrap();
+        }
+        let expected = vm.checksum();
+
+        let mut sut = create_vm();
+        let parsed = CrispAteSaveState::from_text(&state.to_text()).unwrap();
+        assert_eq!(parsed, state);
+
+        parsed.restore(&mut sut).unwrap();
+        assert_eq!(sut.runtime.stack_pointer, 1);
+        for _ in 0..50 {
+            sut.emulation_cyle().unwrap();
+        }
+        assert_eq!(sut.checksum(), expected);
+    }
+
+    #[test]
+    fn needs_the_same_random_number_generator() {
+        let state = CrispAteSaveState::capture(&create_vm(), 42);
+        let mut sut = CrispAte::new(false);
+
+        assert!(state.restore(&mut sut).is_err());
+        assert_eq!(
+            CrispAteSaveState::from_text("crisp-ate-movie 1\n"),
+            Err(String::from("Not a crisp-ate save state"))
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs;
+use std::io;
+
+use super::palette::CrispAtePalette;
+
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+// deflate "stored" blocks can hold at most this many bytes each
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+// the framebuffer as packed 8-bit RGB, every CHIP-8 pixel becoming a
+// `scale` x `scale` square
+pub fn to_rgb(screen: &[bool; 64 * 32], scale: usize, palette: &CrispAtePalette) -> Vec<u8> {
+    let width = SCREEN_WIDTH * scale;
+    let height = SCREEN_HEIGHT * scale;
+
+    let mut rgb: Vec<u8> = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = screen[(y / scale) * SCREEN_WIDTH + x / scale];
+            rgb.extend_from_slice(&palette.color(pixel));
+        }
+    }
+
+    rgb
+}
+
+// Encodes the framebuffer as an 8-bit RGB PNG (see `to_rgb`). The image data
+// is not compressed (stored deflate blocks), which keeps the encoder tiny and
+// the files are still small enough.
+pub fn encode_png(screen: &[bool; 64 * 32], scale: usize, palette: &CrispAtePalette) -> Vec<u8> {
+    let width = SCREEN_WIDTH * scale;
+    let height = SCREEN_HEIGHT * scale;
+
+    let mut raw: Vec<u8> = Vec::with_capacity(height * (1 + width * 3));
+    for row in to_rgb(screen, scale, palette).chunks(width * 3) {
+        raw.push(0); // filter type: none
+        raw.extend_from_slice(row);
+    }
+
+    let mut header: Vec<u8> = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8 bit depth, RGB, no interlace
+
+    let mut png: Vec<u8> = Vec::new();
+    png.extend_from_slice(&PNG_SIGNATURE);
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+pub fn save_png(
+    filename: &str,
+    screen: &[bool; 64 * 32],
+    scale: usize,
+    palette: &CrispAtePalette,
+) -> io::Result<()> {
+    fs::write(filename, encode_png(screen, scale, palette))
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none() as u8;
+        let length = block.len() as u16;
+
+        out.push(last);
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+
+    out
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+
+    for byte in bytes {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b): (u32, u32) = (1, 0);
+
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod screenshot_tests {
+    use super::*;
+
+    // walks the chunks of a PNG made by `encode_png` and returns
+    // (width, height, raw scanlines)
+    fn decode(png: &[u8]) -> (u32, u32, Vec<u8>) {
+        assert_eq!(png[..8], PNG_SIGNATURE);
+
+        let mut offset = 8;
+        let mut size = (0, 0);
+        let mut idat: Vec<u8> = Vec::new();
+
+        while offset < png.len() {
+            let length = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+            let kind = &png[offset + 4..offset + 8];
+            let data = &png[offset + 8..offset + 8 + length];
+            let crc = u32::from_be_bytes(
+                png[offset + 8 + length..offset + 12 + length]
+                    .try_into()
+                    .unwrap(),
+            );
+
+            assert_eq!(crc, crc32(&png[offset + 4..offset + 8 + length]));
+
+            match kind {
+                b"IHDR" => {
+                    size = (
+                        u32::from_be_bytes(data[0..4].try_into().unwrap()),
+                        u32::from_be_bytes(data[4..8].try_into().unwrap()),
+                    )
+                }
+                b"IDAT" => idat.extend_from_slice(data),
+                _ => (),
+            }
+
+            offset += 12 + length;
+        }
+
+        let mut raw: Vec<u8> = Vec::new();
+        let mut position = 2;
+        loop {
+            let last = idat[position] & 1 == 1;
+            let length = u16::from_le_bytes([idat[position + 1], idat[position + 2]]) as usize;
+            raw.extend_from_slice(&idat[position + 5..position + 5 + length]);
+            position += 5 + length;
+
+            if last {
+                break;
+            }
+        }
+
+        assert_eq!(
+            u32::from_be_bytes(idat[position..position + 4].try_into().unwrap()),
+            adler32(&raw)
+        );
+
+        (size.0, size.1, raw)
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn encodes_screen_at_requested_scale() {
+        let mut screen = [false; 64 * 32];
+        screen[0] = true;
+        screen[64 * 31 + 63] = true;
+
+        let (width, height, raw) = decode(&encode_png(&screen, 3, &CrispAtePalette::new()));
+
+        assert_eq!((width, height), (192, 96));
+        assert_eq!(raw.len(), 96 * (1 + 192 * 3));
+    }
+
+    #[test]
+    fn uses_palette_colors() {
+        let mut screen = [false; 64 * 32];
+        screen[1] = true;
+
+        let palette = CrispAtePalette::parse("040506,010203").unwrap();
+        let (_, _, raw) = decode(&encode_png(&screen, 2, &palette));
+        let stride = 1 + 128 * 3;
+
+        // row 1 is the second half of CHIP-8 row 0
+        let row = &raw[stride..2 * stride];
+        assert_eq!(row[0], 0);
+        assert_eq!(row[1..7], [4, 5, 6, 4, 5, 6]);
+        assert_eq!(row[7..13], [1, 2, 3, 1, 2, 3]);
+        assert_eq!(row[13..16], [4, 5, 6]);
+    }
+
+    #[test]
+    fn splits_large_images_into_several_blocks() {
+        let screen = [true; 64 * 32];
+        let (_, _, raw) = decode(&encode_png(&screen, 10, &CrispAtePalette::new()));
+
+        assert!(raw.len() > MAX_STORED_BLOCK);
+        assert!(raw.iter().skip(1).take(640 * 3).all(|byte| *byte == 255));
+    }
+}
@@ -0,0 +1,285 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::utils::hex;
+
+// Where the code of a source line starts. A line's code runs until the next
+// entry's address.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LineEntry {
+    pub address: u16,
+    pub file: PathBuf,
+    pub line: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Label {
+    pub address: u16,
+    pub name: String,
+}
+
+// Debug information for a ROM, read from the `<rom>.sym` file next to it.
+// One record per line, `#` starts a comment:
+//
+//   label <address> <name>
+//   line <address> <line> <source file>
+//
+// Addresses are hex, with or without 0x. Source files are relative to the
+// symbol file and may contain spaces, which is why they come last.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CrispAteSymbols {
+    pub labels: Vec<Label>,    // sorted by address
+    pub lines: Vec<LineEntry>, // sorted by address
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+// the first whitespace separated field and the rest of the line
+fn next_field(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    text.split_at(text.find(char::is_whitespace).unwrap_or(text.len()))
+}
+
+// the same file even when one side is relative or goes through a symlink
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+impl Default for CrispAteSymbols {
+    fn default() -> Self {
+        CrispAteSymbols::new()
+    }
+}
+
+impl CrispAteSymbols {
+    pub fn new() -> Self {
+        CrispAteSymbols {
+            labels: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    // source files are resolved against `directory`
+    pub fn parse(text: &str, directory: &Path) -> Result<Self, String> {
+        let mut symbols = CrispAteSymbols::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = || format!("line {}: can't read `{}`", number + 1, line);
+
+            match next_field(line) {
+                ("label", rest) => {
+                    let (address, name) = next_field(rest);
+
+                    symbols.labels.push(Label {
+                        address: parse_address(address).ok_or_else(error)?,
+                        name: match name.trim() {
+                            "" => return Err(error()),
+                            name => name.to_owned(),
+                        },
+                    });
+                }
+                ("line", rest) => {
+                    let (address, rest) = next_field(rest);
+                    let (source_line, file) = next_field(rest);
+
+                    symbols.lines.push(LineEntry {
+                        address: parse_address(address).ok_or_else(error)?,
+                        line: source_line.parse().map_err(|_| error())?,
+                        file: match file.trim() {
+                            "" => return Err(error()),
+                            file => directory.join(file),
+                        },
+                    });
+                }
+                // newer assemblers may write records we don't know yet
+                _ => continue,
+            }
+        }
+
+        symbols.labels.sort_by_key(|label| label.address);
+        symbols.lines.sort_by_key(|entry| entry.address);
+        Ok(symbols)
+    }
+
+    pub fn load(filename: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(filename)?;
+        let directory = filename.parent().unwrap_or(Path::new(""));
+
+        CrispAteSymbols::parse(&text, directory)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // `game.sym` for `game.ch8`, empty when there is none
+    pub fn for_rom(rom: &str) -> Self {
+        let filename = Path::new(rom).with_extension("sym");
+
+        match CrispAteSymbols::load(&filename) {
+            Ok(symbols) => symbols,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => CrispAteSymbols::new(),
+            Err(e) => {
+                eprintln!("Could not read {}: {}", filename.display(), e);
+                CrispAteSymbols::new()
+            }
+        }
+    }
+
+    // the label right at `address`
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|label| label.address == address)
+            .map(|label| label.name.as_str())
+    }
+
+    pub fn label_address(&self, name: &str) -> Option<u16> {
+        self.labels
+            .iter()
+            .find(|label| label.name == name)
+            .map(|label| label.address)
+    }
+
+    // "draw+4", from the closest label at or before `address`
+    pub fn locate(&self, address: u16) -> Option<String> {
+        let after = self
+            .labels
+            .partition_point(|label| label.address <= address);
+        let label = &self.labels[after.checked_sub(1)?];
+
+        match address - label.address {
+            0 => Some(label.name.clone()),
+            offset => Some(format!("{}+{}", label.name, offset)),
+        }
+    }
+
+    // "0x20a <draw+4>", or just the address without a label before it
+    pub fn describe(&self, address: u16) -> String {
+        match self.locate(address) {
+            Some(name) => format!("{} <{}>", hex(address), name),
+            None => hex(address),
+        }
+    }
+
+    // like `describe`, with the source line when there is one
+    pub fn location(&self, address: u16) -> String {
+        match self.line_at(address) {
+            Some(entry) => {
+                let file = entry.file.file_name().unwrap_or(entry.file.as_os_str());
+                format!(
+                    "{} ({}:{})",
+                    self.describe(address),
+                    file.to_string_lossy(),
+                    entry.line
+                )
+            }
+            None => self.describe(address),
+        }
+    }
+
+    // the return addresses of `CrispAteRuntime::stack`, innermost last
+    pub fn call_stack(&self, stack: &[u16]) -> String {
+        let callers: Vec<String> = stack
+            .iter()
+            .map(|address| self.describe(*address))
+            .collect();
+        format!("[{}]", callers.join(", "))
+    }
+
+    // the source line whose code contains `address`
+    pub fn line_at(&self, address: u16) -> Option<&LineEntry> {
+        let after = self.lines.partition_point(|entry| entry.address <= address);
+        after.checked_sub(1).map(|index| &self.lines[index])
+    }
+
+    // where to break for a line of `file`: its first instruction, or the
+    // first one of the next line that has code
+    pub fn address_of(&self, file: &Path, line: u32) -> Option<&LineEntry> {
+        self.lines
+            .iter()
+            .filter(|entry| entry.line >= line && same_file(&entry.file, file))
+            .min_by_key(|entry| (entry.line, entry.address))
+    }
+}
+
+#[cfg(test)]
+mod symbols_tests {
+    use super::*;
+
+    const SYMBOLS: &str = "# pong\n\
+        line 0x200 3 pong.8o\n\
+        line 0x204 4 pong.8o\n\
+        line 20A 9 lib/draw paddles.8o\n\
+        label 0x200 main\n\
+        label 0x206 draw paddles\n\
+        frame 0x200 2\n";
+
+    #[test]
+    fn reads_line_tables() {
+        let sut = CrispAteSymbols::parse(SYMBOLS, Path::new("games")).unwrap();
+
+        assert_eq!(sut.lines.len(), 3);
+        assert_eq!(sut.labels.len(), 2);
+        assert_eq!(sut.label_address("draw paddles"), Some(0x206));
+        assert_eq!(
+            sut.lines[2],
+            LineEntry {
+                address: 0x20A,
+                file: PathBuf::from("games/lib/draw paddles.8o"),
+                line: 9,
+            }
+        );
+
+        assert!(CrispAteSymbols::parse("line 0x200 pong.8o", Path::new("")).is_err());
+        assert!(CrispAteSymbols::parse("label 0x200", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn maps_addresses_to_lines_and_back() {
+        let sut = CrispAteSymbols::parse(SYMBOLS, Path::new("")).unwrap();
+
+        assert_eq!(sut.line_at(0x1FE), None);
+        assert_eq!(sut.line_at(0x202).unwrap().line, 3);
+        assert_eq!(sut.line_at(0x300).unwrap().line, 9);
+
+        assert_eq!(
+            sut.address_of(Path::new("pong.8o"), 4).unwrap().address,
+            0x204
+        );
+        // blank lines and comments break at the next line with code
+        assert_eq!(
+            sut.address_of(Path::new("pong.8o"), 1).unwrap().address,
+            0x200
+        );
+        assert_eq!(sut.address_of(Path::new("pong.8o"), 5), None);
+    }
+
+    #[test]
+    fn names_addresses_after_labels() {
+        let sut = CrispAteSymbols::parse(SYMBOLS, Path::new("games")).unwrap();
+
+        assert_eq!(sut.label(0x206), Some("draw paddles"));
+        assert_eq!(sut.label(0x208), None);
+        assert_eq!(sut.describe(0x1FE), "0x1fe");
+        assert_eq!(sut.describe(0x200), "0x200 <main>");
+        assert_eq!(sut.describe(0x20A), "0x20a <draw paddles+4>");
+        assert_eq!(sut.location(0x202), "0x202 <main+2> (pong.8o:3)");
+        assert_eq!(
+            sut.call_stack(&[0x200, 0x208]),
+            "[0x200 <main>, 0x208 <draw paddles+2>]"
+        );
+    }
+}
@@ -0,0 +1,387 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use super::fault::CrispAteFault;
+use super::palette::{CrispAtePalette, Rgb};
+use super::symbols::CrispAteSymbols;
+
+// terminals only send key presses (and repeats), never releases, so a key
+// counts as held for this many frames after its last press
+const KEY_HOLD_FRAMES: u8 = 10;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TerminalMode {
+    HalfBlock, // one character per 1x2 pixels, works with any font
+    Braille,   // one character per 2x4 pixels, needs a font with braille
+}
+
+impl TerminalMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "halfblock" => Some(TerminalMode::HalfBlock),
+            "braille" => Some(TerminalMode::Braille),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TerminalEvent {
+    Key(usize),
+    Screenshot,      // F12
+    ToggleRecording, // F9
+    NextPalette,     // F2
+    Pause,           // P
+    ToggleTurbo,     // Tab, there are no key releases to hold it with
+    Quit,            // Esc or Ctrl-C
+}
+
+// same layout as the raylib frontend:
+// CHIP-8 keypad      keyboard
+//  1 2 3 C            1 2 3 4
+//  4 5 6 D            Q W E R
+//  7 8 9 E            A S D F
+//  A 0 B F            Z X C V
+const KEYPAD: [u8; 16] = [
+    b'x', b'1', b'2', b'3', b'q', b'w', b'e', b'a', b's', b'd', b'z', b'c', b'4', b'r', b'f', b'v',
+];
+
+pub fn parse_input(bytes: &[u8]) -> Vec<TerminalEvent> {
+    let mut events: Vec<TerminalEvent> = Vec::new();
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let rest = &bytes[position..];
+
+        if rest.starts_with(b"\x1b[24~") {
+            events.push(TerminalEvent::Screenshot);
+            position += 5;
+            continue;
+        }
+
+        if rest.starts_with(b"\x1b[20~") {
+            events.push(TerminalEvent::ToggleRecording);
+            position += 5;
+            continue;
+        }
+
+        if rest.starts_with(b"\x1bOQ") {
+            events.push(TerminalEvent::NextPalette);
+            position += 3;
+            continue;
+        }
+
+        match rest[0] {
+            // a lone escape is the Esc key, anything else starting with one
+            // is a sequence for a key we don't use
+            0x1B if rest.len() == 1 || rest[1] == 0x1B => events.push(TerminalEvent::Quit),
+            0x1B => {
+                let length = match rest[1] {
+                    b'O' => 3, // F1-F4 and friends
+                    b'[' => rest[2..]
+                        .iter()
+                        .position(|byte| (0x40..=0x7E).contains(byte))
+                        .map_or(rest.len(), |end| end + 3),
+                    _ => 2, // Alt + key
+                };
+                position += length;
+                continue;
+            }
+            0x03 => events.push(TerminalEvent::Quit),
+            b'p' | b'P' => events.push(TerminalEvent::Pause),
+            b'\t' => events.push(TerminalEvent::ToggleTurbo),
+            byte => {
+                let lower = byte.to_ascii_lowercase();
+                if let Some(key) = KEYPAD.iter().position(|mapped| *mapped == lower) {
+                    events.push(TerminalEvent::Key(key));
+                }
+            }
+        }
+
+        position += 1;
+    }
+
+    events
+}
+
+pub struct TerminalKeypad {
+    held: [u8; 16],
+}
+
+impl Default for TerminalKeypad {
+    fn default() -> Self {
+        TerminalKeypad::new()
+    }
+}
+
+impl TerminalKeypad {
+    pub fn new() -> Self {
+        TerminalKeypad { held: [0; 16] }
+    }
+
+    pub fn press(&mut self, key: usize) {
+        self.held[key] = KEY_HOLD_FRAMES;
+    }
+
+    // the keypad for the coming frame
+    pub fn next_frame(&mut self) -> [bool; 16] {
+        let mut keypad = [false; 16];
+
+        for (key, held) in self.held.iter_mut().enumerate() {
+            keypad[key] = *held > 0;
+            *held = held.saturating_sub(1);
+        }
+
+        keypad
+    }
+}
+
+fn foreground(rgb: Rgb) -> String {
+    format!("\x1b[38;2;{};{};{}m", rgb[0], rgb[1], rgb[2])
+}
+
+fn background(rgb: Rgb) -> String {
+    format!("\x1b[48;2;{};{};{}m", rgb[0], rgb[1], rgb[2])
+}
+
+// Draws a `width` pixels wide framebuffer (64x32 or 128x64) as lines of
+// text, starting at the top left corner of the terminal.
+pub fn render(
+    screen: &[bool],
+    width: usize,
+    mode: TerminalMode,
+    palette: &CrispAtePalette,
+) -> String {
+    let height = screen.len() / width;
+    let pixel = |x: usize, y: usize| y < height && screen[y * width + x];
+    let mut text = String::from("\x1b[H");
+
+    match mode {
+        TerminalMode::HalfBlock => {
+            for y in (0..height).step_by(2) {
+                let mut colors: Option<(bool, bool)> = None;
+
+                for x in 0..width {
+                    // upper half block: foreground is the top pixel,
+                    // background the bottom one
+                    let cell = (pixel(x, y), pixel(x, y + 1));
+                    if colors != Some(cell) {
+                        text.push_str(&foreground(palette.color(cell.0)));
+                        text.push_str(&background(palette.color(cell.1)));
+                        colors = Some(cell);
+                    }
+
+                    text.push('\u{2580}');
+                }
+
+                text.push_str("\x1b[0m\r\n");
+            }
+        }
+        TerminalMode::Braille => {
+            // dot numbering of the braille block, by (x, y) inside the cell
+            const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+            for y in (0..height).step_by(4) {
+                text.push_str(&foreground(palette.color(true)));
+                text.push_str(&background(palette.color(false)));
+
+                for x in (0..width).step_by(2) {
+                    let mut bits = 0;
+
+                    for (dx, column) in DOTS.iter().enumerate() {
+                        for (dy, dot) in column.iter().enumerate() {
+                            if x + dx < width && pixel(x + dx, y + dy) {
+                                bits |= dot;
+                            }
+                        }
+                    }
+
+                    text.push(char::from_u32(0x2800 + bits).unwrap_or(' '));
+                }
+
+                text.push_str("\x1b[0m\r\n");
+            }
+        }
+    }
+
+    text
+}
+
+pub fn render_status(
+    fault: Option<CrispAteFault>,
+    symbols: &CrispAteSymbols,
+    recording: bool,
+    paused: bool,
+    turbo: bool,
+) -> String {
+    let mut status = String::from("\x1b[0m\x1b[K");
+
+    match fault {
+        Some(fault) => status.push_str(&format!(
+            "\x1b[1;31mMACHINE FAULT: {}\x1b[0m",
+            fault.explain(symbols)
+        )),
+        None => status.push_str("F12 screenshot  F9 record  F2 palette  P pause  Esc quit"),
+    }
+
+    if paused {
+        status.push_str("  \x1b[33m[PAUSED]\x1b[0m");
+    } else if turbo {
+        status.push_str("  \x1b[33m[TURBO]\x1b[0m");
+    }
+
+    if recording {
+        status.push_str("  \x1b[31m[REC]\x1b[0m");
+    }
+
+    status.push_str("\r\n");
+    status
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::from(File::open("/dev/tty")?))
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+// Puts the terminal in raw mode on an alternate screen for as long as it is
+// alive. Raw mode goes through `stty`, so no terminal library is needed.
+// Input and output go straight to /dev/tty, which leaves stdout free for the
+// emulator's own logging (and redirectable away from the picture).
+pub struct CrispAteTerminal {
+    saved_mode: String,
+    tty: File,
+    input: Receiver<Vec<u8>>,
+}
+
+impl CrispAteTerminal {
+    pub fn new() -> io::Result<Self> {
+        let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        let mut keyboard = tty.try_clone()?;
+
+        let saved_mode = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+
+        // tty reads block, so they happen on their own thread and `poll`
+        // only picks up whatever arrived since the last frame
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 64];
+
+            while let Ok(read) = keyboard.read(&mut buffer) {
+                if read == 0 || sender.send(buffer[..read].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        tty.write_all(b"\x1b[?1049h\x1b[?25l\x1b[2J")?;
+
+        Ok(CrispAteTerminal {
+            saved_mode,
+            tty,
+            input,
+        })
+    }
+
+    pub fn poll(&self) -> Vec<TerminalEvent> {
+        let bytes: Vec<u8> = self.input.try_iter().flatten().collect();
+        parse_input(&bytes)
+    }
+
+    pub fn draw(&mut self, frame: &str) -> io::Result<()> {
+        self.tty.write_all(frame.as_bytes())?;
+        self.tty.flush()
+    }
+}
+
+impl Drop for CrispAteTerminal {
+    fn drop(&mut self) {
+        let _ = self.tty.write_all(b"\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = stty(&[&self.saved_mode]);
+    }
+}
+
+#[cfg(test)]
+mod terminal_tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys_and_hotkeys() {
+        assert_eq!(
+            parse_input(b"1Vx\x1b[24~\x1b[20~\x1bOQ\x1bOP\x1b[1;5Ap\t\x03"),
+            vec![
+                TerminalEvent::Key(0x1),
+                TerminalEvent::Key(0xF),
+                TerminalEvent::Key(0x0),
+                TerminalEvent::Screenshot,
+                TerminalEvent::ToggleRecording,
+                TerminalEvent::NextPalette,
+                TerminalEvent::Pause,
+                TerminalEvent::ToggleTurbo,
+                TerminalEvent::Quit,
+            ]
+        );
+        assert_eq!(parse_input(b"\x1b"), vec![TerminalEvent::Quit]);
+    }
+
+    #[test]
+    fn keys_stay_held_for_a_few_frames() {
+        let mut sut = TerminalKeypad::new();
+        sut.press(0xA);
+
+        for _ in 0..KEY_HOLD_FRAMES {
+            assert!(sut.next_frame()[0xA]);
+        }
+
+        assert_eq!(sut.next_frame(), [false; 16]);
+    }
+
+    #[test]
+    fn renders_half_blocks() {
+        let mut screen = [false; 64 * 32];
+        screen[0] = true;
+
+        let text = render(
+            &screen,
+            64,
+            TerminalMode::HalfBlock,
+            &CrispAtePalette::new(),
+        );
+        let lines: Vec<&str> = text.split("\r\n").collect();
+
+        assert_eq!(lines.len(), 16 + 1);
+        assert_eq!(lines[0].matches('\u{2580}').count(), 64);
+        assert!(lines[0].contains("\x1b[38;2;255;255;255m\x1b[48;2;80;80;80m\u{2580}"));
+    }
+
+    #[test]
+    fn renders_braille_for_hires_screens() {
+        let mut screen = [false; 128 * 64];
+        screen[0] = true; // dot 1
+        screen[128 * 3 + 1] = true; // dot 8
+
+        let text = render(&screen, 128, TerminalMode::Braille, &CrispAtePalette::new());
+        let lines: Vec<&str> = text.split("\r\n").collect();
+
+        assert_eq!(lines.len(), 16 + 1);
+        assert!(lines[0].contains('\u{2881}'));
+        assert_eq!(
+            lines[0]
+                .chars()
+                .filter(|c| ('\u{2800}'..='\u{28FF}').contains(c))
+                .count(),
+            64
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant, SystemTime};
+
+// how often the files are looked at, checking every frame would be wasteful
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// editors and assemblers often write a file in more than one go, so a change
+// only counts once the files stay the same for this long
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+// what a file looks like on disk, None while it is missing
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &PathBuf) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+// Watches files by polling their modification time and size, which works
+// the same everywhere and needs no extra crates.
+pub struct FileWatcher {
+    pub paths: Vec<PathBuf>,
+    stamps: Vec<Stamp>,
+    next_poll: Instant,
+    changed_at: Option<Instant>,
+}
+
+impl FileWatcher {
+    pub fn new(paths: Vec<PathBuf>, now: Instant) -> Self {
+        FileWatcher {
+            stamps: paths.iter().map(stamp).collect(),
+            paths,
+            next_poll: now,
+            changed_at: None,
+        }
+    }
+
+    // true once, after a change has settled
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if now < self.next_poll {
+            return false;
+        }
+        self.next_poll = now + POLL_INTERVAL;
+
+        let stamps: Vec<Stamp> = self.paths.iter().map(stamp).collect();
+        if stamps != self.stamps {
+            self.stamps = stamps;
+            self.changed_at = Some(now);
+            return false;
+        }
+
+        // a file that went missing is still being written
+        let settled = self
+            .changed_at
+            .is_some_and(|changed_at| now.duration_since(changed_at) >= SETTLE_TIME);
+        if settled && self.stamps.iter().all(Option::is_some) {
+            self.changed_at = None;
+            return true;
+        }
+
+        false
+    }
+}
+
+// runs a --build command through the shell, so it can be written the same way
+// as on the command line (`octo game.8o game.ch8`)
+pub fn run_build(command: &str) -> Result<(), String> {
+    let status = match cfg!(windows) {
+        true => Command::new("cmd").args(["/C", command]).status(),
+        false => Command::new("sh").args(["-c", command]).status(),
+    };
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("`{}` failed ({})", command, status)),
+        Err(e) => Err(format!("could not run `{}`: {}", command, e)),
+    }
+}
+
+#[cfg(test)]
+mod watch_tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn reports_a_change_once_it_settles() {
+        let path = env::temp_dir().join(format!("crisp-ate-watch-{}.ch8", std::process::id()));
+        fs::write(&path, [0x12, 0x00]).unwrap();
+
+        let start = Instant::now();
+        let mut sut = FileWatcher::new(vec![path.clone()], start);
+        assert!(!sut.poll(start));
+
+        fs::write(&path, [0x12, 0x00, 0x00, 0xE0]).unwrap();
+
+        // too early to even look
+        assert!(!sut.poll(start + POLL_INTERVAL / 2));
+        assert!(!sut.poll(start + POLL_INTERVAL));
+        assert!(!sut.poll(start + POLL_INTERVAL * 2));
+        assert!(sut.poll(start + POLL_INTERVAL * 3));
+        assert!(!sut.poll(start + POLL_INTERVAL * 4));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn waits_for_missing_files_to_come_back() {
+        let path = env::temp_dir().join(format!("crisp-ate-gone-{}.ch8", std::process::id()));
+        fs::write(&path, [0x12, 0x00]).unwrap();
+
+        let start = Instant::now();
+        let mut sut = FileWatcher::new(vec![path.clone()], start);
+
+        fs::remove_file(&path).unwrap();
+        assert!(!sut.poll(start));
+        assert!(!sut.poll(start + SETTLE_TIME * 2));
+
+        fs::write(&path, [0x12, 0x00]).unwrap();
+        assert!(!sut.poll(start + SETTLE_TIME * 4));
+        assert!(sut.poll(start + SETTLE_TIME * 6));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn runs_build_commands_through_the_shell() {
+        assert!(run_build("exit 0").is_ok());
+        assert!(run_build("exit 3").is_err());
+    }
+}
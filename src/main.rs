@@ -2,16 +2,257 @@ mod crisp_ate;
 use std::io;
 use std::process;
 mod utils;
+use crisp_ate::analyzer::CrispAteAnalysis;
+use crisp_ate::capture::CrispAteCapture;
+use crisp_ate::coverage::CrispAteCoverage;
 use crisp_ate::cpu::CrispAte;
+use crisp_ate::dap::DapServer;
 use crisp_ate::display::create_display;
+use crisp_ate::fault::FaultPolicy;
+use crisp_ate::filter::{CrispAteDisplayFilter, FilterMode};
+use crisp_ate::gdb::GdbServer;
+use crisp_ate::hud::CrispAteHud;
+use crisp_ate::library::{find_thumbnail, scan_roms, CrispAteLauncher, RecentFiles};
+use crisp_ate::memory_view::{CrispAteMemoryView, MemoryViewAction};
+use crisp_ate::movie::{CrispAteMovie, MovieHeader, CORE_VERSION, QUIRKS};
+use crisp_ate::palette::CrispAtePalette;
+use crisp_ate::profiler::CrispAteProfiler;
+use crisp_ate::random::{create_random, RandomSource};
+use crisp_ate::recompiler::CrispAteRecompiler;
+use crisp_ate::savestate::CrispAteSaveState;
+use crisp_ate::screenshot::{save_png, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crisp_ate::symbols::CrispAteSymbols;
+use crisp_ate::terminal::{
+    render, render_status, CrispAteTerminal, TerminalEvent, TerminalKeypad, TerminalMode,
+};
+use crisp_ate::watch::{run_build, FileWatcher};
 use dialog::DialogBox;
+use raylib::prelude::{RaylibHandle, RaylibThread};
 use std::env;
 use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::crisp_ate::display::draw_frame;
+use crate::crisp_ate::display::{
+    draw_fault, draw_frame, draw_launcher, draw_memory_view, dropped_rom, hud_toggled,
+    launcher_input, launcher_requested, load_state_requested, load_thumbnail, memory_view_input,
+    memory_view_toggled, palette_switched, pause_toggled, read_keypad, recording_toggled,
+    save_state_requested, screenshot_requested, state_slot_switched, turbo_held, update_window,
+    ScreenTexture, SCALING,
+};
+use crate::utils::fnv1a;
 
 const MAX_PROGRAM_SIZE: usize = 3584;
 
+const MOVIE_CHECKSUM_INTERVAL: usize = 60;
+
+const TERMINAL_FRAME_TIME: Duration = Duration::from_micros(1_000_000 / 60);
+
+// a 64x32 screen at this scale is 4096x2048, plenty for any screenshot
+const MAX_SCREENSHOT_SCALE: usize = 64;
+
+// cycles run per rendered frame while turbo (Tab) is held
+const TURBO_CYCLES: usize = 8;
+
+const USAGE: &str = "Usage: crisp-ate [fileName] [--fault-policy halt|wrap|ignore] \
+[--rng splitmix|vip] [--seed <number>] [--record-movie <file>] [--play-movie <file>] \
+[--screenshot-at-frame <frame>] [--screenshot-scale <scale>] [--recompile] \
+[--record-gif <file>] [--capture-raw] [--terminal halfblock|braille] [--palette <name>|<colors>] \
+[--filter raw|phosphor|vblank] [--phosphor-decay <0-1>] [--fullscreen] [--smooth-scaling] \
+[--rom-dir <dir>] [--hot-reload] [--watch <file>] [--build <command>] [--gdb <port>] \
+[--dap <port>] [--symbols <file>] [--profile <file>] [--profile-folded <file>] \
+[--coverage <file>] [--coverage-lcov <file>] [--analyze <file>]";
+
+struct Options {
+    filename: String, // empty until a ROM is picked in the launcher
+    fault_policy: FaultPolicy,
+    rng: String,
+    seed: u64,
+    record_movie: Option<String>,
+    play_movie: Option<String>,
+    screenshot_at_frame: Option<usize>,
+    screenshot_scale: usize,
+    recompile: bool,
+    record_gif: Option<String>,
+    capture_raw: bool,
+    terminal: Option<TerminalMode>,
+    palette: Option<CrispAtePalette>,
+    filter: FilterMode,
+    fullscreen: bool,
+    smooth_scaling: bool,
+    rom_directory: String,
+    hot_reload: bool,
+    watch: Option<String>,
+    build: Option<String>,
+    gdb_port: Option<u16>,
+    dap_port: Option<u16>,
+    symbols: Option<String>,
+    profile: Option<String>,
+    profile_folded: Option<String>,
+    coverage: Option<String>,
+    coverage_lcov: Option<String>,
+    analyze: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Option<Options> {
+    let mut filename: Option<String> = None;
+    let mut fault_policy = FaultPolicy::Halt;
+    let mut rng = String::from("splitmix");
+    let mut seed: Option<u64> = None;
+    let mut record_movie: Option<String> = None;
+    let mut play_movie: Option<String> = None;
+    let mut screenshot_at_frame: Option<usize> = None;
+    let mut screenshot_scale = SCALING as usize;
+    let mut recompile = false;
+    let mut record_gif: Option<String> = None;
+    let mut capture_raw = false;
+    let mut terminal: Option<TerminalMode> = None;
+    let mut palette: Option<CrispAtePalette> = None;
+    let mut filter = String::from("raw");
+    let mut phosphor_decay: f32 = 0.6;
+    let mut fullscreen = false;
+    let mut smooth_scaling = false;
+    let mut rom_directory = String::from(".");
+    let mut hot_reload = false;
+    let mut watch: Option<String> = None;
+    let mut build: Option<String> = None;
+    let mut gdb_port: Option<u16> = None;
+    let mut dap_port: Option<u16> = None;
+    let mut symbols: Option<String> = None;
+    let mut profile: Option<String> = None;
+    let mut profile_folded: Option<String> = None;
+    let mut coverage: Option<String> = None;
+    let mut coverage_lcov: Option<String> = None;
+    let mut analyze: Option<String> = None;
+
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fault-policy" => fault_policy = FaultPolicy::from_name(args.next()?)?,
+            "--rng" => rng = args.next()?.to_owned(),
+            "--seed" => seed = Some(args.next()?.parse().ok()?),
+            "--record-movie" => record_movie = Some(args.next()?.to_owned()),
+            "--play-movie" => play_movie = Some(args.next()?.to_owned()),
+            "--screenshot-at-frame" => screenshot_at_frame = Some(args.next()?.parse().ok()?),
+            "--screenshot-scale" => {
+                screenshot_scale = args
+                    .next()?
+                    .parse()
+                    .ok()
+                    .filter(|scale| (1..=MAX_SCREENSHOT_SCALE).contains(scale))?
+            }
+            "--recompile" => recompile = true,
+            "--record-gif" => record_gif = Some(args.next()?.to_owned()),
+            "--capture-raw" => capture_raw = true,
+            "--terminal" => terminal = Some(TerminalMode::from_name(args.next()?)?),
+            "--palette" => palette = Some(CrispAtePalette::parse(args.next()?)?),
+            "--filter" => filter = args.next()?.to_owned(),
+            "--phosphor-decay" => {
+                phosphor_decay = args
+                    .next()?
+                    .parse()
+                    .ok()
+                    .filter(|decay| (0.0..1.0).contains(decay))?
+            }
+            "--fullscreen" => fullscreen = true,
+            "--smooth-scaling" => smooth_scaling = true,
+            "--rom-dir" => rom_directory = args.next()?.to_owned(),
+            "--hot-reload" => hot_reload = true,
+            "--watch" => watch = Some(args.next()?.to_owned()),
+            "--build" => build = Some(args.next()?.to_owned()),
+            "--gdb" => gdb_port = Some(args.next()?.parse().ok()?),
+            "--dap" => dap_port = Some(args.next()?.parse().ok()?),
+            "--symbols" => symbols = Some(args.next()?.to_owned()),
+            "--profile" => profile = Some(args.next()?.to_owned()),
+            "--profile-folded" => profile_folded = Some(args.next()?.to_owned()),
+            "--coverage" => coverage = Some(args.next()?.to_owned()),
+            "--coverage-lcov" => coverage_lcov = Some(args.next()?.to_owned()),
+            "--analyze" => analyze = Some(args.next()?.to_owned()),
+            _ if filename.is_none() => filename = Some(arg.to_owned()),
+            _ => return None,
+        }
+    }
+
+    // without an explicit seed every run is different, but the one we picked
+    // is printed so the run can be reproduced with --seed
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(0)
+    });
+
+    // checked up front, so a typo is reported before anything starts
+    create_random(&rng, seed)?;
+
+    Some(Options {
+        filename: filename.unwrap_or_default(),
+        fault_policy,
+        rng,
+        seed,
+        record_movie,
+        play_movie,
+        screenshot_at_frame,
+        screenshot_scale,
+        recompile,
+        record_gif,
+        capture_raw,
+        terminal,
+        palette,
+        filter: FilterMode::from_name(&filter, phosphor_decay)?,
+        fullscreen,
+        smooth_scaling,
+        rom_directory,
+        // watching a source file or rebuilding only makes sense when reloading
+        hot_reload: hot_reload || watch.is_some() || build.is_some(),
+        watch,
+        build,
+        gdb_port,
+        dap_port,
+        symbols,
+        profile,
+        profile_folded,
+        coverage,
+        coverage_lcov,
+        analyze,
+    })
+}
+
+impl Options {
+    // --palette wins over the ROM's own palette file
+    fn palette(&self) -> CrispAtePalette {
+        self.palette
+            .or_else(|| CrispAtePalette::for_rom(&self.filename))
+            .unwrap_or_default()
+    }
+
+    // --symbols wins over the ROM's own symbol file
+    fn symbols(&self) -> CrispAteSymbols {
+        match &self.symbols {
+            Some(filename) => CrispAteSymbols::load(Path::new(filename)).unwrap_or_else(|e| {
+                eprintln!("Could not read {}: {}", filename, e);
+                CrispAteSymbols::new()
+            }),
+            None => CrispAteSymbols::for_rom(&self.filename),
+        }
+    }
+
+    // counting costs time, so only when a profile was asked for
+    fn profiler(&self) -> Option<CrispAteProfiler> {
+        (self.profile.is_some() || self.profile_folded.is_some()).then(CrispAteProfiler::new)
+    }
+
+    fn coverage(&self, program_size: usize) -> Option<CrispAteCoverage> {
+        (self.coverage.is_some() || self.coverage_lcov.is_some())
+            .then(|| CrispAteCoverage::new(program_size))
+    }
+
+    fn random(&self) -> Box<dyn RandomSource> {
+        create_random(&self.rng, self.seed).expect("rng was checked by parse_args")
+    }
+}
+
 fn get_program_bytes(filename: &str) -> Option<Vec<u8>> {
     match std::fs::read(filename) {
         Ok(bytes) => Some(bytes),
@@ -32,28 +273,659 @@ fn get_program_bytes(filename: &str) -> Option<Vec<u8>> {
     }
 }
 
-fn create_and_start_vm(
-    program_bytes: Vec<u8>,
+// the ROM's bytes, if it can be read and fits in memory
+fn load_program(filename: &str) -> Option<Vec<u8>> {
+    let bytes = get_program_bytes(filename)?;
+
+    if bytes.len() > MAX_PROGRAM_SIZE {
+        eprintln!("File is too big for emulator!");
+        return None;
+    }
+
+    Some(bytes)
+}
+
+// the ROM's file name without its extension
+fn rom_title(rom_filename: &str) -> &str {
+    Path::new(rom_filename)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("crisp-ate")
+}
+
+// <rom name>-<frame>.<extension>, next to wherever the emulator was started from
+fn output_filename(rom_filename: &str, frame: usize, extension: &str) -> String {
+    format!("{}-{}.{}", rom_title(rom_filename), frame, extension)
+}
+
+// <rom name>-slot<slot>.state
+fn state_filename(rom_filename: &str, slot: usize) -> String {
+    format!("{}-slot{}.state", rom_title(rom_filename), slot)
+}
+
+// what the HUD says about it
+fn save_state(vm: &CrispAte, rom_hash: u64, filename: &str, slot: usize) -> String {
+    match CrispAteSaveState::capture(vm, rom_hash).save(filename) {
+        Ok(()) => format!("State saved to slot {}", slot),
+        Err(e) => {
+            eprintln!("Could not save state to {}: {}", filename, e);
+            format!("Could not save slot {}", slot)
+        }
+    }
+}
+
+fn load_state(vm: &mut CrispAte, rom_hash: u64, filename: &str, slot: usize) -> String {
+    let loaded = CrispAteSaveState::load(filename).and_then(|state| match state.rom_hash {
+        hash if hash == rom_hash => state.restore(vm),
+        _ => Err(String::from("State was saved with a different ROM")),
+    });
+
+    match loaded {
+        Ok(()) => format!("State loaded from slot {}", slot),
+        Err(e) => {
+            eprintln!("Could not load {}: {}", filename, e);
+            format!("Could not load slot {}", slot)
+        }
+    }
+}
+
+fn save_screenshot(vm: &CrispAte, filename: &str, scale: usize, palette: &CrispAtePalette) -> bool {
+    match save_png(filename, &vm.screen, scale, palette) {
+        Ok(()) => {
+            println!("Screenshot saved to {}", filename);
+            true
+        }
+        Err(e) => {
+            eprintln!("Could not save screenshot: {}", e);
+            false
+        }
+    }
+}
+
+fn start_recording(
+    filename: &str,
+    options: &Options,
+    palette: &CrispAtePalette,
+) -> Option<CrispAteCapture> {
+    let capture = CrispAteCapture::start(
+        filename,
+        options.screenshot_scale,
+        palette,
+        options.capture_raw,
+    );
+
+    match capture {
+        Ok(capture) => {
+            println!("Recording to {}", filename);
+            Some(capture)
+        }
+        Err(e) => {
+            eprintln!("Could not start recording: {}", e);
+            None
+        }
+    }
+}
+
+fn stop_recording(capture: CrispAteCapture) {
+    let filename = capture.filename.clone();
+    let frames = capture.frames();
+
+    match capture.finish() {
+        Ok(()) => println!("Recording saved to {} ({} frames)", filename, frames),
+        Err(e) => eprintln!("Could not save recording: {}", e),
+    }
+}
+
+// F9: stops the running recording, or starts a new one named after the frame
+fn toggle_recording(
+    capture: Option<CrispAteCapture>,
+    options: &Options,
+    frame: usize,
+    palette: &CrispAtePalette,
+) -> Option<CrispAteCapture> {
+    match capture {
+        Some(capture) => {
+            stop_recording(capture);
+            None
+        }
+        None => {
+            let filename = output_filename(&options.filename, frame, "gif");
+            start_recording(&filename, options, palette)
+        }
+    }
+}
+
+// fed from the emulation clock: one recorded frame per cycle, however long
+// rendering it took
+fn record_frame(capture: &mut Option<CrispAteCapture>, vm: &CrispAte) {
+    if let Some(recording) = capture.as_mut() {
+        if let Err(e) = recording.record(vm) {
+            eprintln!("Recording stopped: {}", e);
+            *capture = None;
+        }
+    }
+}
+
+fn start_movie(options: &Options, vm: &CrispAte, program_bytes: &[u8]) -> Option<CrispAteMovie> {
+    options.record_movie.as_ref().map(|_| {
+        CrispAteMovie::new(MovieHeader {
+            core_version: String::from(CORE_VERSION),
+            rom_hash: fnv1a(program_bytes),
+            rng: String::from(vm.random.name()),
+            seed: vm.random.seed(),
+            fault_policy: vm.fault_policy,
+            quirks: String::from(QUIRKS),
+            checksum_interval: MOVIE_CHECKSUM_INTERVAL,
+        })
+    })
+}
+
+fn save_movie(movie: Option<CrispAteMovie>, options: &Options) {
+    if let (Some(movie), Some(movie_filename)) = (movie, &options.record_movie) {
+        match movie.save(movie_filename) {
+            Ok(()) => println!("Movie saved to {}", movie_filename),
+            Err(e) => eprintln!("Could not save movie: {}", e),
+        }
+    }
+}
+
+fn save_profile(vm: &CrispAte, options: &Options) {
+    if let (Some(profiler), Some(filename)) = (&vm.profiler, &options.profile) {
+        match profiler.save_report(filename, &vm.memory, &vm.symbols) {
+            Ok(()) => println!("Profile saved to {}", filename),
+            Err(e) => eprintln!("Could not save profile: {}", e),
+        }
+    }
+
+    if let (Some(profiler), Some(filename)) = (&vm.profiler, &options.profile_folded) {
+        match profiler.save_folded(filename, &vm.symbols) {
+            Ok(()) => println!("Call stacks saved to {}", filename),
+            Err(e) => eprintln!("Could not save call stacks: {}", e),
+        }
+    }
+}
+
+fn save_coverage(vm: &CrispAte, options: &Options) {
+    if let (Some(coverage), Some(filename)) = (&vm.coverage, &options.coverage) {
+        match coverage.save_annotated(filename, &vm.memory, &vm.symbols) {
+            Ok(()) => println!("Coverage saved to {}", filename),
+            Err(e) => eprintln!("Could not save coverage: {}", e),
+        }
+    }
+
+    if let (Some(coverage), Some(filename)) = (&vm.coverage, &options.coverage_lcov) {
+        match coverage.save_lcov(filename, &vm.memory, &vm.symbols) {
+            Ok(()) => println!("Coverage saved to {}", filename),
+            Err(e) => eprintln!("Could not save coverage: {}", e),
+        }
+    }
+}
+
+fn create_vm(
+    program_bytes: &[u8],
     mut available_memory: [u8; MAX_PROGRAM_SIZE],
     debug_mode: bool,
-) {
+    fault_policy: FaultPolicy,
+    random: Box<dyn RandomSource>,
+) -> CrispAte {
     for (i, byte) in program_bytes.iter().enumerate() {
         available_memory[i] = byte.to_owned()
     }
 
     let mut vm = CrispAte::new(debug_mode);
+    vm.fault_policy = fault_policy;
+    vm.random = random;
+    println!(
+        "Random number generator: {} (seed: {})",
+        vm.random.name(),
+        vm.random.seed()
+    );
 
     println!("Initializing VM...");
     vm.init(available_memory);
     println!("VM initialized!");
 
-    let (mut rl, thread) = create_display();
+    vm
+}
+
+fn play_movie(
+    program_bytes: Vec<u8>,
+    available_memory: [u8; MAX_PROGRAM_SIZE],
+    movie_filename: &str,
+    recompile: bool,
+) {
+    let movie = match CrispAteMovie::load(movie_filename) {
+        Ok(movie) => movie,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if movie.header.rom_hash != fnv1a(&program_bytes) {
+        eprintln!("Movie was recorded with a different ROM!");
+        process::exit(1);
+    }
+
+    if movie.header.quirks != QUIRKS {
+        eprintln!(
+            "Movie was recorded with {} quirks, this core only has {}!",
+            movie.header.quirks, QUIRKS
+        );
+        process::exit(1);
+    }
+
+    if movie.header.core_version != CORE_VERSION {
+        eprintln!(
+            "Movie was recorded with core {} (this is {}), it may desync.",
+            movie.header.core_version, CORE_VERSION
+        );
+    }
+
+    let random = match create_random(&movie.header.rng, movie.header.seed) {
+        Some(random) => random,
+        None => {
+            eprintln!(
+                "Unknown random number generator in movie: {}",
+                movie.header.rng
+            );
+            process::exit(1);
+        }
+    };
+
+    let mut vm = create_vm(
+        &program_bytes,
+        available_memory,
+        false,
+        movie.header.fault_policy,
+        random,
+    );
+    let mut recompiler = recompile.then(CrispAteRecompiler::new);
+    let desyncs = movie.play(&mut vm, recompiler.as_mut());
+
+    if desyncs.is_empty() {
+        println!(
+            "Movie played back: {} frames, no desyncs.",
+            movie.frames.len()
+        );
+        return;
+    }
+
+    for desync in &desyncs {
+        eprintln!(
+            "Desync at frame {}: expected checksum {:016x}, got {:016x}",
+            desync.frame, desync.expected, desync.actual
+        );
+    }
+
+    process::exit(1);
+}
+
+// runs the ROM without a window (and without input) and saves the screen as
+// it is after `frame` frames
+fn take_screenshot(
+    program_bytes: Vec<u8>,
+    available_memory: [u8; MAX_PROGRAM_SIZE],
+    options: Options,
+    frame: usize,
+) {
+    let mut vm = create_vm(
+        &program_bytes,
+        available_memory,
+        false,
+        options.fault_policy,
+        options.random(),
+    );
+    vm.symbols = options.symbols();
+    vm.profiler = options.profiler();
+    vm.coverage = options.coverage(program_bytes.len());
+
+    let result = match options.recompile {
+        true => CrispAteRecompiler::new().run(&mut vm, frame),
+        false => (0..frame).try_for_each(|_| vm.emulation_cyle()),
+    };
+    if let Err(fault) = result {
+        eprintln!("{}", fault.explain(&vm.symbols));
+    }
+
+    save_screenshot(
+        &vm,
+        &output_filename(&options.filename, frame, "png"),
+        options.screenshot_scale,
+        &options.palette(),
+    );
+    save_profile(&vm, &options);
+    save_coverage(&vm, &options);
+}
+
+// looks at the ROM without running it: prints what it found and any
+// warnings, and writes the control flow graph for Graphviz
+fn analyze_rom(program_bytes: &[u8], options: &Options, filename: &str) {
+    let analysis = CrispAteAnalysis::analyze(program_bytes);
+    let symbols = options.symbols();
+
+    println!("{}", analysis.summary());
+    for warning in &analysis.warnings {
+        println!("Warning: {}", warning);
+    }
+
+    let dot = analysis.dot(rom_title(&options.filename), &symbols);
+    match std::fs::write(filename, dot) {
+        Ok(()) => println!("Control flow graph saved to {}", filename),
+        Err(e) => {
+            eprintln!("Could not save control flow graph: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// the movie only has the keypad, anything a debugger writes would desync it
+fn refuse_edits_while_recording(
+    gdb: &mut Option<GdbServer>,
+    dap: &mut Option<DapServer>,
+    recording: bool,
+) {
+    if let Some(gdb) = gdb.as_mut() {
+        gdb.stub.read_only = recording;
+    }
+
+    if let Some(dap) = dap.as_mut() {
+        dap.session.read_only = recording;
+    }
+}
+
+fn start_gdb(port: u16) -> GdbServer {
+    match GdbServer::bind(port) {
+        Ok(server) => {
+            println!("GDB server listening on 127.0.0.1:{}", port);
+            server
+        }
+        Err(e) => {
+            eprintln!("Could not start the GDB server on port {}: {}", port, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn start_dap(port: u16) -> DapServer {
+    match DapServer::bind(port) {
+        Ok(server) => {
+            println!("Debug adapter listening on 127.0.0.1:{}", port);
+            server
+        }
+        Err(e) => {
+            eprintln!("Could not start the debug adapter on port {}: {}", port, e);
+            process::exit(1);
+        }
+    }
+}
+
+// why `create_and_start_vm` (or `run_rom_in_terminal`) returned
+enum WindowExit {
+    Quit,
+    Launcher,     // F1
+    Load(String), // a ROM was dropped on the window
+    Reload,       // --hot-reload saw the ROM (or --watch file) change
+}
+
+// what stays while ROMs come and go in the window
+struct Window {
+    rl: RaylibHandle,
+    thread: RaylibThread,
+    screen: ScreenTexture,
+    gdb: Option<GdbServer>, // a debugger stays attached across ROMs and reloads
+    dap: Option<DapServer>, // and so does an editor
+}
+
+fn create_and_start_vm(
+    window: &mut Window,
+    program_bytes: Vec<u8>,
+    debug_mode: bool,
+    options: &Options,
+    message: Option<&str>,
+) -> WindowExit {
+    let Window {
+        rl,
+        thread,
+        screen,
+        gdb,
+        dap,
+    } = window;
+    let mut vm = create_vm(
+        &program_bytes,
+        [0; MAX_PROGRAM_SIZE],
+        debug_mode,
+        options.fault_policy,
+        options.random(),
+    );
+    vm.symbols = options.symbols();
+    vm.profiler = options.profiler();
+    vm.coverage = options.coverage(program_bytes.len());
+
+    if let Some(dap) = dap.as_mut() {
+        dap.started(&mut vm);
+    }
+
+    let mut movie = start_movie(options, &vm, &program_bytes);
+    refuse_edits_while_recording(gdb, dap, movie.is_some());
+    let rom_hash = fnv1a(&program_bytes);
+
+    let mut palette = options.palette();
+    let mut filter = CrispAteDisplayFilter::new(options.filter);
+    let mut screen_changed = true;
+    let mut hud = CrispAteHud::new(rom_title(&options.filename), Instant::now());
+    if let Some(message) = message {
+        hud.toast(message, Instant::now());
+    }
+    let mut watcher = options.hot_reload.then(|| {
+        let mut paths = vec![PathBuf::from(&options.filename)];
+        paths.extend(options.watch.iter().map(PathBuf::from));
+        FileWatcher::new(paths, Instant::now())
+    });
     let mut history: Vec<String> = Vec::new();
+    let mut frame: usize = 0;
+    let mut memory_view = CrispAteMemoryView::new(program_bytes.len());
+    memory_view.visible = debug_mode;
+    let mut capture = options
+        .record_gif
+        .as_ref()
+        .and_then(|filename| start_recording(filename, options, &palette));
 
-    while !rl.window_should_close() {
-        let d = rl.begin_drawing(&thread);
-        draw_frame(vm.screen, d);
-        vm.emulation_cyle();
+    let exit = loop {
+        if rl.window_should_close() {
+            break WindowExit::Quit;
+        }
+
+        if launcher_requested(rl) {
+            break WindowExit::Launcher;
+        }
+
+        if let Some(filename) = dropped_rom(rl) {
+            break WindowExit::Load(filename);
+        }
+
+        if watcher
+            .as_mut()
+            .is_some_and(|watcher| watcher.poll(Instant::now()))
+        {
+            // a broken build keeps the old ROM running
+            match options.build.as_deref().map(run_build) {
+                Some(Err(e)) => {
+                    eprintln!("Build failed: {}", e);
+                    hud.toast("Build failed, see the console", Instant::now());
+                }
+                _ => break WindowExit::Reload,
+            }
+        }
+
+        update_window(rl);
+        vm.keypad = read_keypad(rl);
+
+        if let Some(gdb) = gdb.as_mut() {
+            gdb.poll(&mut vm);
+        }
+
+        if let Some(dap) = dap.as_mut() {
+            dap.poll(Some(&mut vm));
+
+            if let Some(program) = dap.session.take_launch() {
+                break WindowExit::Load(program);
+            }
+        }
+
+        if screenshot_requested(rl) {
+            let filename = output_filename(&options.filename, frame, "png");
+            if save_screenshot(&vm, &filename, options.screenshot_scale, &palette) {
+                hud.toast(&format!("Screenshot saved to {}", filename), Instant::now());
+            }
+        }
+
+        if state_slot_switched(rl) {
+            hud.next_slot();
+        }
+
+        if save_state_requested(rl) {
+            let filename = state_filename(&options.filename, hud.slot);
+            let message = save_state(&vm, rom_hash, &filename, hud.slot);
+            hud.toast(&message, Instant::now());
+        }
+
+        if load_state_requested(rl) {
+            // the movie only has the keypad, jumping around would desync it
+            let message = match movie {
+                Some(_) => String::from("Can't load states while recording a movie"),
+                None => {
+                    let filename = state_filename(&options.filename, hud.slot);
+                    load_state(&mut vm, rom_hash, &filename, hud.slot)
+                }
+            };
+            hud.toast(&message, Instant::now());
+            screen_changed |= filter.show(&vm);
+        }
+
+        if palette_switched(rl) {
+            palette = palette.next();
+            println!("Palette: {}", palette.name);
+            hud.toast(&format!("Palette: {}", palette.name), Instant::now());
+            screen_changed = true;
+        }
+
+        if recording_toggled(rl) {
+            let was_recording = capture.is_some();
+            capture = toggle_recording(capture.take(), options, frame, &palette);
+
+            match &capture {
+                Some(recording) => hud.toast(
+                    &format!("Recording to {}", recording.filename),
+                    Instant::now(),
+                ),
+                None if was_recording => hud.toast("Recording saved", Instant::now()),
+                None => {}
+            }
+        }
+
+        if hud_toggled(rl) {
+            hud.visible = !hud.visible;
+        }
+
+        if pause_toggled(rl) {
+            hud.paused = !hud.paused;
+        }
+
+        hud.turbo = turbo_held(rl);
+
+        if memory_view_toggled(rl) {
+            memory_view.visible = !memory_view.visible;
+        }
+
+        if memory_view.visible {
+            // stopped by P or by an attached debugger
+            let paused = hud.paused
+                || gdb.as_ref().is_some_and(|gdb| gdb.stub.cycles(1) == 0)
+                || dap.as_ref().is_some_and(|dap| dap.session.cycles(1) == 0);
+
+            for action in memory_view_input(rl) {
+                if movie.is_some() && matches!(action, MemoryViewAction::Digit(_)) {
+                    hud.toast("Can't edit while recording a movie", Instant::now());
+                    continue;
+                }
+                memory_view.apply(action, &mut vm, paused);
+            }
+            memory_view.update(&vm);
+        }
+
+        if screen_changed {
+            let levels = filter.levels();
+            if let Err(error) = screen.upload(rl, thread, &levels, SCREEN_WIDTH, &palette) {
+                eprintln!("Could not update the screen texture: {}", error);
+            }
+            screen_changed = false;
+        }
+
+        // the frame ends with this block, before the machine runs
+        {
+            let mut d = rl.begin_drawing(thread);
+
+            if let Some(fault) = vm.fault {
+                draw_fault(fault, &vm.symbols, d);
+                continue;
+            }
+
+            draw_frame(screen, options.smooth_scaling, &hud, &mut d);
+            if memory_view.visible {
+                draw_memory_view(&mut memory_view, &vm, &mut d);
+            }
+        }
+
+        let cycles = match (hud.paused, hud.turbo) {
+            (true, _) => 0,
+            (false, true) => TURBO_CYCLES,
+            (false, false) => 1,
+        };
+        // an attached debugger decides when the machine runs
+        let cycles = gdb.as_ref().map_or(cycles, |gdb| gdb.stub.cycles(cycles));
+        let cycles = dap
+            .as_ref()
+            .map_or(cycles, |dap| dap.session.cycles(cycles));
+        let mut executed: u64 = 0;
+
+        for _ in 0..cycles {
+            if gdb.as_mut().is_some_and(|gdb| !gdb.before_cycle(&vm)) {
+                break;
+            }
+
+            if dap.as_mut().is_some_and(|dap| !dap.before_cycle(&vm)) {
+                break;
+            }
+
+            if let Err(fault) = vm.emulation_cyle() {
+                eprintln!("{}", fault.explain(&vm.symbols));
+            }
+            executed += 1;
+
+            if let Some(gdb) = gdb.as_mut() {
+                gdb.after_cycle(&vm);
+            }
+
+            if let Some(dap) = dap.as_mut() {
+                dap.after_cycle(&vm);
+            }
+            screen_changed |= filter.observe(&vm);
+            vm.registers.draw_flag = false;
+            frame += 1;
+            record_frame(&mut capture, &vm);
+
+            if let Some(movie) = movie.as_mut() {
+                movie.record(&vm);
+            }
+
+            if vm.fault.is_some() {
+                break;
+            }
+        }
+
+        screen_changed |= filter.fade(&vm);
+        hud.frame(executed, Instant::now());
 
         let state_report = format!(
             "History: \n {:#?} \n Continue execution?",
@@ -66,7 +938,8 @@ fn create_and_start_vm(
 
         vm.registers.history = Vec::new();
 
-        if vm.registers.debug_mode == true {
+        // nothing to confirm while paused, which leaves time to edit memory
+        if vm.registers.debug_mode == true && executed > 0 {
             let choice = dialog::Question::new(state_report)
                 .title("CrispAte")
                 .show()
@@ -78,45 +951,427 @@ fn create_and_start_vm(
                 panic!("Stopped!")
             }
         }
+    };
+
+    if let Some(capture) = capture {
+        stop_recording(capture);
     }
+
+    save_movie(movie, options);
+    save_profile(&vm, options);
+    save_coverage(&vm, options);
+    exit
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 || args.len() > 2 {
-        println!("Usage: crisp-ate <fileName>");
-        std::process::exit(1);
+// ROMs from --rom-dir and the recently played ones, until one is picked (here
+// or by an editor's launch request) or the window is closed
+fn run_launcher(window: &mut Window, options: &Options, recent: &RecentFiles) -> Option<String> {
+    let Window {
+        rl, thread, dap, ..
+    } = window;
+    let roms = scan_roms(Path::new(&options.rom_directory)).unwrap_or_else(|e| {
+        eprintln!("Could not read {}: {}", options.rom_directory, e);
+        Vec::new()
+    });
+    let mut launcher = CrispAteLauncher::new(roms, recent);
+    let mut shown: Option<PathBuf> = None;
+    let mut thumbnail = None;
+
+    while !rl.window_should_close() {
+        update_window(rl);
+
+        if let Some(filename) = dropped_rom(rl) {
+            return Some(filename);
+        }
+
+        if let Some(rom) = launcher_input(rl).and_then(|action| launcher.apply(action)) {
+            return Some(rom.to_string_lossy().into_owned());
+        }
+
+        if let Some(dap) = dap.as_mut() {
+            dap.poll(None);
+
+            if let Some(program) = dap.session.take_launch() {
+                return Some(program);
+            }
+        }
+
+        // screenshots are saved where the emulator runs, see `output_filename`
+        let selected = launcher.selected_entry().map(|entry| entry.path.clone());
+        if selected != shown {
+            thumbnail = selected
+                .as_ref()
+                .and_then(|rom| find_thumbnail(Path::new("."), rom))
+                .and_then(|path| load_thumbnail(rl, thread, &path));
+            shown = selected;
+        }
+
+        let d = rl.begin_drawing(thread);
+        draw_launcher(&launcher, &options.rom_directory, thumbnail.as_ref(), d);
     }
 
-    let filename = &args[1];
+    None
+}
 
-    let available_memory: [u8; 3584] = [0; 3584];
+// Runs ROMs in the window until it is closed, going back to the launcher on
+// F1 and switching to whatever ROM gets dropped on it.
+fn run_window(mut options: Options, debug_mode: bool) {
+    let (mut rl, thread) = create_display();
+    if options.fullscreen {
+        rl.toggle_fullscreen();
+    }
+    let screen = match ScreenTexture::new(&mut rl, &thread, SCREEN_WIDTH, SCREEN_HEIGHT) {
+        Ok(screen) => screen,
+        Err(error) => {
+            eprintln!("Could not create the screen texture: {}", error);
+            std::process::exit(1);
+        }
+    };
+    let mut recent = RecentFiles::load();
+    let mut window = Window {
+        rl,
+        thread,
+        screen,
+        gdb: options.gdb_port.map(start_gdb),
+        dap: options.dap_port.map(start_dap),
+    };
+    let mut next = Some(options.filename.clone()).filter(|filename| !filename.is_empty());
+    let mut message: Option<&str> = None;
+
+    loop {
+        let filename = match next.take() {
+            Some(filename) => filename,
+            None => match run_launcher(&mut window, &options, &recent) {
+                Some(filename) => filename,
+                None => break,
+            },
+        };
+
+        // back to the launcher if it can't be loaded
+        let program_bytes = match load_program(&filename) {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+
+        recent.add(Path::new(&filename));
+        if let Err(e) = recent.save() {
+            eprintln!("Could not save the recent files: {}", e);
+        }
+
+        options.filename = filename;
+        let exit = create_and_start_vm(
+            &mut window,
+            program_bytes,
+            debug_mode,
+            &options,
+            message.take(),
+        );
+
+        // --record-gif and --record-movie are for the first ROM only
+        options.record_gif = None;
+        options.record_movie = None;
 
-    let program_bytes = get_program_bytes(filename);
+        match exit {
+            WindowExit::Quit => break,
+            WindowExit::Launcher => next = None,
+            WindowExit::Load(filename) => next = Some(filename),
+            WindowExit::Reload => {
+                println!("Reloading {}", options.filename);
+                next = Some(options.filename.clone());
+                message = Some("ROM reloaded");
+            }
+        }
+    }
+
+    // an attached editor ends its debug session with the window
+    if let Some(dap) = window.dap.as_mut() {
+        dap.terminated();
+    }
+}
 
-    match program_bytes {
-        Some(bytes) => {
-            if bytes.len() > available_memory.len() {
-                eprintln!("File is too big for emulator!");
-                std::process::exit(1);
+// `run_window` for machines without a display: the ROM from the command line,
+// then whatever reloads or an editor's launch request bring in
+fn run_in_terminal(mut program_bytes: Vec<u8>, mut options: Options, mode: TerminalMode) {
+    let mut gdb = options.gdb_port.map(start_gdb);
+    let mut dap = options.dap_port.map(start_dap);
+
+    loop {
+        let exit = run_rom_in_terminal(&mut gdb, &mut dap, &program_bytes, &options, mode);
+
+        // --record-gif and --record-movie are for the first ROM only
+        options.record_gif = None;
+        options.record_movie = None;
+
+        let filename = match exit {
+            WindowExit::Quit | WindowExit::Launcher => break,
+            WindowExit::Load(filename) => filename,
+            WindowExit::Reload => {
+                println!("Reloading {}", options.filename);
+                options.filename.clone()
             }
+        };
 
-            let choice = dialog::Question::new("Run program in debug mode?")
-                .title("CrispAte")
-                .show()
-                .expect("Could not display dialog box");
+        // a ROM that can't be loaded leaves the old one running
+        match load_program(&filename) {
+            Some(bytes) => {
+                program_bytes = bytes;
+                options.filename = filename;
+            }
+            None => eprintln!("Could not load {}", filename),
+        }
+    }
 
-            let debug_mode = match choice {
-                dialog::Choice::No => false,
-                dialog::Choice::Yes => true,
-                dialog::Choice::Cancel => false,
-            };
+    // an attached editor ends its debug session with the emulator
+    if let Some(dap) = dap.as_mut() {
+        dap.terminated();
+    }
+}
+
+// the same loop as `create_and_start_vm`, drawn with text (debug mode needs
+// dialogs, so it is not available here)
+fn run_rom_in_terminal(
+    gdb: &mut Option<GdbServer>,
+    dap: &mut Option<DapServer>,
+    program_bytes: &[u8],
+    options: &Options,
+    mode: TerminalMode,
+) -> WindowExit {
+    let mut vm = create_vm(
+        program_bytes,
+        [0; MAX_PROGRAM_SIZE],
+        false,
+        options.fault_policy,
+        options.random(),
+    );
+    vm.symbols = options.symbols();
+    vm.profiler = options.profiler();
+    vm.coverage = options.coverage(program_bytes.len());
+
+    if let Some(dap) = dap.as_mut() {
+        dap.started(&mut vm);
+    }
+
+    let mut movie = start_movie(options, &vm, program_bytes);
+    refuse_edits_while_recording(gdb, dap, movie.is_some());
+
+    let mut palette = options.palette();
+    let mut filter = CrispAteDisplayFilter::new(options.filter);
+    let mut terminal = match CrispAteTerminal::new() {
+        Ok(terminal) => terminal,
+        Err(e) => {
+            eprintln!("Could not set up the terminal: {}", e);
+            process::exit(1);
+        }
+    };
+    let mut keypad = TerminalKeypad::new();
+    let mut frame: usize = 0;
+    let mut paused = false;
+    let mut turbo = false;
+    let mut capture = options
+        .record_gif
+        .as_ref()
+        .and_then(|filename| start_recording(filename, options, &palette));
+    let mut watcher = options.hot_reload.then(|| {
+        let mut paths = vec![PathBuf::from(&options.filename)];
+        paths.extend(options.watch.iter().map(PathBuf::from));
+        FileWatcher::new(paths, Instant::now())
+    });
+
+    let exit = 'running: loop {
+        let started = Instant::now();
+
+        if watcher
+            .as_mut()
+            .is_some_and(|watcher| watcher.poll(Instant::now()))
+        {
+            // a broken build keeps the old ROM running
+            match options.build.as_deref().map(run_build) {
+                Some(Err(e)) => eprintln!("Build failed: {}", e),
+                _ => break WindowExit::Reload,
+            }
+        }
+
+        for event in terminal.poll() {
+            match event {
+                TerminalEvent::Key(key) => keypad.press(key),
+                TerminalEvent::Screenshot => {
+                    save_screenshot(
+                        &vm,
+                        &output_filename(&options.filename, frame, "png"),
+                        options.screenshot_scale,
+                        &palette,
+                    );
+                }
+                TerminalEvent::ToggleRecording => {
+                    capture = toggle_recording(capture.take(), options, frame, &palette)
+                }
+                TerminalEvent::NextPalette => palette = palette.next(),
+                TerminalEvent::Pause => paused = !paused,
+                TerminalEvent::ToggleTurbo => turbo = !turbo,
+                TerminalEvent::Quit => break 'running WindowExit::Quit,
+            }
+        }
+
+        vm.keypad = keypad.next_frame();
+
+        if let Some(gdb) = gdb.as_mut() {
+            gdb.poll(&mut vm);
+        }
+
+        if let Some(dap) = dap.as_mut() {
+            dap.poll(Some(&mut vm));
+
+            if let Some(program) = dap.session.take_launch() {
+                break WindowExit::Load(program);
+            }
+        }
+
+        let cycles = match (paused, turbo) {
+            (true, _) => 0,
+            (false, true) => TURBO_CYCLES,
+            (false, false) => 1,
+        };
+        // an attached debugger decides when the machine runs
+        let cycles = gdb.as_ref().map_or(cycles, |gdb| gdb.stub.cycles(cycles));
+        let cycles = dap
+            .as_ref()
+            .map_or(cycles, |dap| dap.session.cycles(cycles));
+
+        for _ in 0..cycles {
+            if vm.fault.is_some() {
+                break;
+            }
+
+            if gdb.as_mut().is_some_and(|gdb| !gdb.before_cycle(&vm)) {
+                break;
+            }
+
+            if dap.as_mut().is_some_and(|dap| !dap.before_cycle(&vm)) {
+                break;
+            }
+
+            if let Err(fault) = vm.emulation_cyle() {
+                eprintln!("{}", fault.explain(&vm.symbols));
+            }
+
+            if let Some(gdb) = gdb.as_mut() {
+                gdb.after_cycle(&vm);
+            }
+
+            if let Some(dap) = dap.as_mut() {
+                dap.after_cycle(&vm);
+            }
+            filter.observe(&vm);
+            vm.registers.draw_flag = false;
+            frame += 1;
+            record_frame(&mut capture, &vm);
+
+            if let Some(movie) = movie.as_mut() {
+                movie.record(&vm);
+            }
+        }
+        filter.fade(&vm);
+
+        vm.registers.history = Vec::new();
+
+        // redrawn every frame, anything logged to the same terminal in
+        // between is simply painted over
+        let mut text = render(&filter.lit(), SCREEN_WIDTH, mode, &palette);
+        text.push_str(&render_status(
+            vm.fault,
+            &vm.symbols,
+            capture.is_some(),
+            paused,
+            turbo,
+        ));
+        if let Err(e) = terminal.draw(&text) {
+            eprintln!("Could not draw to the terminal: {}", e);
+            break WindowExit::Quit;
+        }
+
+        thread::sleep(TERMINAL_FRAME_TIME.saturating_sub(started.elapsed()));
+    };
+
+    // restore the terminal before anything else gets printed
+    drop(terminal);
+
+    if let Some(capture) = capture {
+        stop_recording(capture);
+    }
+
+    save_movie(movie, options);
+    save_profile(&vm, options);
+    save_coverage(&vm, options);
+    exit
+}
+
+fn ask_debug_mode() -> bool {
+    let choice = dialog::Question::new("Run program in debug mode?")
+        .title("CrispAte")
+        .show()
+        .expect("Could not display dialog box");
+
+    match choice {
+        dialog::Choice::No => false,
+        dialog::Choice::Yes => true,
+        dialog::Choice::Cancel => false,
+    }
+}
 
-            create_and_start_vm(bytes, available_memory, debug_mode)
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let options = match parse_args(&args) {
+        Some(options) => options,
+        None => {
+            println!("{}", USAGE);
+            std::process::exit(1);
         }
+    };
+
+    // without a ROM the window opens on the launcher, the other modes need one
+    if options.filename.is_empty() {
+        if options.play_movie.is_some()
+            || options.analyze.is_some()
+            || options.screenshot_at_frame.is_some()
+            || options.terminal.is_some()
+        {
+            println!("{}", USAGE);
+            std::process::exit(1);
+        }
+
+        run_window(options, ask_debug_mode());
+        return;
+    }
+
+    let available_memory: [u8; 3584] = [0; 3584];
+
+    let bytes = match load_program(&options.filename) {
+        Some(bytes) => bytes,
         None => {
             eprintln!("Failed to get program bytes!");
             std::process::exit(1);
         }
+    };
+
+    if let Some(filename) = &options.analyze {
+        analyze_rom(&bytes, &options, filename);
+        return;
+    }
+
+    if let Some(movie_filename) = options.play_movie.clone() {
+        play_movie(bytes, available_memory, &movie_filename, options.recompile);
+        return;
     }
+
+    if let Some(frame) = options.screenshot_at_frame {
+        take_screenshot(bytes, available_memory, options, frame);
+        return;
+    }
+
+    if let Some(mode) = options.terminal {
+        run_in_terminal(bytes, options, mode);
+        return;
+    }
+
+    run_window(options, ask_debug_mode())
 }